@@ -10,16 +10,30 @@
  * The `Api` struct serves as the main entry point for configuring and starting the server, offering methods for setting
  * up TLS, binding to an address, configuring routes, and more.
  */
+use crate::core::auth_config::AuthConfig;
+use crate::core::auth_routes::AuthPaths;
+use crate::core::client_ip::{ForwardedHeader, TrustedProxies};
 use crate::core::config::load_rustls_config;
-use crate::routes::Routes;
+use crate::core::error::ApiError;
+use crate::core::jwks::jwks_routes;
+use crate::core::openapi::{docs_routes, openapi_document, DocsUi};
+use crate::core::password_reset::PasswordReset;
+use crate::core::middleware::{Middleware, MiddlewareChain};
+use crate::core::request_id::request_id_middleware;
+use crate::core::static_files::StaticFiles;
+use crate::core::validation::{PasswordPolicy, UsernamePolicy};
+use crate::core::rate_limit::{RateLimit, RateLimitStore, RateLimiter, SqliteRateLimitStore};
+use crate::routes::{route_table, Routes};
 
-use actix_web::{App, HttpServer, web};
-use actix_governor::{Governor, GovernorConfigBuilder};
+use actix_web::{App, HttpResponse, HttpServer, web};
 use actix_cors::Cors;
 use std::sync::{Arc, Once};
 
 static INIT: Once = Once::new();
 
+/// A closure that builds the CORS middleware for each worker.
+type CorsConfig = Arc<dyn Fn() -> Cors + Send + Sync>;
+
 /**
  * Initialize the crypto provider for Rustls.
  *
@@ -79,20 +93,62 @@ pub struct Api {
     /// Rate limiting configuration: `(requests_per_second, burst_size)`.
     rate_limit: (u64, u32),
 
-    /// Optional custom routes configuration, provided as a closure.
-    custom_routes: Option<Arc<dyn Fn(&mut web::ServiceConfig) + Send + Sync>>,
+    /// Optional rate limit for authenticated callers: `(requests_per_second, burst_size)`.
+    authenticated_rate_limit: Option<(u64, u32)>,
+
+    /// Rate limit applied to each of the built-in login and register routes.
+    auth_rate_limit: RateLimit,
+
+    /// Rules new usernames must follow on the built-in register route.
+    username_policy: UsernamePolicy,
+
+    /// Rules new passwords must follow on the built-in register route.
+    password_policy: PasswordPolicy,
+
+    /// Optional store shared by every rate limiter, instead of per-limiter memory.
+    rate_limit_store: Option<Arc<dyn RateLimitStore>>,
+
+    /// Whether to keep rate-limit state in the user database, shared between instances.
+    share_rate_limits: bool,
+
+    /// Optional custom routes configuration.
+    custom_routes: Option<Arc<Routes>>,
 
     /// Custom CORS configuration, provided as a closure.
-    custom_cors: Arc<dyn Fn() -> Cors + Send + Sync>,
+    custom_cors: CorsConfig,
+
+    /// Proxies whose forwarding headers are trusted when resolving client IP addresses.
+    trusted_proxies: TrustedProxies,
+
+    /// Global middleware, in the order it was added.
+    middleware: Vec<Middleware>,
+
+    /// Directories of static files, in the order they were mounted.
+    static_files: Vec<StaticFiles>,
+
+    /// Optional path to serve the OpenAPI document at.
+    openapi_path: Option<String>,
+
+    /// Optional documentation page, and the path to serve it at.
+    docs_ui: Option<(DocsUi, String)>,
+
+    /// Title and version of the API, as shown in the OpenAPI document.
+    openapi_info: (String, String),
 
     /// Optional enable user database.
     user_db: bool,
 
-    /// Optional custom route configuration for login.
-    login_route: String,
+    /// Paths of the built-in login, register, refresh and logout routes.
+    auth_paths: AuthPaths,
+
+    /// How access tokens are signed and validated, or `None` for HS256 with `JWT_SECRET`.
+    auth_config: Option<AuthConfig>,
+
+    /// Optional path to publish the public verification keys at.
+    jwks_path: Option<String>,
 
-    /// Optional custom route configuration for register.
-    register_route: String,
+    /// How password reset links are sent, or `None` to leave out the reset routes.
+    password_reset: Option<PasswordReset>,
 }
 
 impl Api {
@@ -122,11 +178,25 @@ impl Api {
             addr: "127.0.0.1".into(),
             port: 8443,
             rate_limit: (3, 20),
+            authenticated_rate_limit: None,
+            auth_rate_limit: RateLimit::per_minute(10, 5),
+            username_policy: UsernamePolicy::new(),
+            password_policy: PasswordPolicy::new(),
+            rate_limit_store: None,
+            share_rate_limits: false,
             custom_routes: None,
-            custom_cors: Arc::new(|| Cors::default()),
+            custom_cors: Arc::new(Cors::default),
+            trusted_proxies: TrustedProxies::default(),
+            middleware: Vec::new(),
+            static_files: Vec::new(),
+            openapi_path: None,
+            docs_ui: None,
+            openapi_info: ("rusty-api".into(), "1.0.0".into()),
             user_db: false,
-            login_route: "/login".into(),
-            register_route: "/register".into(),
+            auth_paths: AuthPaths::default(),
+            auth_config: None,
+            jwks_path: None,
+            password_reset: None,
         }
     }
 
@@ -158,6 +228,12 @@ impl Api {
     /**
      * Set the rate limit for API requests.
      *
+     * This limit applies to every request, per client IP address. Routes can add
+     * their own, stricter limits with `Routes::rate_limit`, which stack on top of this one.
+     *
+     * Since 0.2, `per_second` counts requests per second. In 0.1 it was the number of seconds
+     * between requests, so `rate_limit(3, 20)` now allows nine times as many requests as before.
+     *
      * # Arguments
      * * `per_second` - Number of requests allowed per second.
      * * `burst_size` - Maximum burst size for requests.
@@ -179,6 +255,163 @@ impl Api {
         self
     }
 
+    /**
+     * Rate limit authenticated callers by identity instead of by IP address.
+     *
+     * Requests carrying a valid Bearer token are counted per user ID, and requests carrying
+     * a valid API key are counted per key, using this separate quota. Requests without valid
+     * credentials keep using the per-IP limit set with `rate_limit`. This avoids punishing
+     * users who share an IP address, and stops a single user from spreading requests over
+     * many addresses.
+     *
+     * # Arguments
+     * * `per_second` - Number of requests allowed per second for each authenticated caller.
+     * * `burst_size` - Maximum burst size for each authenticated caller.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new()
+     *     .rate_limit(1, 5)
+     *     .authenticated_rate_limit(10, 50);
+     * assert_eq!(api.get_authenticated_rate_limit(), Some((10, 50)));
+     * ```
+     */
+    pub fn authenticated_rate_limit(mut self, per_second: u64, burst_size: u32) -> Self {
+        self.authenticated_rate_limit = Some((per_second, burst_size));
+        self
+    }
+
+    /**
+     * Set the rate limit for the built-in login and register routes.
+     *
+     * Each of the two routes gets its own count per client, on top of the global
+     * rate limit. The default is 10 requests per minute with a burst of 5.
+     *
+     * # Arguments
+     * * `limit` - The rate limit to apply to each route.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Api, RateLimit};
+     *
+     * let api = Api::new().auth_rate_limit(RateLimit::per_minute(5, 3));
+     * assert_eq!(api.get_auth_rate_limit(), RateLimit::per_minute(5, 3));
+     * ```
+     */
+    pub fn auth_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.auth_rate_limit = limit;
+        self
+    }
+
+    /**
+     * Set the rules new usernames must follow on the built-in register route.
+     *
+     * Registrations that break the policy are rejected with `422 Unprocessable Entity`.
+     * By default, usernames are 3 to 32 characters of ASCII letters, digits, `_`, `.` and `-`.
+     *
+     * # Arguments
+     * * `policy` - The username policy.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Api, UsernamePolicy};
+     *
+     * let api = Api::new().username_policy(UsernamePolicy::new().length(4, 20).pattern(r"^[a-z0-9_]+$"));
+     * assert_eq!(api.get_username_policy().get_max_length(), 20);
+     * ```
+     */
+    pub fn username_policy(mut self, policy: UsernamePolicy) -> Self {
+        self.username_policy = policy;
+        self
+    }
+
+    /**
+     * Set the rules new passwords must follow on the built-in register route.
+     *
+     * Registrations that break the policy are rejected with `422 Unprocessable Entity`.
+     * By default, passwords must be at least 8 characters long.
+     *
+     * # Arguments
+     * * `policy` - The password policy.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Api, PasswordPolicy};
+     *
+     * let api = Api::new().password_policy(PasswordPolicy::new().min_length(12).require_digit().require_symbol());
+     * assert_eq!(api.get_password_policy().get_min_length(), 12);
+     * ```
+     */
+    pub fn password_policy(mut self, policy: PasswordPolicy) -> Self {
+        self.password_policy = policy;
+        self
+    }
+
+    /**
+     * Set the store used to keep rate-limit state.
+     *
+     * By default each rate limiter keeps its counts in the memory of the current process,
+     * so running several instances multiplies every quota. A shared store makes every
+     * limiter, including per-route and login/register limits, use the same counts across
+     * instances.
+     *
+     * # Arguments
+     * * `store` - The store to use for all rate limiters.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     * use rusty_api::core::rate_limit::MemoryRateLimitStore;
+     *
+     * let api = Api::new().rate_limit_store(MemoryRateLimitStore::new());
+     * ```
+     */
+    pub fn rate_limit_store<S: RateLimitStore + 'static>(mut self, store: S) -> Self {
+        self.rate_limit_store = Some(Arc::new(store));
+        self
+    }
+
+    /**
+     * Keep rate-limit state in the user database, so that every instance sharing the
+     * database file enforces one combined quota.
+     *
+     * This uses the same database as `enable_user_db`, configured with the `DATABASE_URL`
+     * environment variable, and opens it even if the user database is not enabled.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new()
+     *     .enable_user_db()
+     *     .share_rate_limits();
+     * ```
+     */
+    pub fn share_rate_limits(mut self) -> Self {
+        self.share_rate_limits = true;
+        self
+    }
+
     /**
      * Set the address and port for the API server.
      *
@@ -231,7 +464,7 @@ impl Api {
      * ```
      */
     pub fn configure_routes(mut self, routes: Routes) -> Self {
-        self.custom_routes = Some(Arc::new(move |cfg| routes.configure(cfg)));
+        self.custom_routes = Some(Arc::new(routes));
         self
     }
 
@@ -264,6 +497,221 @@ impl Api {
         self
     }
 
+    /**
+     * Set the reverse proxies whose forwarding headers are trusted.
+     *
+     * When the server runs behind a proxy or tunnel, every request arrives from the proxy's
+     * address. Requests from a trusted proxy have their client address taken from the
+     * `X-Forwarded-For` header instead, or the header set with `forwarded_header`, skipping
+     * any other trusted proxies in the chain. Requests from any other address ignore these
+     * headers, so clients cannot forge their address. The resolved address is used for rate limiting and is available
+     * to handlers through the `ClientIp` extractor.
+     *
+     * # Arguments
+     * * `cidrs` - CIDR ranges (e.g. `"10.0.0.0/8"`) or single addresses (e.g. `"127.0.0.1"`).
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new().trusted_proxies(&["127.0.0.1", "::1", "10.0.0.0/8"]);
+     * assert!(api.get_trusted_proxies().contains(&"10.1.2.3".parse().unwrap()));
+     * ```
+     */
+    pub fn trusted_proxies(mut self, cidrs: &[&str]) -> Self {
+        self.trusted_proxies = TrustedProxies::new(cidrs).header(self.trusted_proxies.get_header());
+        self
+    }
+
+    /**
+     * Set the header the trusted proxies record the client address in.
+     *
+     * The default, `X-Forwarded-For`, is written by nginx, ngrok and most other proxies.
+     * Only this header is read: proxies pass the other one through from the client
+     * unchanged, so reading it would let clients choose their own address.
+     *
+     * # Arguments
+     * * `header` - The header the proxies write.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Api, ForwardedHeader};
+     *
+     * let api = Api::new()
+     *     .trusted_proxies(&["10.0.0.0/8"])
+     *     .forwarded_header(ForwardedHeader::Forwarded);
+     * assert_eq!(api.get_trusted_proxies().get_header(), ForwardedHeader::Forwarded);
+     * ```
+     */
+    pub fn forwarded_header(mut self, header: ForwardedHeader) -> Self {
+        self.trusted_proxies = self.trusted_proxies.header(header);
+        self
+    }
+
+    /**
+     * Add middleware that runs on every request.
+     *
+     * Global middleware runs after the global rate limit and CORS, and before any route
+     * rate limits, route middleware and protection checks. Middleware added with several
+     * calls runs in the order it was added. See the `middleware` module for details.
+     *
+     * # Arguments
+     * * `middleware` - The middleware to run.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Api, Middleware};
+     *
+     * let request_log = Middleware::new(|req, next| async move {
+     *     let method = req.method().clone();
+     *     let path = req.path().to_owned();
+     *     let res = next.call(req).await?;
+     *     println!("INFO: {} {} -> {}", method, path, res.status());
+     *     Ok(res)
+     * });
+     *
+     * let api = Api::new().wrap(request_log);
+     * assert_eq!(api.get_middleware().len(), 1);
+     * ```
+     */
+    pub fn wrap(mut self, middleware: Middleware) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /**
+     * Serve a directory of static files.
+     *
+     * Files are sent with their content type, `ETag` and `Last-Modified` headers, and a
+     * precompressed `.br` or `.gz` sibling is sent instead when the client accepts it.
+     * Requests for a directory are answered with its `index.html`. Static files are mounted
+     * after every API route, so they never shadow an API route, even when mounted at `/`.
+     *
+     * # Arguments
+     * * `mount` - The URL path to serve the files at (e.g. `/assets`).
+     * * `dir` - The directory on disk to serve files from.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new().serve_static("/assets", "./public");
+     * assert_eq!(api.get_static_files()[0].mount, "/assets");
+     * assert!(!api.get_static_files()[0].spa_fallback);
+     * ```
+     */
+    pub fn serve_static(mut self, mount: &str, dir: &str) -> Self {
+        self.static_files.push(StaticFiles::new(mount, dir, false));
+        self
+    }
+
+    /**
+     * Serve a single-page application.
+     *
+     * This works like `serve_static`, but any unknown path under `mount` that is requested
+     * by a browser is answered with the directory's `index.html`, so the frontend can
+     * handle client-side routes such as `/dashboard/settings`. Requests that do not accept
+     * HTML, such as calls to a misspelled API route, still receive `404 Not Found`.
+     *
+     * # Arguments
+     * * `mount` - The URL path to serve the application at (usually `/`).
+     * * `dir` - The directory containing the built application and its `index.html`.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new().serve_spa("/", "./frontend/dist");
+     * assert!(api.get_static_files()[0].spa_fallback);
+     * ```
+     */
+    pub fn serve_spa(mut self, mount: &str, dir: &str) -> Self {
+        self.static_files.push(StaticFiles::new(mount, dir, true));
+        self
+    }
+
+    /**
+     * Serve an OpenAPI 3.1 document describing the API.
+     *
+     * The document is generated at startup from the routes set with `configure_routes`,
+     * including their methods, paths, protection and any schemas set with
+     * `Routes::request_schema` and `Routes::response_schema`. The built-in login and
+     * register routes are included when the user database is enabled.
+     *
+     * # Arguments
+     * * `path` - The URL path to serve the JSON document at.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new().openapi("/openapi.json");
+     * assert_eq!(api.get_openapi_path(), Some("/openapi.json"));
+     * ```
+     */
+    pub fn openapi(mut self, path: &str) -> Self {
+        self.openapi_path = Some(path.into());
+        self
+    }
+
+    /**
+     * Serve a browser page that renders the OpenAPI document.
+     *
+     * If `openapi` has not been called, the document is served at `/openapi.json`.
+     *
+     * # Arguments
+     * * `ui` - The documentation interface to use: Swagger UI or Redoc.
+     * * `path` - The URL path to serve the page at.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Api, DocsUi};
+     *
+     * let api = Api::new().docs_ui(DocsUi::Redoc, "/docs");
+     * assert_eq!(api.get_openapi_path(), Some("/openapi.json"));
+     * ```
+     */
+    pub fn docs_ui(mut self, ui: DocsUi, path: &str) -> Self {
+        self.docs_ui = Some((ui, path.into()));
+        if self.openapi_path.is_none() {
+            self.openapi_path = Some("/openapi.json".into());
+        }
+        self
+    }
+
+    /**
+     * Set the title and version shown in the OpenAPI document.
+     *
+     * # Arguments
+     * * `title` - The title of the API.
+     * * `version` - The version of the API.
+     *
+     * # Returns
+     * A mutable reference to the `Api` instance.
+     */
+    pub fn openapi_info(mut self, title: &str, version: &str) -> Self {
+        self.openapi_info = (title.into(), version.into());
+        self
+    }
+
     /// Enable user database with default login and register routes.
     pub fn enable_user_db(self) -> Self {
         self.enable_user_db_with_routes("/login", "/register")
@@ -272,8 +720,182 @@ impl Api {
     /// Enable user database with optional custom login and register routes.
     pub fn enable_user_db_with_routes(mut self, login_route: &str, register_route: &str) -> Self {
         self.user_db = true;
-        self.login_route = login_route.into();
-        self.register_route = register_route.into();
+        self.auth_paths.login = login_route.into();
+        self.auth_paths.register = register_route.into();
+        self
+    }
+
+    /**
+     * Set the path of the built-in refresh route, `/refresh` by default.
+     *
+     * Login returns a short-lived access token and a refresh token. Clients send the
+     * refresh token to this route for a new pair of tokens before the access token expires.
+     *
+     * # Arguments
+     * * `path` - The URL path of the refresh route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new().enable_user_db().refresh_route("/auth/refresh");
+     * assert_eq!(api.get_refresh_route(), "/auth/refresh");
+     * ```
+     */
+    pub fn refresh_route(mut self, path: &str) -> Self {
+        self.auth_paths.refresh = path.into();
+        self
+    }
+
+    /**
+     * Set how access tokens are signed and validated.
+     *
+     * Without this, tokens are signed with HS256 and the secret in the `JWT_SECRET`
+     * environment variable, read once at startup.
+     *
+     * # Arguments
+     * * `config` - The signing algorithm and keys, claims, lifetimes and leeway.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Api, AuthConfig};
+     *
+     * let api = Api::new()
+     *     .enable_user_db()
+     *     .auth_config(AuthConfig::hs256("a long random secret").issuer("example"));
+     * assert_eq!(api.get_auth_config().and_then(|config| config.get_issuer()), Some("example"));
+     * ```
+     */
+    pub fn auth_config(mut self, config: AuthConfig) -> Self {
+        self.auth_config = Some(config);
+        self
+    }
+
+    /**
+     * Publish the public keys that verify access tokens as a JSON Web Key Set.
+     *
+     * Only the keys of RS256, ES256 and EdDSA configurations are published; with HS256,
+     * the key set is empty.
+     *
+     * # Arguments
+     * * `path` - The path to serve the key set at, normally `JWKS_PATH`.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     * use rusty_api::core::jwks::JWKS_PATH;
+     *
+     * let api = Api::new().jwks(JWKS_PATH);
+     * assert_eq!(api.get_jwks_path(), Some("/.well-known/jwks.json"));
+     * ```
+     */
+    pub fn jwks(mut self, path: &str) -> Self {
+        self.jwks_path = Some(path.into());
+        self
+    }
+
+    /**
+     * Set the paths of the built-in logout routes, `/logout` and `/logout/all` by default.
+     *
+     * Both routes require a valid access token. The first revokes that token, and the
+     * refresh token sent in its body if any; the second revokes every token of the user.
+     *
+     * # Arguments
+     * * `logout` - The URL path of the logout route.
+     * * `logout_all` - The URL path of the route that logs out of every session.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new().enable_user_db().logout_routes("/auth/logout", "/auth/logout-everywhere");
+     * assert_eq!(api.get_auth_paths().logout_all, "/auth/logout-everywhere");
+     * ```
+     */
+    pub fn logout_routes(mut self, logout: &str, logout_all: &str) -> Self {
+        self.auth_paths.logout = logout.into();
+        self.auth_paths.logout_all = logout_all.into();
+        self
+    }
+
+    /**
+     * Set the paths of the built-in two-factor routes.
+     *
+     * By default, a login that needs a second factor is completed at `/login/2fa`, and
+     * users manage their TOTP enrolment at `/2fa/enrol`, `/2fa/confirm` and `/2fa/disable`.
+     * The management routes require a valid access token, and enrolling and confirming also
+     * require the user's current password. See the `two_factor` module.
+     *
+     * # Arguments
+     * * `login` - The URL path that exchanges a challenge token and code for tokens.
+     * * `enrol` - The URL path that starts an enrolment.
+     * * `confirm` - The URL path that enables two-factor authentication.
+     * * `disable` - The URL path that disables two-factor authentication.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new()
+     *     .enable_user_db()
+     *     .two_factor_routes("/auth/2fa", "/account/2fa/enrol", "/account/2fa/confirm", "/account/2fa/disable");
+     * assert_eq!(api.get_auth_paths().two_factor_login, "/auth/2fa");
+     * ```
+     */
+    pub fn two_factor_routes(mut self, login: &str, enrol: &str, confirm: &str, disable: &str) -> Self {
+        self.auth_paths.two_factor_login = login.into();
+        self.auth_paths.two_factor_enrol = enrol.into();
+        self.auth_paths.two_factor_confirm = confirm.into();
+        self.auth_paths.two_factor_disable = disable.into();
+        self
+    }
+
+    /**
+     * Enable the built-in password reset routes.
+     *
+     * Users ask for a reset link at `/password/reset`, which is emailed to the address
+     * they registered with, and set a new password with the token from the link at
+     * `/password/reset/confirm`. See the `password_reset` module.
+     *
+     * # Arguments
+     * * `reset` - The mailer and link template reset emails are sent with.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     * use rusty_api::core::mailer::LogMailer;
+     * use rusty_api::core::password_reset::PasswordReset;
+     *
+     * let api = Api::new()
+     *     .enable_user_db()
+     *     .password_reset(PasswordReset::new(LogMailer, "https://example.com/reset?token={token}"));
+     * assert!(api.get_password_reset().is_some());
+     * ```
+     */
+    pub fn password_reset(mut self, reset: PasswordReset) -> Self {
+        self.password_reset = Some(reset);
+        self
+    }
+
+    /**
+     * Set the paths of the built-in password reset routes, `/password/reset` and
+     * `/password/reset/confirm` by default.
+     *
+     * # Arguments
+     * * `request` - The URL path that emails a reset link.
+     * * `confirm` - The URL path that sets a new password.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new().enable_user_db().password_reset_routes("/auth/forgot", "/auth/reset");
+     * assert_eq!(api.get_auth_paths().password_reset_confirm, "/auth/reset");
+     * ```
+     */
+    pub fn password_reset_routes(mut self, request: &str, confirm: &str) -> Self {
+        self.auth_paths.password_reset_request = request.into();
+        self.auth_paths.password_reset_confirm = confirm.into();
         self
     }
 
@@ -295,46 +917,112 @@ impl Api {
             println!("INFO: Starting API server...");
 
             dotenv::dotenv().ok();
-            let pool = if self.user_db {
+            let pool = if self.user_db || self.share_rate_limits {
                 Some(crate::core::db::init_db().await.expect("Failed to init DB"))
             } else {
                 None
             };
 
+            let rate_limit_store: Option<Arc<dyn RateLimitStore>> = match (&pool, self.share_rate_limits) {
+                (Some(pool), true) => Some(Arc::new(SqliteRateLimitStore::new(pool.clone()))),
+                _ => self.rate_limit_store.clone(),
+            };
+            let user_pool = if self.user_db { pool.clone() } else { None };
+
             let tls_config = load_rustls_config(&self.cert_path, &self.key_path).expect("TLS failed");
 
-            let governor_config = GovernorConfigBuilder::default()
-                .per_second(self.rate_limit.0)
-                .burst_size(self.rate_limit.1)
-                .finish()
-                .unwrap();
+            // Limiters are created once here so that every worker shares the same counts
+            let anonymous_limit = RateLimit::per_second(self.rate_limit.0, self.rate_limit.1);
+            let rate_limiter = match self.authenticated_rate_limit {
+                Some((per_second, burst_size)) => {
+                    RateLimiter::by_identity(anonymous_limit, RateLimit::per_second(per_second, burst_size))
+                }
+                None => RateLimiter::new(anonymous_limit),
+            };
+            let auth_routes = Arc::new(crate::core::auth_routes::auth_routes(
+                &self.auth_paths,
+                self.auth_rate_limit,
+                &self.username_policy,
+                &self.password_policy,
+                self.password_reset.as_ref(),
+            ));
+
+            // Keys are parsed once here, not on every request
+            let auth_config = web::Data::new(self.auth_config.clone().unwrap_or_else(AuthConfig::default_config));
+            let jwks_routes = self.jwks_path.as_deref().map(|path| Arc::new(jwks_routes(path, &auth_config)));
+
+            let mut mounted: Vec<&Routes> = Vec::new();
+            if self.user_db {
+                mounted.push(&auth_routes);
+            }
+            mounted.extend(self.custom_routes.as_deref());
+            mounted.extend(jwks_routes.as_deref());
+
+            let docs_routes = self.openapi_path.as_deref().map(|spec_path| {
+                let (title, version) = &self.openapi_info;
+                let document = openapi_document(title, version, mounted.iter().flat_map(|r| r.iter()));
+                let ui = self.docs_ui.as_ref().map(|(ui, path)| (*ui, path.as_str()));
+                Arc::new(docs_routes(spec_path, &document, ui))
+            });
+            mounted.extend(docs_routes.as_deref());
+            println!("INFO: Mounted routes:\n{}", route_table(mounted.iter().flat_map(|r| r.iter())));
+            for files in &self.static_files {
+                if !files.dir.is_dir() {
+                    println!("WARNING: Static file directory '{}' does not exist", files.dir.display());
+                }
+                println!("INFO: Serving static files from '{}' at {}", files.dir.display(), files.mount);
+            }
 
             let cors_config = self.custom_cors.clone();
+            let trusted_proxies = web::Data::new(self.trusted_proxies.clone());
 
             let bind_addr = format!("{}:{}", self.addr, self.port);
 
             println!("INFO: Server binding to {}", bind_addr);
             HttpServer::new(move || {
-            let cors = (cors_config)();
+                let cors = (cors_config)();
                 let mut app = App::new()
+                    .app_data(trusted_proxies.clone())
+                    .app_data(auth_config.clone())
+                    // Bodies, queries and paths that fail to parse are answered with an `ApiError`
+                    .app_data(web::JsonConfig::default().error_handler(|e, _| ApiError::from(e).into()))
+                    .app_data(web::QueryConfig::default().error_handler(|e, _| ApiError::from(e).into()))
+                    .app_data(web::PathConfig::default().error_handler(|e, _| ApiError::from(e).into()))
+                    .wrap(MiddlewareChain::new(&self.middleware))
                     .wrap(cors)
-                    .wrap(Governor::new(&governor_config));
+                    .wrap(rate_limiter.clone())
+                    .wrap(MiddlewareChain::new(&[request_id_middleware()]))
+                    .default_service(web::to(|| async { HttpResponse::from(ApiError::not_found("Not found")) }));
+
+                // Share one rate-limit store between all limiters if configured
+                if let Some(store) = rate_limit_store.clone() {
+                    app = app.app_data(web::Data::from(store));
+                }
 
                 // Add app_data for the pool if it exists
-                if let Some(pool) = pool.clone() {
+                if let Some(pool) = user_pool.clone() {
                     app = app.app_data(web::Data::new(pool));
-                    app = app.configure(|cfg| {
-                        crate::core::auth_routes::configure_auth_routes(
-                            cfg,
-                            &self.login_route,
-                            &self.register_route
-                        );
-                    });
+                    app = app.configure(|cfg| auth_routes.configure(cfg));
                 }
 
                 // Apply custom routes if provided
                 if let Some(custom_routes) = &self.custom_routes {
-                    app = app.configure(|cfg| custom_routes(cfg));
+                    app = app.configure(|cfg| custom_routes.configure(cfg));
+                }
+
+                // Publish the public verification keys if enabled
+                if let Some(jwks_routes) = &jwks_routes {
+                    app = app.configure(|cfg| jwks_routes.configure(cfg));
+                }
+
+                // Serve the OpenAPI document and documentation page if enabled
+                if let Some(docs_routes) = &docs_routes {
+                    app = app.configure(|cfg| docs_routes.configure(cfg));
+                }
+
+                // Static files come last, so they only answer paths that no API route matches
+                for files in &self.static_files {
+                    app = app.configure(|cfg| files.configure(cfg));
                 }
 
                 app
@@ -476,10 +1164,147 @@ impl Api {
     pub fn get_rate_limit_burst_size(&self) -> u32 { self.rate_limit.1 }
 
     /**
-     * Get the custom CORS configuration.
+     * Get the rate limit for authenticated callers, if identity-based limiting is enabled.
+     *
+     * # Returns
+     * A tuple `(requests_per_second, burst_size)`, or `None` if all callers are limited by IP address.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new();
+     * assert_eq!(api.get_authenticated_rate_limit(), None);
+     * ```
+     */
+    pub fn get_authenticated_rate_limit(&self) -> Option<(u64, u32)> { self.authenticated_rate_limit }
+
+    /**
+     * Get the rate limit for the built-in login and register routes.
+     *
+     * # Returns
+     * The `RateLimit` applied to each of the two routes.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Api, RateLimit};
+     *
+     * let api = Api::new();
+     * assert_eq!(api.get_auth_rate_limit(), RateLimit::per_minute(10, 5));
+     * ```
+     */
+    pub fn get_auth_rate_limit(&self) -> RateLimit { self.auth_rate_limit }
+
+    /**
+     * Get the username policy of the built-in register route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new();
+     * assert_eq!(api.get_username_policy().get_min_length(), 3);
+     * ```
+     */
+    pub fn get_username_policy(&self) -> &UsernamePolicy { &self.username_policy }
+
+    /**
+     * Get the password policy of the built-in register route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new();
+     * assert_eq!(api.get_password_policy().get_min_length(), 8);
+     * ```
+     */
+    pub fn get_password_policy(&self) -> &PasswordPolicy { &self.password_policy }
+
+    /**
+     * Get the path of the built-in refresh route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new();
+     * assert_eq!(api.get_refresh_route(), "/refresh");
+     * ```
+     */
+    pub fn get_refresh_route(&self) -> &str { &self.auth_paths.refresh }
+
+    /**
+     * Get the paths of the built-in authentication routes.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Api;
+     *
+     * let api = Api::new().enable_user_db_with_routes("/auth/login", "/auth/register");
+     * assert_eq!(api.get_auth_paths().login, "/auth/login");
+     * assert_eq!(api.get_auth_paths().logout, "/logout");
+     * ```
+     */
+    pub fn get_auth_paths(&self) -> &AuthPaths { &self.auth_paths }
+
+    /// Get the token configuration set with `Api::auth_config`, if any.
+    pub fn get_auth_config(&self) -> Option<&AuthConfig> { self.auth_config.as_ref() }
+
+    /// Get the password reset configuration set with `Api::password_reset`, if any.
+    pub fn get_password_reset(&self) -> Option<&PasswordReset> { self.password_reset.as_ref() }
+
+    /**
+     * Get the path the JSON Web Key Set is published at.
      *
      * # Returns
-     * A reference to the custom CORS configuration closure.
+     * The path, or `None` if the key set is not published.
      */
-    pub fn get_custom_routes(&self) -> Option<&Arc<dyn Fn(&mut web::ServiceConfig) + Send + Sync>> { self.custom_routes.as_ref() }
+    pub fn get_jwks_path(&self) -> Option<&str> { self.jwks_path.as_deref() }
+
+    /**
+     * Get the trusted proxy configuration.
+     *
+     * # Returns
+     * A reference to the set of trusted proxies.
+     */
+    pub fn get_trusted_proxies(&self) -> &TrustedProxies { &self.trusted_proxies }
+
+    /**
+     * Get the global middleware.
+     *
+     * # Returns
+     * The middleware added with `wrap`, in the order it runs.
+     */
+    pub fn get_middleware(&self) -> &[Middleware] { &self.middleware }
+
+    /**
+     * Get the directories of static files.
+     *
+     * # Returns
+     * The static file mounts, in the order they were added.
+     */
+    pub fn get_static_files(&self) -> &[StaticFiles] { &self.static_files }
+
+    /**
+     * Get the path the OpenAPI document is served at.
+     *
+     * # Returns
+     * The path, or `None` if the OpenAPI document is not served.
+     */
+    pub fn get_openapi_path(&self) -> Option<&str> { self.openapi_path.as_deref() }
+
+    /**
+     * Get the custom routes.
+     *
+     * # Returns
+     * A reference to the routes set with `configure_routes`, if any.
+     */
+    pub fn get_custom_routes(&self) -> Option<&Routes> { self.custom_routes.as_deref() }
+}
+
+impl Default for Api {
+    fn default() -> Self {
+        Self::new()
+    }
 }
\ No newline at end of file
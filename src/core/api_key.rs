@@ -0,0 +1,241 @@
+/*!
+ * API key module.
+ *
+ * This module manages API keys: long random secrets that machines use to call
+ * protected routes. Keys are sent in the `X-API-Key` header, stored in the
+ * database only as a SHA-256 hash, and compared in constant time. Each key has a
+ * name, an optional expiry date, and a list of route patterns it may call, and can
+ * be rotated or revoked at runtime without redeploying the server.
+ *
+ * # Example
+ * ```rust,no_run
+ * use rusty_api::core::api_key::{create_api_key, rotate_api_key};
+ *
+ * # async fn example(pool: &sqlx::SqlitePool) -> Result<(), sqlx::Error> {
+ * // Issue a key that may only call the `/reports` route, valid for 90 days
+ * let expires_at = chrono::Utc::now() + chrono::Duration::days(90);
+ * let (key, secret) = create_api_key(pool, "reporting-service", &["/reports"], Some(expires_at)).await?;
+ * println!("Give this secret to the reporting service: {}", secret);
+ *
+ * // Later, replace the secret while keeping the same name, routes and expiry
+ * let new_secret = rotate_api_key(pool, key.id).await?;
+ * # Ok(())
+ * # }
+ * ```
+ */
+use actix_web::{dev::Payload, web, FromRequest, HttpMessage, HttpRequest};
+use chrono::{DateTime, Utc};
+use futures_util::future::LocalBoxFuture;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use crate::core::error::ApiError;
+use crate::core::secret::{generate_secret, hash_secret};
+
+/// The request header that carries the API key.
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Prefix added to every generated key, making keys easy to recognise in configuration files.
+const KEY_PREFIX: &str = "rk_";
+
+/// Route pattern that allows a key to call every API-key protected route.
+pub const ALL_ROUTES: &str = "*";
+
+/**
+ * An API key record.
+ *
+ * The key's secret is never stored; only its hash is kept in the database. When used
+ * as an extractor, `ApiKey` validates the `X-API-Key` header of the request and rejects
+ * it with `401 Unauthorized` if the key is missing, unknown, revoked or expired, or with
+ * `403 Forbidden` if the key is not allowed to call the matched route.
+ */
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    /// Comma-separated route patterns this key may call, or `*` for every route.
+    pub routes: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ApiKey {
+    /// Check whether this key may call the route with the given pattern (e.g. `/items/{id}`).
+    pub fn allows(&self, route: &str) -> bool {
+        self.routes
+            .split(',')
+            .map(str::trim)
+            .any(|allowed| allowed == ALL_ROUTES || allowed == route)
+    }
+
+    /// Check whether this key has passed its expiry date.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+}
+
+impl FromRequest for ApiKey {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let secret = req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_owned);
+        let pool = req.app_data::<web::Data<SqlitePool>>().cloned();
+        let route = req.match_pattern().unwrap_or_else(|| req.path().to_owned());
+        // An identity-keyed rate limiter may already have verified the key of this request
+        let cached = req.extensions().get::<ApiKey>().cloned();
+
+        Box::pin(async move {
+            let key = match cached {
+                Some(key) => key,
+                None => {
+                    let secret = secret.ok_or_else(|| ApiError::unauthorized("Missing API key").with_code("missing_api_key"))?;
+                    let pool = pool.ok_or_else(|| ApiError::internal("User database is not enabled"))?;
+                    verify_api_key(&pool, &secret)
+                        .await?
+                        .ok_or_else(|| ApiError::unauthorized("Invalid API key").with_code("invalid_api_key"))?
+                }
+            };
+
+            if !key.allows(&route) {
+                return Err(ApiError::forbidden("API key is not allowed to call this route").with_code("api_key_not_allowed"));
+            }
+
+            Ok(key)
+        })
+    }
+}
+
+/**
+ * Create a new API key.
+ *
+ * # Arguments
+ * - `pool`: The SQLite connection pool.
+ * - `name`: A descriptive name for the key, such as the service that will use it.
+ * - `routes`: Route patterns the key may call, or `["*"]` for every API-key protected route.
+ * - `expires_at`: Optional expiry date, after which the key is rejected.
+ *
+ * # Returns
+ * The stored key record and the plaintext secret. The secret is only available here,
+ * so it must be handed to the client immediately.
+ */
+pub async fn create_api_key(
+    pool: &SqlitePool,
+    name: &str,
+    routes: &[&str],
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<(ApiKey, String), sqlx::Error> {
+    let secret = generate_secret(KEY_PREFIX);
+    let key = insert_key(pool, name, &routes.join(","), expires_at, &secret).await?;
+    Ok((key, secret))
+}
+
+/**
+ * Look up an API key by its secret.
+ *
+ * # Returns
+ * The key record if the secret matches a key that is neither revoked nor expired,
+ * or `None` otherwise.
+ */
+pub async fn verify_api_key(pool: &SqlitePool, secret: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+    // The lookup is by hash, so timing reveals nothing about the secret itself
+    let key: Option<ApiKey> = sqlx::query_as(
+        "SELECT id, name, routes, expires_at, revoked, created_at FROM api_keys WHERE key_hash = ?"
+    )
+    .bind(hash_secret(secret))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(key.filter(|key| !key.revoked && !key.is_expired()))
+}
+
+/**
+ * Replace the secret of an API key.
+ *
+ * A new key with the same name, routes and expiry date is created and the old key
+ * is revoked, in a single transaction. Clients using the old secret are rejected
+ * as soon as this returns.
+ *
+ * # Returns
+ * The plaintext secret of the new key, or `None` if no active key has the given ID.
+ */
+pub async fn rotate_api_key(pool: &SqlitePool, id: i32) -> Result<Option<String>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let old: Option<ApiKey> = sqlx::query_as(
+        "SELECT id, name, routes, expires_at, revoked, created_at FROM api_keys WHERE id = ? AND revoked = 0"
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(old) = old else {
+        return Ok(None);
+    };
+
+    sqlx::query("UPDATE api_keys SET revoked = 1 WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    let secret = generate_secret(KEY_PREFIX);
+    insert_key(&mut *tx, &old.name, &old.routes, old.expires_at, &secret).await?;
+
+    tx.commit().await?;
+    Ok(Some(secret))
+}
+
+/**
+ * Revoke an API key, so it is rejected from now on.
+ *
+ * # Returns
+ * `true` if a key was revoked, or `false` if no active key has the given ID.
+ */
+pub async fn revoke_api_key(pool: &SqlitePool, id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE api_keys SET revoked = 1 WHERE id = ? AND revoked = 0")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/**
+ * List all API keys, including revoked and expired ones.
+ *
+ * Secrets and their hashes are not included.
+ */
+pub async fn list_api_keys(pool: &SqlitePool) -> Result<Vec<ApiKey>, sqlx::Error> {
+    sqlx::query_as("SELECT id, name, routes, expires_at, revoked, created_at FROM api_keys ORDER BY id")
+        .fetch_all(pool)
+        .await
+}
+
+/// Insert a key record for the given secret.
+async fn insert_key<'e, E>(
+    executor: E,
+    name: &str,
+    routes: &str,
+    expires_at: Option<DateTime<Utc>>,
+    secret: &str,
+) -> Result<ApiKey, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    sqlx::query_as(
+        "INSERT INTO api_keys (name, key_hash, routes, expires_at, created_at) VALUES (?, ?, ?, ?, ?)
+         RETURNING id, name, routes, expires_at, revoked, created_at"
+    )
+    .bind(name)
+    .bind(hash_secret(secret))
+    .bind(routes)
+    .bind(expires_at)
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await
+}
+
@@ -1,14 +1,37 @@
-use crate::core::user::{LoginResponse, User};
+use crate::core::auth_config::AuthConfig;
+use crate::core::error::ApiError;
+use crate::core::refresh_token::{issue_refresh_token, rotate_refresh_token};
+use crate::core::revocation::{is_token_revoked, revoke_all_tokens, token_version};
+use crate::core::two_factor::{create_challenge, redeem_challenge, CHALLENGE_LIFETIME};
+use crate::core::user::{ChallengeResponse, LoginOutcome, LoginResponse, TwoFactorLoginInput, User};
+use actix_web::{dev::Payload, web, FromRequest, HttpMessage, HttpRequest};
 use bcrypt::{hash, verify};
-use jsonwebtoken::{encode, Header, EncodingKey};
+use futures_util::future::LocalBoxFuture;
 use serde::{Deserialize, Serialize};
-use std::env;
-use sqlx::Row;
+use sqlx::{Row, SqlitePool};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: i32,
     pub exp: usize,
+    /// Privilege level of the user at the time the token was issued.
+    #[serde(default)]
+    pub privilege: i32,
+    /// Unique ID of the token, used to revoke it.
+    #[serde(default)]
+    pub jti: String,
+    /// Time the token was issued, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub iat: usize,
+    /// Token version of the user when the token was issued; see the `revocation` module.
+    #[serde(default)]
+    pub ver: i64,
+    /// Issuer of the token, set from `AuthConfig::issuer`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    /// Audience of the token, set from `AuthConfig::audience`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
 }
 
 pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
@@ -19,75 +42,359 @@ pub fn verify_password(password: &str, hash: &str) -> bool {
     verify(password, hash).unwrap_or(false)
 }
 
-pub fn generate_jwt(user: &User) -> String {
+/**
+ * Generate an access token for a user.
+ *
+ * # Arguments
+ * - `config`: How the token is signed, and how long it stays valid.
+ * - `user`: The user the token is issued to.
+ * - `token_version`: The user's current token version, so the token can be revoked with
+ *   all the user's other tokens.
+ */
+pub fn generate_jwt(config: &AuthConfig, user: &User, token_version: i64) -> Result<String, ApiError> {
+    let now = chrono::Utc::now().timestamp() as usize;
     let claims = Claims {
         sub: user.id,
-        exp: (chrono::Utc::now() + chrono::Duration::days(7)).timestamp() as usize,
+        exp: now + config.get_access_token_lifetime().as_secs() as usize,
+        privilege: user.privilege,
+        jti: format!("{:032x}", rand::random::<u128>()),
+        iat: now,
+        ver: token_version,
+        ..Default::default()
     };
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref())).unwrap()
+    config.encode(&claims)
 }
 
 pub async fn register_user(
     pool: &sqlx::SqlitePool,
     input: crate::core::user::RegisterInput,
-) -> Result<User, String> {
+) -> Result<User, ApiError> {
     // Hash password
-    let password_hash = hash_password(&input.password).map_err(|e| e.to_string())?;
+    let password_hash = hash_password(&input.password).map_err(|e| {
+        println!("ERROR: Failed to hash password: {}", e);
+        ApiError::internal("Failed to hash password")
+    })?;
     
     // Insert user
     let user = sqlx::query_as::<_, User>(
-        "INSERT INTO users (username, password_hash) VALUES (?, ?) RETURNING id, username, password_hash"
+        "INSERT INTO users (username, password_hash, email) VALUES (?, ?, ?) RETURNING id, username, password_hash, privilege"
     )
     .bind(&input.username)
     .bind(&password_hash)
+    .bind(&input.email)
     .fetch_one(pool)
     .await
-    .map_err(|e| format!("Database error: {}", e))?;
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            ApiError::conflict("Username is already taken").with_code("username_taken")
+        }
+        e => ApiError::from(e),
+    })?;
     
     Ok(user)
 }
 
+/**
+ * Check a username and password.
+ *
+ * Users with two-factor authentication enabled receive a challenge token, to exchange
+ * for tokens with `complete_two_factor_login`; everyone else receives tokens straight away.
+ *
+ * # Returns
+ * The tokens or challenge, or `401 Unauthorized` if the credentials are wrong.
+ */
 pub async fn login_user(
     pool: &sqlx::SqlitePool,
+    config: &AuthConfig,
     input: crate::core::user::LoginInput,
-) -> Result<LoginResponse, String> {
+) -> Result<LoginOutcome, ApiError> {
     // Find user
-    let row = sqlx::query("SELECT id, username, password_hash FROM users WHERE username = ?")
+    let row = sqlx::query("SELECT id, username, password_hash, privilege, token_version, totp_enabled FROM users WHERE username = ?")
         .bind(&input.username)
         .fetch_optional(pool)
-        .await
-        .map_err(|e| format!("Database error: {}", e))?
-        .ok_or("User not found")?;
+        .await?
+        .ok_or_else(invalid_credentials)?;
 
     let user = User {
         id: row.get("id"),
         username: row.get("username"),
         password_hash: row.get("password_hash"),
+        privilege: row.get("privilege"),
     };
     
     // Verify password
     if !verify_password(&input.password, &user.password_hash) {
-        return Err("Invalid password".to_string());
+        return Err(invalid_credentials());
     }
     
-    // Generate JWT
-    let token = generate_jwt(&user);
-    Ok(LoginResponse { token })
+    // Ask for the second factor before issuing any token
+    if row.get::<bool, _>("totp_enabled") {
+        let challenge_token = create_challenge(pool, user.id).await?;
+        return Ok(LoginOutcome::Challenge(ChallengeResponse::new(challenge_token, CHALLENGE_LIFETIME)));
+    }
+
+    let response = start_session(pool, config, &user, row.get("token_version")).await?;
+    Ok(LoginOutcome::Tokens(response))
+}
+
+/**
+ * Complete a two-factor login with the challenge token from `login_user` and a TOTP or
+ * recovery code.
+ *
+ * # Returns
+ * The new tokens, or `401 Unauthorized` if the challenge token is unknown, expired or
+ * used up, or the code is wrong.
+ */
+pub async fn complete_two_factor_login(
+    pool: &sqlx::SqlitePool,
+    config: &AuthConfig,
+    input: TwoFactorLoginInput,
+) -> Result<LoginResponse, ApiError> {
+    let invalid = || ApiError::unauthorized("Invalid challenge token or code").with_code("invalid_two_factor_code");
+
+    let user_id = redeem_challenge(pool, &input.challenge_token, &input.code)
+        .await?
+        .ok_or_else(invalid)?;
+    let user = sqlx::query_as::<_, User>("SELECT id, username, password_hash, privilege FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(invalid)?;
+
+    let version = token_version(pool, user.id).await?;
+    start_session(pool, config, &user, version).await
+}
+
+/// Generate an access token and start a refresh token family for a user who has logged in.
+async fn start_session(
+    pool: &SqlitePool,
+    config: &AuthConfig,
+    user: &User,
+    token_version: i64,
+) -> Result<LoginResponse, ApiError> {
+    let refresh_token = issue_refresh_token(pool, user.id, config.get_refresh_token_lifetime()).await?;
+    let token = generate_jwt(config, user, token_version)?;
+    Ok(LoginResponse::new(token, config.get_access_token_lifetime(), refresh_token))
+}
+
+/**
+ * Exchange a refresh token for a new access token and refresh token.
+ *
+ * The refresh token is rotated: it cannot be used again, and using it again revokes
+ * every token issued since the login it came from.
+ *
+ * # Returns
+ * The new tokens, or `401 Unauthorized` if the refresh token is unknown, expired,
+ * revoked or reused, or its user no longer exists.
+ */
+pub async fn refresh_session(
+    pool: &sqlx::SqlitePool,
+    config: &AuthConfig,
+    refresh_token: &str,
+) -> Result<LoginResponse, ApiError> {
+    let invalid = || ApiError::unauthorized("Invalid refresh token").with_code("invalid_refresh_token");
+
+    let (user_id, refresh_token) = rotate_refresh_token(pool, refresh_token, config.get_refresh_token_lifetime())
+        .await?
+        .ok_or_else(invalid)?;
+    let user = sqlx::query_as::<_, User>("SELECT id, username, password_hash, privilege FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(invalid)?;
+
+    let version = token_version(pool, user.id).await?;
+    let token = generate_jwt(config, &user, version)?;
+    Ok(LoginResponse::new(token, config.get_access_token_lifetime(), refresh_token))
+}
+
+/**
+ * Change the password of a user.
+ *
+ * All the user's access tokens and refresh tokens are revoked, so every session,
+ * including one an attacker may hold, has to log in again with the new password.
+ *
+ * # Returns
+ * `Ok(())`, or `404 Not Found` if the user does not exist.
+ */
+pub async fn change_password(pool: &SqlitePool, user_id: i32, new_password: &str) -> Result<(), ApiError> {
+    let password_hash = hash_password(new_password).map_err(|e| {
+        println!("ERROR: Failed to hash password: {}", e);
+        ApiError::internal("Failed to hash password")
+    })?;
+
+    let result = sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+        .bind(&password_hash)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found(format!("User {} not found", user_id)).with_code("user_not_found"));
+    }
+
+    revoke_all_tokens(pool, user_id).await?;
+    Ok(())
+}
+
+/**
+ * Check the current password of a user, before a change to their account's security.
+ *
+ * # Returns
+ * `Ok(())`, `403 Forbidden` if the password is wrong, or `404 Not Found` if the user does
+ * not exist.
+ */
+pub async fn confirm_current_password(pool: &SqlitePool, user_id: i32, password: &str) -> Result<(), ApiError> {
+    let password_hash: String = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("User {} not found", user_id)).with_code("user_not_found"))?;
+
+    if !verify_password(password, &password_hash) {
+        return Err(ApiError::forbidden("Wrong password").with_code("wrong_password"));
+    }
+    Ok(())
+}
+
+/// The error for a failed login, which does not reveal whether the username exists.
+fn invalid_credentials() -> ApiError {
+    ApiError::unauthorized("Invalid username or password").with_code("invalid_credentials")
+}
+
+/**
+ * Validate a JWT and check that it has not been revoked.
+ *
+ * # Arguments
+ * - `config`: The keys and claims the token is checked against.
+ * - `pool`: The user database holding revoked tokens.
+ * - `token`: The token to validate.
+ *
+ * # Returns
+ * The claims of the token, or `401 Unauthorized` if the token is invalid, expired,
+ * issued for another issuer or audience, or revoked.
+ */
+pub async fn validate_token(config: &AuthConfig, pool: &SqlitePool, token: &str) -> Result<Claims, ApiError> {
+    let claims = config.decode(token)?;
+    if is_token_revoked(pool, &claims).await? {
+        return Err(ApiError::unauthorized("Token has been revoked").with_code("token_revoked"));
+    }
+    Ok(claims)
+}
+
+/**
+ * Extractor for the authenticated user of a request.
+ *
+ * `AuthUser` reads the `Authorization: Bearer <token>` header, validates the token with
+ * `validate_token`, rejecting revoked tokens, and exposes the user ID and claims. The
+ * claims are kept in the request extensions, so the token is only checked once per
+ * request, even when a scope guard has already checked it. Because it implements Actix's
+ * `FromRequest`, it can be combined with any other extractor such as `web::Json<T>`,
+ * `web::Path<T>`, `web::Query<T>` or `web::Data<T>`. Requests without a valid token
+ * are rejected with `401 Unauthorized` before the handler runs.
+ *
+ * Register handlers that take an `AuthUser` with `add_route_with_bearer` or
+ * `add_route_with_privilege`, so the route table and OpenAPI document show that they need
+ * a token; `add_route` lists every route as public.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{web, AuthUser, HttpResponse, Method, Routes};
+ * use serde::Deserialize;
+ *
+ * #[derive(Deserialize)]
+ * struct Rename {
+ *     name: String,
+ * }
+ *
+ * async fn rename(user: AuthUser, input: web::Json<Rename>) -> HttpResponse {
+ *     HttpResponse::Ok().body(format!("User {} renamed to {}", user.id, input.name))
+ * }
+ *
+ * let routes = Routes::new()
+ *     .add_method_route_with_bearer(Method::POST, "/rename", rename);
+ * ```
+ */
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    /// The ID of the authenticated user, taken from the token's `sub` claim.
+    pub id: i32,
+
+    /// The privilege level of the authenticated user, as recorded in the token.
+    pub privilege: i32,
+
+    /// The full set of claims carried by the token.
+    pub claims: Claims,
+}
+
+impl FromRequest for AuthUser {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            // A scope guard or route wrapper may already have checked the token of this request
+            let cached = req.extensions().get::<Claims>().cloned();
+            let claims = match cached {
+                Some(claims) => claims,
+                None => {
+                    let token = bearer_token(&req).ok_or_else(missing_token)?;
+                    let claims = authenticate_token(&req, token).await?;
+                    req.extensions_mut().insert(claims.clone());
+                    claims
+                }
+            };
+            Ok(AuthUser { id: claims.sub, privilege: claims.privilege, claims })
+        })
+    }
 }
 
 /**
- * Middleware to extract and validate JWT token from the request.
+ * Validate the token of a request.
+ *
+ * Revocations are checked when the user database is enabled. Without it, no tokens can
+ * have been revoked, so only the signature and expiry are checked.
  */
-pub fn validate_token(token: &str) -> Result<Claims, actix_web::Error> {
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-
-    match jsonwebtoken::decode::<Claims>(
-        token,
-        &jsonwebtoken::DecodingKey::from_secret(secret.as_ref()),
-        &jsonwebtoken::Validation::default(),
-    ) {
-        Ok(decoded) => Ok(decoded.claims),
-        Err(_) => Err(actix_web::error::ErrorUnauthorized("Invalid token")),
+pub(crate) async fn authenticate_token(req: &HttpRequest, token: &str) -> Result<Claims, ApiError> {
+    let config = AuthConfig::for_request(req);
+    match req.app_data::<web::Data<SqlitePool>>() {
+        Some(pool) => validate_token(config, pool, token).await,
+        None => config.decode(token),
     }
-}
\ No newline at end of file
+}
+
+/// The error for a request that carries no token.
+pub(crate) fn missing_token() -> ApiError {
+    ApiError::unauthorized("Missing or invalid token").with_code("missing_token")
+}
+
+/// The error for a user whose privilege level is too low for the request.
+pub(crate) fn insufficient_privilege() -> ApiError {
+    ApiError::forbidden("Insufficient privilege").with_code("insufficient_privilege")
+}
+
+/// Read the token from the `Authorization: Bearer <token>` header of a request.
+pub(crate) fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+}
+
+/// Read the token from the `token` query parameter, for browser clients that cannot set headers.
+pub(crate) fn query_token(req: &HttpRequest) -> Option<String> {
+    #[derive(Deserialize)]
+    struct TokenQuery {
+        token: Option<String>,
+    }
+
+    web::Query::<TokenQuery>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.into_inner().token)
+}
+
+/// The time left until a token's `exp` claim, in seconds since the Unix epoch, has passed.
+pub(crate) fn time_until_expiry(expires_at: usize) -> std::time::Duration {
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    std::time::Duration::from_secs((expires_at as u64).saturating_sub(now))
+}
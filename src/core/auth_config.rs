@@ -0,0 +1,608 @@
+/*!
+ * Auth configuration module.
+ *
+ * `AuthConfig` controls how access tokens are signed and validated: the algorithm and
+ * keys, the `iss` and `aud` claims, token lifetimes, and the clock leeway allowed when
+ * checking expiry. It is set with `Api::auth_config`, and its keys are parsed once when
+ * the configuration is built rather than on every request.
+ *
+ * Supported algorithms:
+ * - `HS256`, with a shared secret. This is the default, using the `JWT_SECRET`
+ *   environment variable.
+ * - `RS256`, with an RSA key pair.
+ * - `ES256`, with an ECDSA key pair on the P-256 curve.
+ * - `EdDSA`, with an Ed25519 key pair.
+ *
+ * With an asymmetric algorithm, other services can verify tokens with the public key
+ * alone, without being able to issue tokens themselves; see the `jwks` module.
+ *
+ * Every token names the key it was signed with in its `kid` header. Besides the key
+ * tokens are signed with, a configuration can hold older verification keys, so that a
+ * new signing key can be rolled out while tokens signed with the old one stay valid
+ * until they expire.
+ *
+ * # Example
+ * ```rust,no_run
+ * use rusty_api::{Api, AuthConfig};
+ * use std::time::Duration;
+ *
+ * let auth = AuthConfig::rs256("keys/private.pem", "keys/public.pem")
+ *     .expect("Failed to load signing keys")
+ *     .issuer("https://auth.example.com")
+ *     .audience("example-api")
+ *     .access_token_lifetime(Duration::from_secs(10 * 60));
+ *
+ * Api::new()
+ *     .enable_user_db()
+ *     .auth_config(auth)
+ *     .start();
+ * ```
+ */
+use actix_web::{web, HttpRequest};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use once_cell::sync::Lazy;
+use std::path::Path;
+use std::time::Duration;
+use std::{env, fmt, fs, io};
+use crate::core::auth::Claims;
+use crate::core::error::ApiError;
+use crate::core::jwks::public_jwk;
+use crate::core::secret::generate_secret;
+use serde_json::{json, Value};
+
+/// How long access tokens stay valid unless configured otherwise.
+pub const DEFAULT_ACCESS_TOKEN_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// How long refresh tokens stay valid unless configured otherwise.
+pub const DEFAULT_REFRESH_TOKEN_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How far clocks may drift when checking `exp`, unless configured otherwise.
+pub const DEFAULT_LEEWAY: Duration = Duration::from_secs(60);
+
+/// The ID of an HS256 secret unless set otherwise, which unlike a thumbprint reveals nothing about the secret.
+const DEFAULT_HS256_KEY_ID: &str = "hs256-1";
+
+/// The configuration used when none is set on the `Api`, such as in tests that build an `App` directly.
+static DEFAULT_CONFIG: Lazy<AuthConfig> = Lazy::new(AuthConfig::from_env);
+
+/**
+ * How access tokens are signed and validated.
+ *
+ * See the module documentation for the supported algorithms.
+ */
+#[derive(Clone)]
+pub struct AuthConfig {
+    algorithm: Algorithm,
+    key_id: String,
+    encoding_key: EncodingKey,
+    /// The keys tokens are verified with; the first is the public part of the signing key.
+    keys: Vec<VerificationKey>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    access_token_lifetime: Duration,
+    refresh_token_lifetime: Duration,
+    leeway: Duration,
+}
+
+impl AuthConfig {
+    /**
+     * Sign tokens with HS256 and a shared secret.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::AuthConfig;
+     * use jsonwebtoken::Algorithm;
+     *
+     * let auth = AuthConfig::hs256("a long random secret");
+     * assert_eq!(auth.get_algorithm(), Algorithm::HS256);
+     * assert_eq!(auth.get_key_id(), "hs256-1");
+     * ```
+     */
+    pub fn hs256(secret: &str) -> Self {
+        Self::with_keys(EncodingKey::from_secret(secret.as_bytes()), VerificationKey::hs256(secret))
+    }
+
+    /**
+     * Sign tokens with HS256 and the secret in the `JWT_SECRET` environment variable.
+     *
+     * If the variable is not set, a random secret is generated and a warning is printed.
+     * Tokens signed with a random secret stop being valid when the server restarts.
+     */
+    pub fn from_env() -> Self {
+        match env::var("JWT_SECRET") {
+            Ok(secret) if !secret.is_empty() => Self::hs256(&secret),
+            _ => {
+                println!("WARNING: JWT_SECRET is not set; tokens are signed with a random secret and will not survive a restart");
+                Self::hs256(&generate_secret(""))
+            }
+        }
+    }
+
+    /**
+     * Sign tokens with RS256 and an RSA key pair.
+     *
+     * # Arguments
+     * - `private_key`: Path to the PEM-encoded RSA private key, in PKCS#1 or PKCS#8 form.
+     * - `public_key`: Path to the PEM-encoded RSA public key.
+     *
+     * # Returns
+     * The configuration, or an error if a file cannot be read, does not contain a valid
+     * key, or the keys do not belong together.
+     */
+    pub fn rs256(private_key: impl AsRef<Path>, public_key: impl AsRef<Path>) -> io::Result<Self> {
+        Self::with_key_pair(
+            read_key(private_key.as_ref(), EncodingKey::from_rsa_pem)?,
+            VerificationKey::rs256(public_key)?,
+        )
+    }
+
+    /**
+     * Sign tokens with ES256 and an ECDSA key pair on the P-256 curve.
+     *
+     * # Arguments
+     * - `private_key`: Path to the PEM-encoded PKCS#8 private key.
+     * - `public_key`: Path to the PEM-encoded public key.
+     *
+     * # Returns
+     * The configuration, or an error if a file cannot be read, does not contain a valid
+     * key, or the keys do not belong together.
+     */
+    pub fn es256(private_key: impl AsRef<Path>, public_key: impl AsRef<Path>) -> io::Result<Self> {
+        Self::with_key_pair(
+            read_key(private_key.as_ref(), EncodingKey::from_ec_pem)?,
+            VerificationKey::es256(public_key)?,
+        )
+    }
+
+    /**
+     * Sign tokens with EdDSA and an Ed25519 key pair.
+     *
+     * # Arguments
+     * - `private_key`: Path to the PEM-encoded PKCS#8 private key.
+     * - `public_key`: Path to the PEM-encoded public key.
+     *
+     * # Returns
+     * The configuration, or an error if a file cannot be read, does not contain a valid
+     * key, or the keys do not belong together.
+     */
+    pub fn ed_dsa(private_key: impl AsRef<Path>, public_key: impl AsRef<Path>) -> io::Result<Self> {
+        Self::with_key_pair(
+            read_key(private_key.as_ref(), EncodingKey::from_ed_pem)?,
+            VerificationKey::ed_dsa(public_key)?,
+        )
+    }
+
+    /// Build a configuration with the default claims and lifetimes.
+    fn with_keys(encoding_key: EncodingKey, key: VerificationKey) -> Self {
+        Self {
+            algorithm: key.algorithm,
+            key_id: key.key_id.clone(),
+            encoding_key,
+            keys: vec![key],
+            issuer: None,
+            audience: None,
+            access_token_lifetime: DEFAULT_ACCESS_TOKEN_LIFETIME,
+            refresh_token_lifetime: DEFAULT_REFRESH_TOKEN_LIFETIME,
+            leeway: DEFAULT_LEEWAY,
+        }
+    }
+
+    /// Build a configuration from a private and public key, checking that they belong together.
+    fn with_key_pair(encoding_key: EncodingKey, key: VerificationKey) -> io::Result<Self> {
+        let config = Self::with_keys(encoding_key, key);
+        let probe = Claims { exp: chrono::Utc::now().timestamp() as usize + 60, ..Default::default() };
+        match config.encode(&probe).and_then(|token| config.decode(&token)) {
+            Ok(_) => Ok(config),
+            Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "The private key does not match the public key")),
+        }
+    }
+
+    /**
+     * Set the ID of the signing key, sent in the `kid` header of every token.
+     *
+     * By default, the ID of a public key is its JWK thumbprint, and the ID of an HS256
+     * secret is `hs256-1`.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::AuthConfig;
+     *
+     * let auth = AuthConfig::hs256("secret").key_id("2025-06");
+     * assert_eq!(auth.get_key_id(), "2025-06");
+     * ```
+     */
+    pub fn key_id(mut self, kid: &str) -> Self {
+        self.key_id = kid.to_owned();
+        self.keys[0] = self.keys[0].clone().key_id(kid);
+        self
+    }
+
+    /**
+     * Also accept tokens signed with another key, such as the previous signing key.
+     *
+     * Keep the old key until every token it signed has expired, then remove it.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::AuthConfig;
+     * use rusty_api::core::auth_config::VerificationKey;
+     *
+     * let auth = AuthConfig::hs256("new secret")
+     *     .verification_key(VerificationKey::hs256("old secret").key_id("old"));
+     * assert_eq!(auth.get_verification_keys().len(), 2);
+     * ```
+     */
+    pub fn verification_key(mut self, key: VerificationKey) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    /**
+     * Set the `iss` claim of issued tokens. Tokens from any other issuer are rejected.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::AuthConfig;
+     *
+     * let auth = AuthConfig::hs256("secret").issuer("https://auth.example.com");
+     * assert_eq!(auth.get_issuer(), Some("https://auth.example.com"));
+     * ```
+     */
+    pub fn issuer(mut self, issuer: &str) -> Self {
+        self.issuer = Some(issuer.to_owned());
+        self
+    }
+
+    /**
+     * Set the `aud` claim of issued tokens. Tokens for any other audience are rejected.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::AuthConfig;
+     *
+     * let auth = AuthConfig::hs256("secret").audience("example-api");
+     * assert_eq!(auth.get_audience(), Some("example-api"));
+     * ```
+     */
+    pub fn audience(mut self, audience: &str) -> Self {
+        self.audience = Some(audience.to_owned());
+        self
+    }
+
+    /**
+     * Set how long access tokens stay valid, 15 minutes by default.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::AuthConfig;
+     * use std::time::Duration;
+     *
+     * let auth = AuthConfig::hs256("secret").access_token_lifetime(Duration::from_secs(300));
+     * assert_eq!(auth.get_access_token_lifetime(), Duration::from_secs(300));
+     * ```
+     */
+    pub fn access_token_lifetime(mut self, lifetime: Duration) -> Self {
+        self.access_token_lifetime = lifetime;
+        self
+    }
+
+    /**
+     * Set how long refresh tokens stay valid, 30 days by default.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::AuthConfig;
+     * use std::time::Duration;
+     *
+     * let auth = AuthConfig::hs256("secret").refresh_token_lifetime(Duration::from_secs(7 * 24 * 60 * 60));
+     * assert_eq!(auth.get_refresh_token_lifetime().as_secs(), 604800);
+     * ```
+     */
+    pub fn refresh_token_lifetime(mut self, lifetime: Duration) -> Self {
+        self.refresh_token_lifetime = lifetime;
+        self
+    }
+
+    /**
+     * Set how far clocks may drift when checking whether a token has expired, 60 seconds by default.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::AuthConfig;
+     * use std::time::Duration;
+     *
+     * let auth = AuthConfig::hs256("secret").leeway(Duration::from_secs(5));
+     * assert_eq!(auth.get_leeway(), Duration::from_secs(5));
+     * ```
+     */
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// The algorithm tokens are signed with.
+    pub fn get_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The ID of the signing key.
+    pub fn get_key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// The keys tokens are verified with, starting with the signing key.
+    pub fn get_verification_keys(&self) -> &[VerificationKey] {
+        &self.keys
+    }
+
+    /// The `iss` claim of issued tokens, if set.
+    pub fn get_issuer(&self) -> Option<&str> {
+        self.issuer.as_deref()
+    }
+
+    /// The `aud` claim of issued tokens, if set.
+    pub fn get_audience(&self) -> Option<&str> {
+        self.audience.as_deref()
+    }
+
+    /// How long access tokens stay valid.
+    pub fn get_access_token_lifetime(&self) -> Duration {
+        self.access_token_lifetime
+    }
+
+    /// How long refresh tokens stay valid.
+    pub fn get_refresh_token_lifetime(&self) -> Duration {
+        self.refresh_token_lifetime
+    }
+
+    /// How far clocks may drift when checking expiry.
+    pub fn get_leeway(&self) -> Duration {
+        self.leeway
+    }
+
+    /**
+     * Sign a set of claims.
+     *
+     * The `iss` and `aud` claims are set from the configuration, and the `kid` header
+     * names the signing key.
+     *
+     * # Returns
+     * The signed token, or `500 Internal Server Error` if signing fails.
+     */
+    pub fn encode(&self, claims: &Claims) -> Result<String, ApiError> {
+        let claims = Claims { iss: self.issuer.clone(), aud: self.audience.clone(), ..claims.clone() };
+        let header = Header { kid: Some(self.key_id.clone()), ..Header::new(self.algorithm) };
+        jsonwebtoken::encode(&header, &claims, &self.encoding_key).map_err(|e| {
+            println!("ERROR: Failed to sign token: {}", e);
+            ApiError::internal("Failed to sign token")
+        })
+    }
+
+    /**
+     * Validate the signature, expiry, issuer and audience of a token.
+     *
+     * The token is verified with the key named by its `kid` header. Tokens without a
+     * `kid` are tried against every key of their algorithm. Revocations are not checked here; see `validate_token`.
+     *
+     * # Returns
+     * The claims of the token, or `401 Unauthorized` if the token is not valid.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{AuthConfig, Claims};
+     *
+     * let auth = AuthConfig::hs256("secret").audience("example-api");
+     * let claims = Claims {
+     *     sub: 42,
+     *     exp: (chrono::Utc::now().timestamp() + 60) as usize,
+     *     ..Default::default()
+     * };
+     *
+     * let token = auth.encode(&claims).unwrap();
+     * assert_eq!(auth.decode(&token).unwrap().sub, 42);
+     *
+     * // A token for another audience is rejected
+     * let other = AuthConfig::hs256("secret").audience("other-api");
+     * assert!(other.decode(&token).is_err());
+     * ```
+     */
+    pub fn decode(&self, token: &str) -> Result<Claims, ApiError> {
+        let invalid = || ApiError::unauthorized("Invalid token").with_code("invalid_token");
+        let header = jsonwebtoken::decode_header(token).map_err(|_| invalid())?;
+
+        self.keys
+            .iter()
+            .filter(|key| key.algorithm == header.alg)
+            .filter(|key| header.kid.as_ref().is_none_or(|kid| *kid == key.key_id))
+            .find_map(|key| jsonwebtoken::decode::<Claims>(token, &key.decoding_key, &self.validation(key.algorithm)).ok())
+            .map(|data| data.claims)
+            .ok_or_else(invalid)
+    }
+
+    /**
+     * Build the JSON Web Key Set of the public verification keys.
+     *
+     * HS256 keys are secret, so they are never included.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::AuthConfig;
+     *
+     * let auth = AuthConfig::hs256("secret");
+     * assert_eq!(auth.jwks()["keys"].as_array().unwrap().len(), 0);
+     * ```
+     */
+    pub fn jwks(&self) -> Value {
+        let keys: Vec<&Value> = self.keys.iter().filter_map(|key| key.jwk.as_ref()).collect();
+        json!({ "keys": keys })
+    }
+
+    /// The rules a token signed with the given algorithm must pass to be accepted.
+    fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway.as_secs();
+        let mut required = vec!["exp"];
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+            required.push("iss");
+        }
+        match &self.audience {
+            Some(audience) => {
+                validation.set_audience(&[audience]);
+                required.push("aud");
+            }
+            None => validation.validate_aud = false,
+        }
+        validation.set_required_spec_claims(&required);
+        validation
+    }
+
+    /// The configuration of the `Api` serving a request, or the default configuration.
+    pub(crate) fn for_request(req: &HttpRequest) -> &AuthConfig {
+        req.app_data::<web::Data<AuthConfig>>()
+            .map(|config| config.get_ref())
+            .unwrap_or_else(|| &DEFAULT_CONFIG)
+    }
+
+    /// The configuration used when none is set, read from the environment once.
+    pub(crate) fn default_config() -> AuthConfig {
+        DEFAULT_CONFIG.clone()
+    }
+}
+
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Keys are left out, so they never end up in logs
+        f.debug_struct("AuthConfig")
+            .field("algorithm", &self.algorithm)
+            .field("key_id", &self.key_id)
+            .field("keys", &self.keys)
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .field("access_token_lifetime", &self.access_token_lifetime)
+            .field("refresh_token_lifetime", &self.refresh_token_lifetime)
+            .field("leeway", &self.leeway)
+            .finish_non_exhaustive()
+    }
+}
+
+/**
+ * A key that access tokens are verified with.
+ *
+ * The key of an asymmetric algorithm is a public key, which is published in the key set
+ * of the `jwks` module.
+ */
+#[derive(Clone)]
+pub struct VerificationKey {
+    key_id: String,
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+    /// The public JWK, for asymmetric keys.
+    jwk: Option<Value>,
+}
+
+impl VerificationKey {
+    /// A shared HS256 secret, with the ID `hs256-1` unless set with `key_id`.
+    pub fn hs256(secret: &str) -> Self {
+        Self {
+            key_id: DEFAULT_HS256_KEY_ID.to_owned(),
+            algorithm: Algorithm::HS256,
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            jwk: None,
+        }
+    }
+
+    /**
+     * An RSA public key, for RS256 tokens.
+     *
+     * # Arguments
+     * - `public_key`: Path to the PEM-encoded `PUBLIC KEY`.
+     */
+    pub fn rs256(public_key: impl AsRef<Path>) -> io::Result<Self> {
+        Self::public(Algorithm::RS256, public_key.as_ref(), DecodingKey::from_rsa_pem)
+    }
+
+    /**
+     * An ECDSA P-256 public key, for ES256 tokens.
+     *
+     * # Arguments
+     * - `public_key`: Path to the PEM-encoded `PUBLIC KEY`.
+     */
+    pub fn es256(public_key: impl AsRef<Path>) -> io::Result<Self> {
+        Self::public(Algorithm::ES256, public_key.as_ref(), DecodingKey::from_ec_pem)
+    }
+
+    /**
+     * An Ed25519 public key, for EdDSA tokens.
+     *
+     * # Arguments
+     * - `public_key`: Path to the PEM-encoded `PUBLIC KEY`.
+     */
+    pub fn ed_dsa(public_key: impl AsRef<Path>) -> io::Result<Self> {
+        Self::public(Algorithm::EdDSA, public_key.as_ref(), DecodingKey::from_ed_pem)
+    }
+
+    /// Read a public key file, and build its JWK.
+    fn public(
+        algorithm: Algorithm,
+        path: &Path,
+        parse: fn(&[u8]) -> jsonwebtoken::errors::Result<DecodingKey>,
+    ) -> io::Result<Self> {
+        let decoding_key = read_key(path, parse)?;
+        let pem = fs::read(path)?;
+        let jwk = public_jwk(algorithm, &pem)
+            .map_err(|e| io::Error::new(e.kind(), format!("Invalid key in {}: {}", path.display(), e)))?;
+        Ok(Self {
+            key_id: jwk["kid"].as_str().unwrap_or_default().to_owned(),
+            algorithm,
+            decoding_key,
+            jwk: Some(jwk),
+        })
+    }
+
+    /**
+     * Set the ID of the key, matched against the `kid` header of tokens.
+     *
+     * By default, the ID of a public key is its JWK thumbprint, and the ID of an HS256
+     * secret is `hs256-1`.
+     */
+    pub fn key_id(mut self, kid: &str) -> Self {
+        self.key_id = kid.to_owned();
+        if let Some(jwk) = &mut self.jwk {
+            jwk["kid"] = json!(kid);
+        }
+        self
+    }
+
+    /// The ID of the key.
+    pub fn get_key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// The algorithm of the tokens the key verifies.
+    pub fn get_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The public JWK of the key, or `None` for a secret HS256 key.
+    pub fn get_jwk(&self) -> Option<&Value> {
+        self.jwk.as_ref()
+    }
+}
+
+impl fmt::Debug for VerificationKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VerificationKey")
+            .field("key_id", &self.key_id)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Read and parse a PEM key file.
+fn read_key<K>(path: &Path, parse: fn(&[u8]) -> jsonwebtoken::errors::Result<K>) -> io::Result<K> {
+    let pem = fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("Failed to read {}: {}", path.display(), e)))?;
+    parse(&pem).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("Invalid key in {}: {}", path.display(), e))
+    })
+}
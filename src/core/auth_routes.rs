@@ -5,22 +5,340 @@
  * the necessary input and output structures. It uses Actix Web for routing
  * and SQLx for database interaction.
  */
-use actix_web::{web, HttpResponse};
-use crate::core::auth::{login_user, register_user};
-use crate::core::user::{LoginInput, RegisterInput};
+use actix_web::{web, CustomizeResponder, HttpRequest, HttpResponse, Responder};
+use actix_web::http::{Method, StatusCode};
+use crate::core::auth::{complete_two_factor_login, confirm_current_password, login_user, refresh_session, register_user, AuthUser};
+use crate::core::auth_config::AuthConfig;
+use crate::core::content::Content;
+use crate::core::error::ApiError;
+use crate::core::password_reset::{confirm_password_reset, request_password_reset, PasswordReset};
+use crate::core::rate_limit::RateLimit;
+use crate::core::refresh_token::revoke_refresh_token;
+use crate::core::revocation::{revoke_all_tokens, revoke_token};
+use crate::core::two_factor::{begin_enrolment, confirm_enrolment, disable_two_factor, Enrolment};
+use crate::core::user::{
+    LoginInput, LoginOutcome, LoginResponse, LogoutInput, PasswordResetConfirmInput, PasswordResetRequestInput,
+    RecoveryCodesResponse, RefreshInput, RegisterInput, TwoFactorCodeInput, TwoFactorConfirmInput, TwoFactorEnrolInput,
+    TwoFactorLoginInput, User,
+};
+use crate::core::validation::{PasswordPolicy, UsernamePolicy, Valid, MAX_PASSWORD_BYTES};
+use crate::routes::Routes;
+use serde_json::json;
+use std::sync::Arc;
+use validator::ValidationErrors;
 
 /**
- * Configure routes for user authentication and registration.
+ * The URL paths of the built-in authentication routes.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthPaths {
+    /// Exchanges a username and password for tokens, `/login` by default.
+    pub login: String,
+    /// Creates a user, `/register` by default.
+    pub register: String,
+    /// Exchanges a refresh token for new tokens, `/refresh` by default.
+    pub refresh: String,
+    /// Revokes the token of the request, `/logout` by default.
+    pub logout: String,
+    /// Revokes every token of the user, `/logout/all` by default.
+    pub logout_all: String,
+    /// Exchanges a challenge token and a two-factor code for tokens, `/login/2fa` by default.
+    pub two_factor_login: String,
+    /// Starts a TOTP enrolment, `/2fa/enrol` by default.
+    pub two_factor_enrol: String,
+    /// Enables two-factor authentication with a first code, `/2fa/confirm` by default.
+    pub two_factor_confirm: String,
+    /// Disables two-factor authentication, `/2fa/disable` by default.
+    pub two_factor_disable: String,
+    /// Emails a password reset link, `/password/reset` by default.
+    pub password_reset_request: String,
+    /// Sets a new password with the token from a reset link, `/password/reset/confirm` by default.
+    pub password_reset_confirm: String,
+}
+
+impl Default for AuthPaths {
+    fn default() -> Self {
+        Self {
+            login: "/login".into(),
+            register: "/register".into(),
+            refresh: "/refresh".into(),
+            logout: "/logout".into(),
+            logout_all: "/logout/all".into(),
+            two_factor_login: "/login/2fa".into(),
+            two_factor_enrol: "/2fa/enrol".into(),
+            two_factor_confirm: "/2fa/confirm".into(),
+            two_factor_disable: "/2fa/disable".into(),
+            password_reset_request: "/password/reset".into(),
+            password_reset_confirm: "/password/reset/confirm".into(),
+        }
+    }
+}
+
+/**
+ * Build the routes for user authentication and registration.
  *
- * This function sets up the routes for user login and registration, using
- * Actix Web's `ServiceConfig`. It also applies middleware for JWT validation.
+ * This function creates the `POST` routes for user login, registration, token refresh,
+ * logout and two-factor authentication. Every route that checks a password, token or
+ * code, except logout, is given the provided rate limit, which is normally stricter than
+ * the global limit to slow down guessing and account spam. The logout and two-factor
+ * management routes require a valid access token. New usernames and passwords must
+ * follow the given policies, or registration fails with `422 Unprocessable Entity`.
+ * The password reset routes are only added when password resets are configured.
  *
  * # Arguments
- * - `cfg`: A mutable reference to the Actix Web `ServiceConfig`.
+ * - `paths`: The URL paths of the routes.
+ * - `rate_limit`: The rate limit applied to the login, register, refresh, two-factor and
+ *   password reset routes.
+ * - `username_policy`: The rules new usernames must follow.
+ * - `password_policy`: The rules new passwords must follow.
+ * - `password_reset`: How reset links are sent, or `None` to leave out the reset routes.
+ *
+ * # Example
+ * ```rust
+ * use actix_web::{test, web, App};
+ * use rusty_api::{PasswordPolicy, RateLimit, UsernamePolicy};
+ * use rusty_api::core::auth_routes::{auth_routes, AuthPaths};
+ * use rusty_api::core::db::migrate;
+ * use sqlx::sqlite::SqlitePoolOptions;
+ *
+ * # actix_web::rt::System::new().block_on(async {
+ * let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+ * migrate(&pool).await.unwrap();
+ *
+ * let routes = auth_routes(
+ *     &AuthPaths::default(),
+ *     RateLimit::per_minute(10, 5),
+ *     &UsernamePolicy::new(),
+ *     &PasswordPolicy::new(),
+ *     None,
+ * );
+ * let app = test::init_service(
+ *     App::new().app_data(web::Data::new(pool)).configure(|cfg| routes.configure(cfg)),
+ * ).await;
+ *
+ * let req = test::TestRequest::post()
+ *     .uri("/register")
+ *     .set_json(serde_json::json!({ "username": "alice", "password": "correct horse battery staple" }))
+ *     .to_request();
+ * let res = test::call_service(&app, req).await;
+ * assert_eq!(res.status(), 201);
+ *
+ * // The response matches the documented schema, and never includes the password hash
+ * let user: serde_json::Value = test::read_body_json(res).await;
+ * assert_eq!(user["username"], "alice");
+ * assert!(user.get("password_hash").is_none());
+ * # });
+ * ```
  */
-pub fn configure_auth_routes(cfg: &mut web::ServiceConfig, login_path: &str, register_path: &str) {
-    cfg.route(login_path, web::post().to(login))
-       .route(register_path, web::post().to(register));
+pub fn auth_routes(
+    paths: &AuthPaths,
+    rate_limit: RateLimit,
+    username_policy: &UsernamePolicy,
+    password_policy: &PasswordPolicy,
+    password_reset: Option<&PasswordReset>,
+) -> Routes {
+    let policies = Arc::new((username_policy.clone(), password_policy.clone()));
+    let register = {
+        let policies = policies.clone();
+        move |pool: web::Data<sqlx::SqlitePool>, input: Valid<Content<RegisterInput>>| {
+            let policies = policies.clone();
+            async move { register(pool, input, &policies.0, &policies.1).await }
+        }
+    };
+
+    let mut register_schema = credentials_schema(
+        json!({
+            "type": "string",
+            "minLength": username_policy.get_min_length(),
+            "maxLength": username_policy.get_max_length(),
+            "pattern": username_policy.get_pattern(),
+        }),
+        json!({
+            "type": "string",
+            "format": "password",
+            "minLength": password_policy.get_min_length(),
+            "maxLength": MAX_PASSWORD_BYTES,
+        }),
+    );
+    register_schema["properties"]["email"] = json!({ "type": "string", "format": "email", "maxLength": 254 });
+
+    let routes = Routes::new()
+        .add_method_route(Method::POST, &paths.login, login)
+        .rate_limit(rate_limit)
+        .summary("Log in and receive tokens, or a challenge if two-factor authentication is enabled")
+        .request_schema(credentials_schema(
+            json!({ "type": "string", "minLength": 1, "maxLength": 256 }),
+            json!({ "type": "string", "format": "password", "minLength": 1, "maxLength": 256 }),
+        ))
+        .response_schema(json!({
+            "oneOf": [
+                tokens_schema(),
+                {
+                    "type": "object",
+                    "properties": {
+                        "challenge_token": { "type": "string" },
+                        "challenge_type": { "type": "string", "enum": ["totp"] },
+                        "expires_in": { "type": "integer" }
+                    },
+                    "required": ["challenge_token", "challenge_type", "expires_in"]
+                }
+            ]
+        }))
+        .add_method_route(Method::POST, &paths.two_factor_login, two_factor_login)
+        .rate_limit(rate_limit)
+        .summary("Complete a login with a TOTP or recovery code")
+        .request_schema(json!({
+            "type": "object",
+            "properties": {
+                "challenge_token": { "type": "string", "minLength": 1, "maxLength": 256 },
+                "code": { "type": "string", "minLength": 1, "maxLength": 64 }
+            },
+            "required": ["challenge_token", "code"]
+        }))
+        .response_schema(tokens_schema())
+        .add_method_route(Method::POST, &paths.refresh, refresh)
+        .rate_limit(rate_limit)
+        .summary("Exchange a refresh token for new tokens")
+        .request_schema(json!({
+            "type": "object",
+            "properties": { "refresh_token": { "type": "string", "minLength": 1, "maxLength": 256 } },
+            "required": ["refresh_token"]
+        }))
+        .response_schema(tokens_schema())
+        .add_method_route(Method::POST, &paths.register, register)
+        .rate_limit(rate_limit)
+        .summary("Register a new user")
+        .request_schema(register_schema)
+        .response_schema(json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "username": { "type": "string" },
+                "privilege": { "type": "integer" }
+            },
+            "required": ["id", "username", "privilege"]
+        }))
+        .add_method_route_with_bearer(Method::POST, &paths.logout, logout)
+        .summary("Log out, revoking the access token and optionally its refresh token")
+        .add_method_route_with_bearer(Method::POST, &paths.logout_all, logout_all)
+        .summary("Log out of every session")
+        .add_method_route_with_bearer(Method::POST, &paths.two_factor_enrol, two_factor_enrol)
+        .rate_limit(rate_limit)
+        .summary("Start enrolling in two-factor authentication")
+        .request_schema(json!({
+            "type": "object",
+            "properties": { "password": current_password_schema() },
+            "required": ["password"]
+        }))
+        .response_schema(json!({
+            "type": "object",
+            "properties": {
+                "secret": { "type": "string" },
+                "otpauth_uri": { "type": "string" }
+            },
+            "required": ["secret", "otpauth_uri"]
+        }))
+        .add_method_route_with_bearer(Method::POST, &paths.two_factor_confirm, two_factor_confirm)
+        .rate_limit(rate_limit)
+        .summary("Enable two-factor authentication and receive recovery codes")
+        .request_schema(json!({
+            "type": "object",
+            "properties": {
+                "password": current_password_schema(),
+                "code": { "type": "string", "minLength": 1, "maxLength": 64 }
+            },
+            "required": ["password", "code"]
+        }))
+        .response_schema(json!({
+            "type": "object",
+            "properties": {
+                "recovery_codes": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["recovery_codes"]
+        }))
+        .add_method_route_with_bearer(Method::POST, &paths.two_factor_disable, two_factor_disable)
+        .rate_limit(rate_limit)
+        .summary("Disable two-factor authentication")
+        .request_schema(code_schema());
+
+    let Some(reset) = password_reset else {
+        return routes;
+    };
+    let reset = Arc::new(reset.clone());
+    let request_reset = move |pool: web::Data<sqlx::SqlitePool>, input: Valid<Content<PasswordResetRequestInput>>| {
+        let reset = reset.clone();
+        async move { request_reset(pool, input, &reset).await }
+    };
+    let confirm_reset = move |pool: web::Data<sqlx::SqlitePool>, input: Valid<Content<PasswordResetConfirmInput>>| {
+        let policies = policies.clone();
+        async move { confirm_reset(pool, input, &policies.1).await }
+    };
+
+    routes
+        .add_method_route(Method::POST, &paths.password_reset_request, request_reset)
+        .rate_limit(rate_limit)
+        .summary("Email a password reset link")
+        .request_schema(json!({
+            "type": "object",
+            "properties": { "username": { "type": "string", "minLength": 1, "maxLength": 256 } },
+            "required": ["username"]
+        }))
+        .add_method_route(Method::POST, &paths.password_reset_confirm, confirm_reset)
+        .rate_limit(rate_limit)
+        .summary("Set a new password with the token from a reset link")
+        .request_schema(json!({
+            "type": "object",
+            "properties": {
+                "token": { "type": "string", "minLength": 1, "maxLength": 256 },
+                "new_password": {
+                    "type": "string",
+                    "format": "password",
+                    "minLength": password_policy.get_min_length(),
+                    "maxLength": MAX_PASSWORD_BYTES,
+                }
+            },
+            "required": ["token", "new_password"]
+        }))
+}
+
+/// JSON Schema of the username and password body accepted by the login and register routes.
+fn credentials_schema(username: serde_json::Value, password: serde_json::Value) -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "username": username,
+            "password": password
+        },
+        "required": ["username", "password"]
+    })
+}
+
+/// JSON Schema of the tokens returned by the login and refresh routes.
+fn tokens_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "token": { "type": "string" },
+            "token_type": { "type": "string", "enum": ["Bearer"] },
+            "expires_in": { "type": "integer" },
+            "refresh_token": { "type": "string" }
+        },
+        "required": ["token", "token_type", "expires_in", "refresh_token"]
+    })
+}
+
+/// JSON Schema of the current password, which the two-factor enrol and confirm routes ask for.
+fn current_password_schema() -> serde_json::Value {
+    json!({ "type": "string", "format": "password", "minLength": 1, "maxLength": 256 })
+}
+
+/// JSON Schema of the body of the two-factor disable route.
+fn code_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": { "code": { "type": "string", "minLength": 1, "maxLength": 64 } },
+        "required": ["code"]
+    })
 }
 
 /**
@@ -28,23 +346,73 @@ pub fn configure_auth_routes(cfg: &mut web::ServiceConfig, login_path: &str, reg
  * 
  * This function handles user login requests. It extracts the login input
  * from the request, calls the `login_user` function to authenticate the user,
- * and returns a JSON response with the login token or an error message.
+ * and returns the login token in the format the client accepts, or an error message.
  *
  * # Arguments
+ * - `req`: The request, whose `Api` provides the `AuthConfig` tokens are signed with.
  * - `pool`: A reference to the SQLx SQLite connection pool.
- * - `input`: The login input data, containing the username and password.
+ * - `input`: The login input data, containing the username and password, in JSON or any
+ *   other enabled format.
  *
  * # Returns
- * An `HttpResponse` containing the login token or an error message.
+ * A response containing the access token and refresh token, or a challenge token if the
+ * user has two-factor authentication enabled, or an `ApiError` if the credentials are invalid.
  */
 async fn login(
+    req: HttpRequest,
     pool: web::Data<sqlx::SqlitePool>,
-    input: web::Json<LoginInput>,
-) -> HttpResponse {
-    match login_user(&pool, input.into_inner()).await {
-        Ok(response) => HttpResponse::Ok().json(response),
-        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
-    }
+    input: Valid<Content<LoginInput>>,
+) -> Result<Content<LoginOutcome>, ApiError> {
+    let response = login_user(&pool, AuthConfig::for_request(&req), input.into_inner().into_inner()).await?;
+    Ok(Content(response))
+}
+
+/**
+ * Two-factor login route handler.
+ *
+ * This function completes a login started on the login route, exchanging the challenge
+ * token and a TOTP or recovery code for tokens with `complete_two_factor_login`.
+ *
+ * # Arguments
+ * - `req`: The request, whose `Api` provides the `AuthConfig` tokens are signed with.
+ * - `pool`: A reference to the SQLx SQLite connection pool.
+ * - `input`: The challenge token and code.
+ *
+ * # Returns
+ * A response containing the access token and refresh token, or `401 Unauthorized` if the
+ * challenge token or code is not valid.
+ */
+async fn two_factor_login(
+    req: HttpRequest,
+    pool: web::Data<sqlx::SqlitePool>,
+    input: Valid<Content<TwoFactorLoginInput>>,
+) -> Result<Content<LoginResponse>, ApiError> {
+    let response = complete_two_factor_login(&pool, AuthConfig::for_request(&req), input.into_inner().into_inner()).await?;
+    Ok(Content(response))
+}
+
+/**
+ * Refresh route handler.
+ *
+ * This function exchanges a refresh token for a new access token and refresh token,
+ * using `refresh_session`. The old refresh token cannot be used again.
+ *
+ * # Arguments
+ * - `req`: The request, whose `Api` provides the `AuthConfig` tokens are signed with.
+ * - `pool`: A reference to the SQLx SQLite connection pool.
+ * - `input`: The refresh input data, containing the refresh token.
+ *
+ * # Returns
+ * A response containing the new tokens, or `401 Unauthorized` if the refresh token is
+ * not valid.
+ */
+async fn refresh(
+    req: HttpRequest,
+    pool: web::Data<sqlx::SqlitePool>,
+    input: Valid<Content<RefreshInput>>,
+) -> Result<Content<LoginResponse>, ApiError> {
+    let response = refresh_session(&pool, AuthConfig::for_request(&req), &input.refresh_token).await?;
+    Ok(Content(response))
 }
 
 /**
@@ -52,21 +420,217 @@ async fn login(
  * 
  * This function handles user registration requests. It extracts the registration
  * input from the request, calls the `register_user` function to create a new user,
- * and returns a JSON response with the user data or an error message.
+ * and returns the user data in the format the client accepts, or an error message.
  *
  * # Arguments
  * - `pool`: A reference to the SQLx SQLite connection pool.
- * - `input`: The registration input data, containing the username and password.
+ * - `input`: The registration input data, containing the username and password, in JSON or
+ *   any other enabled format.
+ * - `username_policy`: The rules the username must follow.
+ * - `password_policy`: The rules the password must follow.
  *
  * # Returns
- * An `HttpResponse` containing the user data or an error message.
+ * A response containing the user data, or an `ApiError` such as `422 Unprocessable Entity`
+ * if the credentials break a policy, or `409 Conflict` if the username is already taken.
  */
 async fn register(
     pool: web::Data<sqlx::SqlitePool>,
-    input: web::Json<RegisterInput>,
-) -> HttpResponse {
-    match register_user(&pool, input.into_inner()).await {
-        Ok(user) => HttpResponse::Created().json(user),
-        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    input: Valid<Content<RegisterInput>>,
+    username_policy: &UsernamePolicy,
+    password_policy: &PasswordPolicy,
+) -> Result<CustomizeResponder<Content<User>>, ApiError> {
+    let input = input.into_inner().into_inner();
+
+    let mut errors = ValidationErrors::new();
+    for error in username_policy.check(&input.username) {
+        errors.add("username", error);
+    }
+    for error in password_policy.check(&input.password) {
+        errors.add("password", error);
+    }
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+
+    let user = register_user(&pool, input).await?;
+    Ok(Content(user).customize().with_status(StatusCode::CREATED))
+}
+
+/**
+ * Logout route handler.
+ *
+ * This function revokes the access token the request was made with. If the body
+ * contains a refresh token, its whole family is revoked too, so the session cannot be
+ * renewed.
+ *
+ * # Arguments
+ * - `pool`: A reference to the SQLx SQLite connection pool.
+ * - `user`: The authenticated user, whose token is revoked.
+ * - `input`: The optional logout input data, containing the refresh token of the session.
+ *
+ * # Returns
+ * `204 No Content`, or an `ApiError` if the token is not valid.
+ */
+async fn logout(
+    pool: web::Data<sqlx::SqlitePool>,
+    user: AuthUser,
+    input: Option<Content<LogoutInput>>,
+) -> Result<HttpResponse, ApiError> {
+    revoke_token(&pool, &user.claims).await?;
+    if let Some(refresh_token) = input.and_then(|input| input.into_inner().refresh_token) {
+        revoke_refresh_token(&pool, user.id, &refresh_token).await?;
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/**
+ * Logout-everywhere route handler.
+ *
+ * This function revokes every access token and refresh token of the authenticated user,
+ * ending all their sessions on every device.
+ *
+ * # Arguments
+ * - `pool`: A reference to the SQLx SQLite connection pool.
+ * - `user`: The authenticated user.
+ *
+ * # Returns
+ * `204 No Content`, or an `ApiError` if the token is not valid.
+ */
+async fn logout_all(pool: web::Data<sqlx::SqlitePool>, user: AuthUser) -> Result<HttpResponse, ApiError> {
+    revoke_all_tokens(&pool, user.id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/**
+ * Two-factor enrolment route handler.
+ *
+ * This function creates a new TOTP secret for the authenticated user with
+ * `begin_enrolment`, once they have confirmed their current password. The `otpauth://`
+ * URI names the `AuthConfig` issuer, or `rusty-api`.
+ *
+ * # Arguments
+ * - `req`: The request, whose `Api` provides the `AuthConfig`.
+ * - `pool`: A reference to the SQLx SQLite connection pool.
+ * - `user`: The authenticated user.
+ * - `input`: The current password of the user.
+ *
+ * # Returns
+ * The secret and its URI, `403 Forbidden` if the password is wrong, or `409 Conflict` if
+ * two-factor authentication is already enabled.
+ */
+async fn two_factor_enrol(
+    req: HttpRequest,
+    pool: web::Data<sqlx::SqlitePool>,
+    user: AuthUser,
+    input: Valid<Content<TwoFactorEnrolInput>>,
+) -> Result<Content<Enrolment>, ApiError> {
+    confirm_current_password(&pool, user.id, &input.password).await?;
+    let issuer = AuthConfig::for_request(&req).get_issuer().unwrap_or("rusty-api");
+    let enrolment = begin_enrolment(&pool, user.id, issuer).await?;
+    Ok(Content(enrolment))
+}
+
+/**
+ * Two-factor confirmation route handler.
+ *
+ * This function enables two-factor authentication with `confirm_enrolment`, once the
+ * user sends their current password and a code from their authenticator app.
+ *
+ * # Arguments
+ * - `pool`: A reference to the SQLx SQLite connection pool.
+ * - `user`: The authenticated user.
+ * - `input`: The current password and the TOTP code.
+ *
+ * # Returns
+ * The recovery codes, or an `ApiError` if the password is wrong, there is no pending
+ * enrolment or the code is wrong.
+ */
+async fn two_factor_confirm(
+    pool: web::Data<sqlx::SqlitePool>,
+    user: AuthUser,
+    input: Valid<Content<TwoFactorConfirmInput>>,
+) -> Result<Content<RecoveryCodesResponse>, ApiError> {
+    confirm_current_password(&pool, user.id, &input.password).await?;
+    let recovery_codes = confirm_enrolment(&pool, user.id, &input.code).await?;
+    Ok(Content(RecoveryCodesResponse { recovery_codes }))
+}
+
+/**
+ * Two-factor disable route handler.
+ *
+ * This function disables two-factor authentication with `disable_two_factor`.
+ *
+ * # Arguments
+ * - `pool`: A reference to the SQLx SQLite connection pool.
+ * - `user`: The authenticated user.
+ * - `input`: A TOTP code or recovery code.
+ *
+ * # Returns
+ * `204 No Content`, or an `ApiError` if two-factor authentication is not enabled or the
+ * code is wrong.
+ */
+async fn two_factor_disable(
+    pool: web::Data<sqlx::SqlitePool>,
+    user: AuthUser,
+    input: Valid<Content<TwoFactorCodeInput>>,
+) -> Result<HttpResponse, ApiError> {
+    disable_two_factor(&pool, user.id, &input.code).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/**
+ * Password reset request route handler.
+ *
+ * This function emails a reset link with `request_password_reset`. The response is the
+ * same whether or not the user exists, so it cannot be used to find usernames.
+ *
+ * # Arguments
+ * - `pool`: A reference to the SQLx SQLite connection pool.
+ * - `input`: The username of the account.
+ * - `reset`: How the reset link is sent.
+ *
+ * # Returns
+ * `202 Accepted`, or an `ApiError` if the database fails.
+ */
+async fn request_reset(
+    pool: web::Data<sqlx::SqlitePool>,
+    input: Valid<Content<PasswordResetRequestInput>>,
+    reset: &PasswordReset,
+) -> Result<HttpResponse, ApiError> {
+    request_password_reset(&pool, reset, &input.username).await?;
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/**
+ * Password reset confirm route handler.
+ *
+ * This function sets a new password with `confirm_password_reset`, after checking it
+ * against the password policy.
+ *
+ * # Arguments
+ * - `pool`: A reference to the SQLx SQLite connection pool.
+ * - `input`: The reset token and the new password.
+ * - `password_policy`: The rules the new password must follow.
+ *
+ * # Returns
+ * `204 No Content`, `422 Unprocessable Entity` if the password breaks the policy, or
+ * `400 Bad Request` if the token is not valid.
+ */
+async fn confirm_reset(
+    pool: web::Data<sqlx::SqlitePool>,
+    input: Valid<Content<PasswordResetConfirmInput>>,
+    password_policy: &PasswordPolicy,
+) -> Result<HttpResponse, ApiError> {
+    let input = input.into_inner().into_inner();
+
+    let mut errors = ValidationErrors::new();
+    for error in password_policy.check(&input.new_password) {
+        errors.add("new_password", error);
     }
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+
+    confirm_password_reset(&pool, &input.token, &input.new_password).await?;
+    Ok(HttpResponse::NoContent().finish())
 }
\ No newline at end of file
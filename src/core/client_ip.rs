@@ -0,0 +1,271 @@
+/*!
+ * Client IP module.
+ *
+ * This module resolves the real IP address of the client behind a request. When the
+ * server runs behind a reverse proxy or tunnel (such as nginx or ngrok), every request
+ * arrives from the proxy's address, and the original client is only named in the
+ * `X-Forwarded-For` or `Forwarded` header. Those headers can be forged by anyone, so
+ * they are only believed when the request comes from a proxy listed with
+ * `Api::trusted_proxies`, and only the header that proxy writes is read: a proxy that
+ * appends to `X-Forwarded-For` passes a client's own `Forwarded` header through unchanged.
+ * The header defaults to `X-Forwarded-For`, as written by nginx and ngrok, and can be
+ * changed with `Api::forwarded_header`.
+ *
+ * The resolved address is used by the rate limiter, and is available to handlers
+ * through the `ClientIp` extractor for logging and IP-based checks.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{Api, ClientIp, HttpResponse, Routes};
+ *
+ * async fn whoami(ip: ClientIp) -> HttpResponse {
+ *     HttpResponse::Ok().body(format!("Your IP address is {}", ip.0))
+ * }
+ *
+ * let api = Api::new()
+ *     .trusted_proxies(&["127.0.0.1", "10.0.0.0/8"])
+ *     .configure_routes(Routes::new().add_route("/whoami", whoami));
+ * ```
+ */
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use actix_web::http::header::{HeaderName, FORWARDED, X_FORWARDED_FOR};
+use futures_util::future::{ready, Ready};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use crate::core::error::ApiError;
+
+/**
+ * The header a trusted proxy records the client address in.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ForwardedHeader {
+    /// The de facto `X-Forwarded-For` header, written by nginx, ngrok and most proxies.
+    #[default]
+    XForwardedFor,
+    /// The standard `Forwarded` header ([RFC 7239](https://www.rfc-editor.org/rfc/rfc7239)).
+    Forwarded,
+}
+
+/**
+ * The set of proxies whose forwarding headers are trusted.
+ *
+ * Entries are CIDR ranges such as `10.0.0.0/8`, or single addresses such as `127.0.0.1`.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+    header: ForwardedHeader,
+}
+
+impl TrustedProxies {
+    /**
+     * Parse a list of trusted proxy ranges.
+     *
+     * Invalid entries are reported and skipped, so a typo never causes a forged header
+     * to be trusted.
+     *
+     * # Arguments
+     * - `cidrs`: CIDR ranges or single IP addresses.
+     */
+    pub fn new(cidrs: &[&str]) -> Self {
+        let networks = cidrs
+            .iter()
+            .filter_map(|cidr| {
+                let parsed = IpNet::from_str(cidr)
+                    .or_else(|_| IpAddr::from_str(cidr).map(IpNet::from));
+                if parsed.is_err() {
+                    println!("WARNING: Ignoring invalid trusted proxy '{}'", cidr);
+                }
+                parsed.ok()
+            })
+            .collect();
+
+        Self { networks, header: ForwardedHeader::default() }
+    }
+
+    /**
+     * Set the header the proxies record the client address in, `X-Forwarded-For` by default.
+     *
+     * The other header is never read, since the proxies pass it through from the client.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::client_ip::{ForwardedHeader, TrustedProxies};
+     *
+     * let trusted = TrustedProxies::new(&["10.0.0.0/8"]).header(ForwardedHeader::Forwarded);
+     * assert_eq!(trusted.get_header(), ForwardedHeader::Forwarded);
+     * ```
+     */
+    pub fn header(mut self, header: ForwardedHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Get the header the proxies record the client address in.
+    pub fn get_header(&self) -> ForwardedHeader {
+        self.header
+    }
+
+    /// Check whether the given address belongs to a trusted proxy.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    /// Check whether no proxies are trusted.
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+}
+
+/**
+ * Extractor for the resolved IP address of the client.
+ *
+ * This is the peer address of the connection, unless that peer is a trusted proxy, in
+ * which case the forwarding headers are followed back to the first untrusted address.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl FromRequest for ClientIp {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            client_ip(req)
+                .map(ClientIp)
+                .ok_or_else(|| ApiError::bad_request("Unable to determine client address")),
+        )
+    }
+}
+
+/**
+ * Resolve the IP address of the client that sent a request.
+ *
+ * The trusted proxies are read from the application data registered by `Api`. Without
+ * any trusted proxies, this is simply the peer address of the connection.
+ *
+ * # Returns
+ * The client's IP address, or `None` if the request has no peer address (as in some tests).
+ *
+ * # Example
+ * ```rust
+ * use actix_web::{test::TestRequest, web};
+ * use rusty_api::core::client_ip::{client_ip, ForwardedHeader, TrustedProxies};
+ *
+ * let trusted = web::Data::new(TrustedProxies::new(&["10.0.0.0/8"]));
+ * let request = |peer: &str, header: (&'static str, &'static str)| {
+ *     TestRequest::default()
+ *         .peer_addr(peer.parse().unwrap())
+ *         .insert_header(header)
+ *         .app_data(trusted.clone())
+ *         .to_http_request()
+ * };
+ *
+ * // Behind a trusted proxy, the chain is followed back to the first untrusted address
+ * let req = request("10.0.0.1:443", ("X-Forwarded-For", "203.0.113.7, 198.51.100.2, 10.0.0.2"));
+ * assert_eq!(client_ip(&req), Some("198.51.100.2".parse().unwrap()));
+ *
+ * // The proxy appends to `X-Forwarded-For`, so a `Forwarded` header came from the client
+ * let req = TestRequest::default()
+ *     .peer_addr("10.0.0.1:443".parse().unwrap())
+ *     .insert_header(("Forwarded", "for=1.2.3.4"))
+ *     .insert_header(("X-Forwarded-For", "203.0.113.7"))
+ *     .app_data(trusted.clone())
+ *     .to_http_request();
+ * assert_eq!(client_ip(&req), Some("203.0.113.7".parse().unwrap()));
+ *
+ * // Proxies that write the standard `Forwarded` header must be configured to, and then
+ * // `X-Forwarded-For` is the one ignored
+ * let req = TestRequest::default()
+ *     .peer_addr("10.0.0.1:443".parse().unwrap())
+ *     .insert_header(("Forwarded", r#"for=192.0.2.60;proto=https, for="[2001:db8::1]:4711""#))
+ *     .insert_header(("X-Forwarded-For", "1.2.3.4"))
+ *     .app_data(web::Data::new(TrustedProxies::new(&["10.0.0.0/8"]).header(ForwardedHeader::Forwarded)))
+ *     .to_http_request();
+ * assert_eq!(client_ip(&req), Some("2001:db8::1".parse().unwrap()));
+ *
+ * // Anyone else's forwarding headers are ignored, since they can be forged
+ * let req = request("198.51.100.9:50000", ("X-Forwarded-For", "203.0.113.7"));
+ * assert_eq!(client_ip(&req), Some("198.51.100.9".parse().unwrap()));
+ *
+ * // Without trusted proxies, the peer address is used
+ * let req = TestRequest::default()
+ *     .peer_addr("10.0.0.1:443".parse().unwrap())
+ *     .insert_header(("X-Forwarded-For", "203.0.113.7"))
+ *     .to_http_request();
+ * assert_eq!(client_ip(&req), Some("10.0.0.1".parse().unwrap()));
+ * ```
+ */
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    match req.app_data::<web::Data<TrustedProxies>>() {
+        Some(trusted) if !trusted.is_empty() => Some(resolve(req, peer, trusted)),
+        _ => Some(peer),
+    }
+}
+
+/// Walk the forwarding chain from the nearest hop outwards, stopping at the first untrusted address.
+fn resolve(req: &HttpRequest, peer: IpAddr, trusted: &TrustedProxies) -> IpAddr {
+    let mut client = peer;
+    if !trusted.contains(&client) {
+        return client;
+    }
+
+    for hop in forwarded_chain(req, trusted.header).into_iter().rev() {
+        // An unreadable hop (such as `unknown` or an obfuscated name) ends the chain at the proxy that reported it
+        let Some(ip) = hop else {
+            return client;
+        };
+        client = ip;
+        if !trusted.contains(&client) {
+            return client;
+        }
+    }
+
+    client
+}
+
+/// Read the client addresses recorded by proxies, from the one header the trusted proxies write.
+fn forwarded_chain(req: &HttpRequest, header: ForwardedHeader) -> Vec<Option<IpAddr>> {
+    match header {
+        ForwardedHeader::Forwarded => header_elements(req, FORWARDED)
+            .iter()
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| parse_node(value))
+            })
+            .collect(),
+        ForwardedHeader::XForwardedFor => header_elements(req, X_FORWARDED_FOR)
+            .iter()
+            .map(|element| parse_node(element))
+            .collect(),
+    }
+}
+
+/// Collect the comma-separated elements of every instance of a header, in order.
+fn header_elements(req: &HttpRequest, name: HeaderName) -> Vec<String> {
+    req.headers()
+        .get_all(name)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|element| element.trim().to_owned())
+        .filter(|element| !element.is_empty())
+        .collect()
+}
+
+/// Parse a node from a forwarding header, such as `192.0.2.1`, `"[2001:db8::1]:4711"` or `192.0.2.1:80`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next().and_then(|ip| ip.parse().ok());
+    }
+
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
@@ -0,0 +1,340 @@
+/*!
+ * Content negotiation module.
+ *
+ * This module lets clients choose the format of request and response bodies. JSON is
+ * always available; compact binary formats for constrained clients, such as embedded
+ * devices, are enabled with cargo features:
+ * - `msgpack`: MessagePack (`application/msgpack`).
+ * - `cbor`: CBOR (`application/cbor`).
+ *
+ * `Content<T>` is both an extractor and a responder. As an extractor it decodes the
+ * request body in the format given by its `Content-Type` header, and as a responder it
+ * encodes the value in the format preferred by the `Accept` header of the request.
+ * Requests without an `Accept` header, or accepting anything, receive JSON. Requests
+ * that only accept unsupported formats also receive JSON, since the response is only
+ * encoded once the handler has run and its effects cannot be undone. Bodies in an
+ * unsupported format are rejected with `415 Unsupported Media Type` before the handler runs.
+ *
+ * Error responses are always sent as JSON problem details, so clients can read them
+ * whatever format they asked for.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{Content, HttpResponse, Method, Routes, StatusCode};
+ * use actix_web::Responder;
+ * use serde::{Deserialize, Serialize};
+ *
+ * #[derive(Deserialize, Serialize)]
+ * struct Reading {
+ *     sensor: String,
+ *     value: f64,
+ * }
+ *
+ * async fn record(reading: Content<Reading>) -> impl Responder {
+ *     // Echo the reading back in whichever format the client accepts
+ *     reading.customize().with_status(StatusCode::CREATED)
+ * }
+ *
+ * let routes = Routes::new().add_method_route(Method::POST, "/readings", record);
+ * ```
+ */
+use actix_web::body::BoxBody;
+use actix_web::dev::Payload;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::web::BytesMut;
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Responder};
+use futures_util::future::LocalBoxFuture;
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ops::{Deref, DerefMut};
+use crate::core::error::ApiError;
+
+/// The largest request body `Content` accepts, in bytes; the same as Actix's JSON default.
+pub const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+/**
+ * A format that request and response bodies can be encoded in.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// JSON, always available.
+    Json,
+    /// MessagePack, with the `msgpack` feature.
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    /// CBOR, with the `cbor` feature.
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Format {
+    /**
+     * The content type of the format.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::content::Format;
+     *
+     * assert_eq!(Format::Json.content_type(), "application/json");
+     * ```
+     */
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => "application/msgpack",
+            #[cfg(feature = "cbor")]
+            Format::Cbor => "application/cbor",
+        }
+    }
+
+    /**
+     * Find the format of a media type, ignoring its parameters.
+     *
+     * # Returns
+     * The format, or `None` if the media type is not supported. Wildcard media ranges are
+     * not media types, and are handled by `Format::negotiate`.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::content::Format;
+     *
+     * assert_eq!(Format::from_media_type("application/json; charset=utf-8"), Some(Format::Json));
+     * assert_eq!(Format::from_media_type("application/vnd.api+json"), Some(Format::Json));
+     * assert_eq!(Format::from_media_type("text/plain"), None);
+     * ```
+     */
+    pub fn from_media_type(media_type: &str) -> Option<Format> {
+        let essence = media_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        match essence.as_str() {
+            "application/json" => Some(Format::Json),
+            _ if essence.starts_with("application/") && essence.ends_with("+json") => Some(Format::Json),
+            #[cfg(feature = "msgpack")]
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => Some(Format::MessagePack),
+            #[cfg(feature = "cbor")]
+            "application/cbor" => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    /**
+     * Choose the response format preferred by the `Accept` header of a request.
+     *
+     * Media types are tried in order of their quality values. A missing header, or a
+     * wildcard media range for any type or any application type, selects JSON.
+     *
+     * # Returns
+     * The format, or `None` if the client accepts none of the supported formats.
+     */
+    pub fn negotiate(req: &HttpRequest) -> Option<Format> {
+        let mut ranges: Vec<(&str, f32)> = req
+            .headers()
+            .get_all(header::ACCEPT)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|element| {
+                let mut parts = element.split(';').map(str::trim);
+                let range = parts.next().filter(|range| !range.is_empty())?;
+                let quality = parts
+                    .find_map(|param| param.strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                Some((range, quality))
+            })
+            .collect();
+
+        if ranges.is_empty() {
+            return Some(Format::Json);
+        }
+        // A stable sort keeps the client's order between ranges of equal quality
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges
+            .into_iter()
+            .filter(|(_, quality)| *quality > 0.0)
+            .find_map(|(range, _)| match range {
+                "*/*" | "application/*" => Some(Format::Json),
+                _ => Format::from_media_type(range),
+            })
+    }
+
+    /**
+     * Encode a value in the format.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::content::Format;
+     *
+     * let bytes = Format::Json.serialize(&vec![1, 2, 3]).unwrap();
+     * assert_eq!(bytes, b"[1,2,3]");
+     * ```
+     */
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /**
+     * Decode a value from the format.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::content::Format;
+     *
+     * let numbers: Vec<u32> = Format::Json.deserialize(b"[1,2,3]").unwrap();
+     * assert_eq!(numbers, vec![1, 2, 3]);
+     * ```
+     */
+    pub fn deserialize<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/**
+ * Build a response whose body is encoded in the format preferred by the request.
+ *
+ * # Arguments
+ * - `req`: The request, whose `Accept` header selects the format.
+ * - `status`: The status of the response.
+ * - `value`: The value to encode.
+ *
+ * # Returns
+ * The response, encoded as JSON if the client accepts none of the supported formats.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{HttpRequest, HttpResponse, StatusCode};
+ * use rusty_api::core::content::respond;
+ * use actix_web::test::TestRequest;
+ * use serde_json::json;
+ *
+ * async fn status(req: HttpRequest) -> HttpResponse {
+ *     respond(&req, StatusCode::OK, &json!({ "uptime": 3600 }))
+ * }
+ *
+ * // A client that only accepts an unsupported format still gets the result, as JSON
+ * let req = TestRequest::default().insert_header(("Accept", "text/csv")).to_http_request();
+ * let res = respond(&req, StatusCode::CREATED, &json!({ "id": 1 }));
+ * assert_eq!(res.status(), StatusCode::CREATED);
+ * assert_eq!(res.headers().get("Content-Type").unwrap(), "application/json");
+ * ```
+ */
+pub fn respond<T: Serialize>(req: &HttpRequest, status: StatusCode, value: &T) -> HttpResponse {
+    // The handler has already run, so its result is sent as JSON rather than thrown away
+    let format = Format::negotiate(req).unwrap_or(Format::Json);
+
+    match format.serialize(value) {
+        Ok(body) => HttpResponse::build(status)
+            .insert_header((header::CONTENT_TYPE, HeaderValue::from_static(format.content_type())))
+            .insert_header((header::VARY, HeaderValue::from_static("accept")))
+            .body(body),
+        Err(e) => {
+            println!("ERROR: Failed to encode response: {}", e);
+            ApiError::internal("Failed to encode response").into()
+        }
+    }
+}
+
+/**
+ * A request or response body in a negotiated format.
+ *
+ * See the module documentation for how the format is chosen.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Content<T>(pub T);
+
+impl<T> Content<T> {
+    /// Unwrap the body.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Content<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Content<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Serialize> Responder for Content<T> {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse {
+        respond(req, StatusCode::OK, &self.0)
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for Content<T> {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let format = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .and_then(Format::from_media_type);
+        let length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|length| length.parse::<usize>().ok());
+        let mut payload = payload.take();
+
+        Box::pin(async move {
+            let format = format.ok_or_else(|| {
+                ApiError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type", "Unsupported request body format")
+            })?;
+            if length.is_some_and(|length| length > MAX_BODY_SIZE) {
+                return Err(payload_too_large());
+            }
+
+            let mut body = BytesMut::new();
+            while let Some(chunk) = payload.next().await {
+                let chunk = chunk.map_err(|e| ApiError::bad_request(e.to_string()).with_code("invalid_body"))?;
+                if body.len() + chunk.len() > MAX_BODY_SIZE {
+                    return Err(payload_too_large());
+                }
+                body.extend_from_slice(&chunk);
+            }
+
+            format
+                .deserialize(&body)
+                .map(Content)
+                .map_err(|e| ApiError::bad_request(e).with_code("invalid_body"))
+        })
+    }
+}
+
+/// The error for a request body larger than `MAX_BODY_SIZE`.
+fn payload_too_large() -> ApiError {
+    ApiError::new(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "payload_too_large",
+        format!("Request body is larger than {} bytes", MAX_BODY_SIZE),
+    )
+}
@@ -5,27 +5,196 @@
  * with the database, including querying and updating user fields.
  */
 use sqlx::{Pool, Sqlite, SqlitePool};
+use sqlx::sqlite::SqliteConnectOptions;
 use std::env;
+use std::str::FromStr;
 use actix_web::HttpResponse;
+use crate::core::auth::{insufficient_privilege, AuthUser};
+use crate::core::error::ApiError;
 use crate::DB_POOL;
 
+/// User columns that can only be changed through dedicated functions, never through `set_user_field`.
+const PROTECTED_FIELDS: &[&str] = &[
+    "id",
+    "username",
+    "password_hash",
+    "privilege",
+    "token_version",
+    "totp_secret",
+    "totp_enabled",
+    "totp_last_step",
+    "totp_failures",
+    "totp_locked_until",
+];
+
+/// User columns that hold credentials, which `get_user_field` never returns.
+const SECRET_FIELDS: &[&str] = &["password_hash", "totp_secret"];
+
 /**
  * Initialize the database connection.
  *
  * This function creates a connection pool to the SQLite database specified in
  * the `DATABASE_URL` environment variable. If the variable is not set, it defaults
- * to `sqlite:./users.db.db`.
+ * to `sqlite:./users.db`. The database file is created if it does not exist, and
+ * the schema is brought up to date with `migrate`.
  *
  * # Returns
  * A `Result` containing the connection pool or an error if the connection fails.
  */
 pub async fn init_db() -> Result<Pool<Sqlite>, sqlx::Error> {
     let db_url = env::var("DATABASE_URL").unwrap_or("sqlite:./users.db".to_string());
-    let pool = SqlitePool::connect(&db_url).await?;
+    let options = SqliteConnectOptions::from_str(&db_url)?.create_if_missing(true);
+    let pool = SqlitePool::connect_with(options).await?;
+    migrate(&pool).await?;
 
     Ok(pool)
 }
 
+/**
+ * Create or update the tables used by rusty-api.
+ *
+ * This function is safe to run on every start. It creates the `users`, `api_keys`,
+ * `rate_limits`, `refresh_tokens`, `revoked_tokens`, `recovery_codes`, `login_challenges`
+ * and `password_reset_tokens` tables if they are missing, and adds columns introduced by
+ * newer versions of the crate to existing databases.
+ *
+ * # Arguments
+ * - `pool`: The SQLite connection pool to migrate.
+ */
+pub async fn migrate(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT NOT NULL UNIQUE,
+            password_hash TEXT NOT NULL,
+            privilege INTEGER NOT NULL DEFAULT 0
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    add_column_if_missing(pool, "users", "privilege", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(pool, "users", "token_version", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(pool, "users", "email", "TEXT").await?;
+    add_column_if_missing(pool, "users", "totp_secret", "TEXT").await?;
+    add_column_if_missing(pool, "users", "totp_enabled", "BOOLEAN NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(pool, "users", "totp_last_step", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(pool, "users", "totp_failures", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(pool, "users", "totp_locked_until", "DATETIME").await?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS api_keys (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            key_hash TEXT NOT NULL UNIQUE,
+            routes TEXT NOT NULL DEFAULT '*',
+            expires_at TEXT,
+            revoked BOOLEAN NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS rate_limits (
+            key TEXT PRIMARY KEY,
+            tat INTEGER NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS refresh_tokens (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            family_id TEXT NOT NULL,
+            token_hash TEXT NOT NULL UNIQUE,
+            expires_at TEXT NOT NULL,
+            used BOOLEAN NOT NULL DEFAULT 0,
+            revoked BOOLEAN NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS refresh_tokens_family ON refresh_tokens (family_id)")
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS revoked_tokens (
+            jti TEXT PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS recovery_codes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            code_hash TEXT NOT NULL,
+            used BOOLEAN NOT NULL DEFAULT 0
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS login_challenges (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            token_hash TEXT NOT NULL UNIQUE,
+            expires_at TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS password_reset_tokens (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            token_hash TEXT NOT NULL UNIQUE,
+            expires_at TEXT NOT NULL,
+            used BOOLEAN NOT NULL DEFAULT 0
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Add a column to an existing table, unless the table already has it.
+async fn add_column_if_missing(pool: &SqlitePool, table: &str, column: &str, definition: &str) -> Result<(), sqlx::Error> {
+    let exists = sqlx::query("SELECT 1 FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+
+    if !exists {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Check that a field name is a plain column identifier, so it can be safely placed in a query.
+fn is_valid_field(field: &str) -> bool {
+    !field.is_empty() && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 
 /**
  * Get a user field from the database.
@@ -40,6 +209,10 @@ pub async fn init_db() -> Result<Pool<Sqlite>, sqlx::Error> {
  * An `HttpResponse` containing the value of the field or an error message if the field is not found.
  */
 pub async fn get_user_field(user_id: i32, field: &str) -> HttpResponse {
+    if !is_valid_field(field) || SECRET_FIELDS.contains(&field) {
+        return ApiError::bad_request(format!("Field '{}' cannot be read", field)).with_code("invalid_field").into();
+    }
+
     let query = format!("SELECT {} FROM users WHERE id = ?", field);
     let result: Option<(String,)> = match sqlx::query_as(&query)
         .bind(user_id)
@@ -47,12 +220,12 @@ pub async fn get_user_field(user_id: i32, field: &str) -> HttpResponse {
         .await
     {
         Ok(result) => result,
-        Err(_) => return HttpResponse::InternalServerError().body("Database error"),
+        Err(e) => return ApiError::from(e).into(),
     };
 
     match result {
         Some((value,)) => HttpResponse::Ok().body(value),
-        None => HttpResponse::NotFound().body(format!("Field '{}' not found for user", field)),
+        None => ApiError::not_found(format!("Field '{}' not found for user", field)).into(),
     }
 }
 
@@ -60,6 +233,8 @@ pub async fn get_user_field(user_id: i32, field: &str) -> HttpResponse {
  * Set a user field in the database.
  *
  * This function updates a specific field in the `users` table for a given user ID.
+ * Identity and security columns, such as `id`, `username`, `password_hash`, `privilege`
+ * and the two-factor columns, cannot be changed this way; use `set_user_privilege` to change privilege levels.
  *
  * # Arguments
  * - `user_id`: The ID of the user to update the field for.
@@ -70,6 +245,10 @@ pub async fn get_user_field(user_id: i32, field: &str) -> HttpResponse {
  * An `HttpResponse` indicating the success or failure of the operation.
  */
 pub async fn set_user_field(user_id: i32, field: &str, value: &str) -> HttpResponse {
+    if !is_valid_field(field) || PROTECTED_FIELDS.contains(&field) {
+        return ApiError::forbidden(format!("Field '{}' cannot be changed", field)).with_code("protected_field").into();
+    }
+
     let query = format!("UPDATE users SET {} = ? WHERE id = ?", field);
     let result = sqlx::query(&query)
         .bind(value)
@@ -81,7 +260,105 @@ pub async fn set_user_field(user_id: i32, field: &str, value: &str) -> HttpRespo
         Ok(rows_affected) if rows_affected.rows_affected() > 0 => {
             HttpResponse::Ok().body(format!("Field '{}' updated successfully", field))
         }
-        Ok(_) => HttpResponse::NotFound().body(format!("User with ID '{}' not found", user_id)),
-        Err(_) => HttpResponse::InternalServerError().body("Database error"),
+        Ok(_) => user_not_found(user_id).into(),
+        Err(e) => ApiError::from(e).into(),
     }
-}
\ No newline at end of file
+}
+
+/**
+ * Set the privilege level of a user.
+ *
+ * This function is intended for trusted server-side code, such as creating the first
+ * administrator. It performs no permission checks; use `change_user_privilege` when
+ * the change is requested by another user. The new level applies to tokens issued
+ * after the change.
+ *
+ * # Arguments
+ * - `user_id`: The ID of the user to update.
+ * - `level`: The new privilege level.
+ *
+ * # Returns
+ * An `HttpResponse` indicating the success or failure of the operation.
+ */
+pub async fn set_user_privilege(user_id: i32, level: i32) -> HttpResponse {
+    let result = sqlx::query("UPDATE users SET privilege = ? WHERE id = ?")
+        .bind(level)
+        .bind(user_id)
+        .execute(&*DB_POOL)
+        .await;
+
+    match result {
+        Ok(rows_affected) if rows_affected.rows_affected() > 0 => {
+            HttpResponse::Ok().body(format!("Privilege of user '{}' set to {}", user_id, level))
+        }
+        Ok(_) => user_not_found(user_id).into(),
+        Err(e) => ApiError::from(e).into(),
+    }
+}
+
+/**
+ * Promote or demote a user on behalf of another user.
+ *
+ * The acting user's current privilege level is read from the database, so a stale
+ * token cannot be used after a demotion. The change is only allowed when:
+ * - the acting user is not changing their own level,
+ * - the target user's current level is below the acting user's level, and
+ * - the new level does not exceed the acting user's level.
+ *
+ * # Arguments
+ * - `actor`: The authenticated user requesting the change.
+ * - `user_id`: The ID of the user to promote or demote.
+ * - `level`: The new privilege level.
+ *
+ * # Returns
+ * An `HttpResponse` indicating the success or failure of the operation.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{web, AuthUser, HttpResponse, Method, Routes, PRIVILEGE_ADMIN};
+ *
+ * async fn promote(actor: AuthUser, path: web::Path<(i32, i32)>) -> HttpResponse {
+ *     let (user_id, level) = path.into_inner();
+ *     rusty_api::change_user_privilege(&actor, user_id, level).await
+ * }
+ *
+ * let routes = Routes::new()
+ *     .add_method_route_with_privilege(Method::POST, "/users/{id}/privilege/{level}", promote, PRIVILEGE_ADMIN);
+ * ```
+ */
+pub async fn change_user_privilege(actor: &AuthUser, user_id: i32, level: i32) -> HttpResponse {
+    if actor.id == user_id {
+        return ApiError::forbidden("Cannot change your own privilege level").with_code("own_privilege").into();
+    }
+
+    let actor_level = match current_privilege(actor.id).await {
+        Ok(Some(level)) => level,
+        Ok(None) => return ApiError::unauthorized("User no longer exists").with_code("user_deleted").into(),
+        Err(e) => return ApiError::from(e).into(),
+    };
+
+    let target_level = match current_privilege(user_id).await {
+        Ok(Some(level)) => level,
+        Ok(None) => return user_not_found(user_id).into(),
+        Err(e) => return ApiError::from(e).into(),
+    };
+
+    if target_level >= actor_level || level > actor_level {
+        return insufficient_privilege().into();
+    }
+
+    set_user_privilege(user_id, level).await
+}
+
+/// The error for a user ID that does not exist.
+fn user_not_found(user_id: i32) -> ApiError {
+    ApiError::not_found(format!("User with ID '{}' not found", user_id)).with_code("user_not_found")
+}
+
+/// Read a user's current privilege level from the database.
+async fn current_privilege(user_id: i32) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query_scalar("SELECT privilege FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(&*DB_POOL)
+        .await
+}
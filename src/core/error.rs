@@ -0,0 +1,280 @@
+/*!
+ * Error module.
+ *
+ * This module defines `ApiError`, the error type behind every error response produced
+ * by the framework: failed protection checks, rate limits, database helpers, the built-in
+ * login and register routes, and request bodies, queries and paths that fail to parse.
+ *
+ * Errors are sent as [RFC 7807](https://www.rfc-editor.org/rfc/rfc7807) problem details
+ * with the `application/problem+json` content type. Besides the standard members, each
+ * body carries a stable, machine-readable `code` and the ID of the request, which is also
+ * sent in the `X-Request-ID` header:
+ *
+ * ```json
+ * {
+ *   "type": "about:blank",
+ *   "title": "Unauthorized",
+ *   "status": 401,
+ *   "detail": "Missing or invalid token",
+ *   "code": "missing_token",
+ *   "request_id": "6f1c0a2e9b7d4c31a8e5f0d2b6c9e4a7"
+ * }
+ * ```
+ *
+ * Handlers can return `ApiError` too, so their errors have the same shape.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{web, ApiError, HttpResponse, Routes};
+ *
+ * async fn get_item(path: web::Path<u32>) -> Result<HttpResponse, ApiError> {
+ *     let id = path.into_inner();
+ *     if id > 100 {
+ *         return Err(ApiError::not_found(format!("Item {} does not exist", id)));
+ *     }
+ *     Ok(HttpResponse::Ok().body(format!("Item {}", id)))
+ * }
+ *
+ * let routes = Routes::new().add_route("/items/{id}", get_item);
+ * ```
+ */
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::borrow::Cow;
+use std::fmt;
+
+/// The content type of problem details.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/**
+ * An error response in the RFC 7807 problem details format.
+ */
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    status: StatusCode,
+    code: Cow<'static, str>,
+    message: String,
+    extensions: Map<String, Value>,
+}
+
+impl ApiError {
+    /**
+     * Create an error with any status code.
+     *
+     * # Arguments
+     * - `status`: The HTTP status of the response.
+     * - `code`: A stable, machine-readable code in `snake_case`, such as `out_of_stock`.
+     * - `message`: A human-readable description of this occurrence of the error.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::ApiError;
+     * use actix_web::http::StatusCode;
+     *
+     * let error = ApiError::new(StatusCode::PAYMENT_REQUIRED, "quota_exceeded", "Monthly quota exceeded");
+     * assert_eq!(error.code(), "quota_exceeded");
+     * ```
+     */
+    pub fn new(status: StatusCode, code: impl Into<Cow<'static, str>>, message: impl Into<String>) -> Self {
+        Self { status, code: code.into(), message: message.into(), extensions: Map::new() }
+    }
+
+    /// A `400 Bad Request` error with the code `bad_request`.
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    /// A `401 Unauthorized` error with the code `unauthorized`.
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "unauthorized", message)
+    }
+
+    /// A `403 Forbidden` error with the code `forbidden`.
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "forbidden", message)
+    }
+
+    /// A `404 Not Found` error with the code `not_found`.
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    /// A `409 Conflict` error with the code `conflict`.
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, "conflict", message)
+    }
+
+    /// A `429 Too Many Requests` error with the code `rate_limited`.
+    pub fn too_many_requests() -> Self {
+        Self::new(StatusCode::TOO_MANY_REQUESTS, "rate_limited", "Too many requests")
+    }
+
+    /**
+     * A `500 Internal Server Error` with the code `internal_error`.
+     *
+     * The message is sent to the client, so it must not contain internal details such as
+     * database errors; log those instead.
+     */
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", message)
+    }
+
+    /**
+     * Replace the machine-readable code of the error.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::ApiError;
+     *
+     * let error = ApiError::conflict("Username is already taken").with_code("username_taken");
+     * assert_eq!(error.code(), "username_taken");
+     * ```
+     */
+    pub fn with_code(mut self, code: impl Into<Cow<'static, str>>) -> Self {
+        self.code = code.into();
+        self
+    }
+
+    /**
+     * Add an extension member to the problem details, such as the fields that failed validation.
+     *
+     * # Arguments
+     * - `name`: The name of the member.
+     * - `value`: The value of the member. It is serialized as JSON.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::ApiError;
+     *
+     * let error = ApiError::conflict("Seat is already booked").with_extension("seat", "14C");
+     * assert_eq!(error.to_problem(None)["seat"], "14C");
+     * ```
+     */
+    pub fn with_extension(mut self, name: &str, value: impl Serialize) -> Self {
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+        self.extensions.insert(name.to_owned(), value);
+        self
+    }
+
+    /// The HTTP status of the error.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The machine-readable code of the error.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// The human-readable message of the error.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /**
+     * Build the problem details body of the error.
+     *
+     * # Arguments
+     * - `request_id`: The ID of the request that failed, if known.
+     */
+    pub fn to_problem(&self, request_id: Option<&str>) -> Value {
+        let mut problem = json!({
+            "type": "about:blank",
+            "title": self.status.canonical_reason().unwrap_or("Error"),
+            "status": self.status.as_u16(),
+            "detail": self.message,
+            "code": self.code,
+            "request_id": request_id,
+        });
+        for (name, value) in &self.extensions {
+            problem[name] = value.clone();
+        }
+        problem
+    }
+
+    /// Build the response for the error, including the given request ID in its body.
+    pub(crate) fn to_response(&self, request_id: Option<&str>) -> HttpResponse {
+        HttpResponse::build(self.status)
+            .insert_header((header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_CONTENT_TYPE)))
+            .body(self.to_problem(request_id).to_string())
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.to_response(None)
+    }
+}
+
+/// Turn the error into a response that still carries it, so the request ID can be added later.
+impl From<ApiError> for HttpResponse {
+    fn from(error: ApiError) -> Self {
+        HttpResponse::from_error(error)
+    }
+}
+
+/**
+ * Map a database error.
+ *
+ * Unique-constraint violations become `409 Conflict` and missing rows `404 Not Found`.
+ * Any other error is logged and becomes `500 Internal Server Error`, without exposing its
+ * details to the client.
+ */
+impl From<sqlx::Error> for ApiError {
+    fn from(error: sqlx::Error) -> Self {
+        match &error {
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                Self::conflict("Resource already exists").with_code("already_exists")
+            }
+            sqlx::Error::RowNotFound => Self::not_found("Resource not found"),
+            _ => {
+                println!("ERROR: Database error: {}", error);
+                Self::internal("Database error").with_code("database_error")
+            }
+        }
+    }
+}
+
+impl From<JsonPayloadError> for ApiError {
+    fn from(error: JsonPayloadError) -> Self {
+        match error {
+            JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+                Self::new(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", error.to_string())
+            }
+            JsonPayloadError::ContentType => Self::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                "Expected a JSON request body",
+            ),
+            _ => Self::bad_request(error.to_string()).with_code("invalid_json"),
+        }
+    }
+}
+
+impl From<QueryPayloadError> for ApiError {
+    fn from(error: QueryPayloadError) -> Self {
+        Self::bad_request(error.to_string()).with_code("invalid_query")
+    }
+}
+
+impl From<PathError> for ApiError {
+    fn from(error: PathError) -> Self {
+        Self::bad_request(error.to_string()).with_code("invalid_path")
+    }
+}
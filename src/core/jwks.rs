@@ -0,0 +1,201 @@
+/*!
+ * JWKS module.
+ *
+ * This module publishes the public keys that verify access tokens as a JSON Web Key Set
+ * ([RFC 7517](https://www.rfc-editor.org/rfc/rfc7517)), so other services can verify
+ * tokens issued by rusty-api without sharing a secret. Every token names the key it was
+ * signed with in its `kid` header, and the set contains every key in `AuthConfig`, so
+ * tokens signed with a retired key stay verifiable while a new key is rolled out.
+ *
+ * Only asymmetric keys (RS256, ES256 and EdDSA) are published; HS256 secrets never are.
+ * Unless set explicitly, the ID of a public key is its JWK thumbprint
+ * ([RFC 7638](https://www.rfc-editor.org/rfc/rfc7638)), so it stays the same across
+ * restarts and servers. HS256 secrets get the fixed ID `hs256-1` instead, since a hash of
+ * a secret would let anyone holding a token test guesses of it.
+ *
+ * # Example
+ * ```rust,no_run
+ * use rusty_api::{Api, AuthConfig};
+ * use rusty_api::core::auth_config::VerificationKey;
+ * use rusty_api::core::jwks::JWKS_PATH;
+ *
+ * // Sign new tokens with the new key, and keep accepting tokens signed with the old one
+ * let auth = AuthConfig::es256("keys/2025-06.pem", "keys/2025-06.pub.pem")
+ *     .expect("Failed to load signing keys")
+ *     .verification_key(VerificationKey::es256("keys/2025-01.pub.pem").expect("Failed to load old key"));
+ *
+ * Api::new()
+ *     .enable_user_db()
+ *     .auth_config(auth)
+ *     .jwks(JWKS_PATH)
+ *     .start();
+ * ```
+ */
+use actix_web::http::header;
+use actix_web::HttpResponse;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::Algorithm;
+use rustls::pki_types::{pem::PemObject, SubjectPublicKeyInfoDer};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::sync::Arc;
+use crate::core::auth_config::AuthConfig;
+use crate::routes::Routes;
+
+/// The conventional path of the key set.
+pub const JWKS_PATH: &str = "/.well-known/jwks.json";
+
+/// How long clients may cache the key set, in seconds. Short, so new keys are picked up soon after a rollout.
+const CACHE_MAX_AGE: u32 = 300;
+
+/// DER tags used in a SubjectPublicKeyInfo structure.
+const DER_SEQUENCE: u8 = 0x30;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_INTEGER: u8 = 0x02;
+
+/**
+ * Build the routes that serve the key set of an `AuthConfig`.
+ *
+ * # Arguments
+ * - `path`: The URL path of the key set, normally `JWKS_PATH`.
+ * - `config`: The configuration whose verification keys are published.
+ */
+pub fn jwks_routes(path: &str, config: &AuthConfig) -> Routes {
+    let jwks = Arc::new(config.jwks().to_string());
+
+    Routes::new()
+        .add_route(path, move || {
+            let jwks = jwks.clone();
+            async move {
+                HttpResponse::Ok()
+                    .content_type("application/jwk-set+json")
+                    .insert_header((header::CACHE_CONTROL, format!("public, max-age={}", CACHE_MAX_AGE)))
+                    .body(jwks.as_str().to_owned())
+            }
+        })
+        .summary("Public keys that verify access tokens")
+}
+
+/**
+ * Build the public JWK of a PEM-encoded public key.
+ *
+ * # Arguments
+ * - `algorithm`: The algorithm the key is used with: RS256, ES256 or EdDSA.
+ * - `pem`: A `PUBLIC KEY` (SubjectPublicKeyInfo) PEM document.
+ *
+ * # Returns
+ * The JWK, with its thumbprint as `kid`, or an error if the key cannot be read.
+ */
+pub(crate) fn public_jwk(algorithm: Algorithm, pem: &[u8]) -> io::Result<Value> {
+    let spki = SubjectPublicKeyInfoDer::from_pem_slice(pem).map_err(|e| {
+        invalid_key(format!("Expected a PEM `PUBLIC KEY` (SubjectPublicKeyInfo): {}", e))
+    })?;
+    let key = subject_public_key(spki.as_ref()).ok_or_else(|| invalid_key("Malformed public key".into()))?;
+
+    let mut jwk = match algorithm {
+        Algorithm::RS256 => {
+            let (n, e) = rsa_components(key).ok_or_else(|| invalid_key("Expected an RSA public key".into()))?;
+            json!({ "kty": "RSA", "n": URL_SAFE_NO_PAD.encode(n), "e": URL_SAFE_NO_PAD.encode(e) })
+        }
+        Algorithm::ES256 => {
+            // An uncompressed P-256 point: 0x04, then the x and y coordinates
+            if key.len() != 65 || key[0] != 0x04 {
+                return Err(invalid_key("Expected an uncompressed P-256 public key".into()));
+            }
+            json!({
+                "kty": "EC",
+                "crv": "P-256",
+                "x": URL_SAFE_NO_PAD.encode(&key[1..33]),
+                "y": URL_SAFE_NO_PAD.encode(&key[33..]),
+            })
+        }
+        Algorithm::EdDSA => {
+            if key.len() != 32 {
+                return Err(invalid_key("Expected an Ed25519 public key".into()));
+            }
+            json!({ "kty": "OKP", "crv": "Ed25519", "x": URL_SAFE_NO_PAD.encode(key) })
+        }
+        _ => return Err(invalid_key(format!("{:?} keys cannot be published", algorithm))),
+    };
+
+    jwk["kid"] = json!(thumbprint(&jwk));
+    jwk["alg"] = json!(format!("{:?}", algorithm));
+    jwk["use"] = json!("sig");
+    Ok(jwk)
+}
+
+/**
+ * Compute the RFC 7638 thumbprint of a JWK: the SHA-256 digest of its required members,
+ * in lexicographic order and without whitespace.
+ */
+fn thumbprint(jwk: &Value) -> String {
+    let member = |name: &str| jwk[name].as_str().unwrap_or_default().to_owned();
+    let canonical = match jwk["kty"].as_str() {
+        Some("RSA") => format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, member("e"), member("n")),
+        Some("EC") => format!(
+            r#"{{"crv":"{}","kty":"EC","x":"{}","y":"{}"}}"#,
+            member("crv"),
+            member("x"),
+            member("y")
+        ),
+        Some("OKP") => format!(r#"{{"crv":"{}","kty":"OKP","x":"{}"}}"#, member("crv"), member("x")),
+        _ => format!(r#"{{"k":"{}","kty":"oct"}}"#, member("k")),
+    };
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+/// The error for a public key that cannot be published.
+fn invalid_key(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Read the key bytes from the `subjectPublicKey` bit string of a SubjectPublicKeyInfo.
+fn subject_public_key(spki: &[u8]) -> Option<&[u8]> {
+    let (spki, _) = read_der(spki, DER_SEQUENCE)?;
+    let (_algorithm, rest) = read_der(spki, DER_SEQUENCE)?;
+    let (bits, _) = read_der(rest, DER_BIT_STRING)?;
+    // The first byte counts the unused bits, which is always zero for keys
+    match bits.split_first() {
+        Some((0, key)) => Some(key),
+        _ => None,
+    }
+}
+
+/// Read the modulus and exponent of a PKCS#1 RSA public key, without leading zeros.
+fn rsa_components(key: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (key, _) = read_der(key, DER_SEQUENCE)?;
+    let (n, rest) = read_der(key, DER_INTEGER)?;
+    let (e, _) = read_der(rest, DER_INTEGER)?;
+    Some((strip_leading_zeros(n), strip_leading_zeros(e)))
+}
+
+/// Remove the sign padding of a DER integer.
+fn strip_leading_zeros(int: &[u8]) -> &[u8] {
+    let start = int.iter().position(|&b| b != 0).unwrap_or(int.len());
+    &int[start..]
+}
+
+/// Read one DER element with the expected tag, returning its contents and the bytes after it.
+fn read_der(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (&found, input) = input.split_first()?;
+    if found != tag {
+        return None;
+    }
+
+    let (&first, mut input) = input.split_first()?;
+    let length = if first < 0x80 {
+        first as usize
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || input.len() < count {
+            return None;
+        }
+        let (bytes, rest) = input.split_at(count);
+        input = rest;
+        bytes.iter().fold(0usize, |length, &b| (length << 8) | b as usize)
+    };
+
+    (input.len() >= length).then(|| input.split_at(length))
+}
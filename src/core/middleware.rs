@@ -0,0 +1,195 @@
+/*!
+ * Middleware module.
+ *
+ * This module lets users add their own middleware to the API, either to every request
+ * with `Api::wrap`, or to individual routes with `Routes::wrap`. A middleware is an async
+ * function that receives the request and a `Next` handle. It can inspect or modify the
+ * request, call `next.call(req)` to continue, and inspect or modify the response, or it
+ * can return a response of its own without calling the rest of the chain. Typical uses
+ * are audit logging, adding custom headers and resolving the tenant of a request.
+ *
+ * # Ordering
+ * A request passes through the layers of the API in this order, after being assigned
+ * its request ID:
+ * 1. The global rate limit set with `Api::rate_limit`.
+ * 2. CORS, as configured with `Api::configure_cors`.
+ * 3. Global middleware added with `Api::wrap`, in the order it was added.
+ * 4. Rate limits of the route's enclosing scopes set with `Routes::rate_limit_all`, outermost
+ *    scope first, then the route's own rate limit set with `Routes::rate_limit`.
+ * 5. Middleware of the enclosing scopes added with `Routes::wrap_all`, outermost scope first,
+ *    then route middleware added with `Routes::wrap`, each in the order it was added.
+ * 6. Protection checks of the enclosing scopes, such as `Routes::require_auth`, outermost
+ *    scope first, then the route's own protection (password, API key, JWT or privilege level).
+ * 7. The handler.
+ *
+ * Responses travel back through the same layers in reverse. Because route middleware
+ * runs before the protection check, it also sees requests that are later rejected with
+ * `401 Unauthorized` or `403 Forbidden`, which is useful for audit logging.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{Api, HttpResponse, Middleware, Routes};
+ * use actix_web::http::header::{HeaderName, HeaderValue};
+ *
+ * async fn health() -> HttpResponse {
+ *     HttpResponse::Ok().body("OK")
+ * }
+ *
+ * let powered_by = Middleware::new(|req, next| async move {
+ *     let mut res = next.call(req).await?;
+ *     res.headers_mut().insert(HeaderName::from_static("x-powered-by"), HeaderValue::from_static("rusty-api"));
+ *     Ok(res)
+ * });
+ *
+ * let audit = Middleware::new(|req, next| async move {
+ *     println!("AUDIT: {} {}", req.method(), req.path());
+ *     next.call(req).await
+ * });
+ *
+ * let api = Api::new()
+ *     .wrap(powered_by)
+ *     .configure_routes(Routes::new().add_route("/health", health).wrap(audit));
+ * ```
+ */
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error;
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use std::future::Future;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The boxed function behind a `Middleware`.
+type MiddlewareFn = dyn Fn(ServiceRequest, Next) -> LocalBoxFuture<'static, Result<ServiceResponse, Error>> + Send + Sync;
+
+/// The rest of the chain after a middleware: the following middleware, and finally the wrapped service.
+type InnerFn = dyn Fn(ServiceRequest) -> LocalBoxFuture<'static, Result<ServiceResponse, Error>>;
+
+/**
+ * A user-defined middleware function.
+ *
+ * Middleware is cheap to clone, so the same middleware can be attached to several routes.
+ */
+#[derive(Clone)]
+pub struct Middleware(Arc<MiddlewareFn>);
+
+impl Middleware {
+    /**
+     * Create a middleware from an async function.
+     *
+     * # Arguments
+     * - `f`: A function that takes the request and a `Next` handle, and returns the response.
+     *   Call `next.call(req)` to pass the request on to the rest of the chain.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{HttpResponse, Middleware};
+     *
+     * // Reject requests without a tenant header before they reach any handler
+     * let require_tenant = Middleware::new(|req, next| async move {
+     *     if req.headers().contains_key("X-Tenant") {
+     *         next.call(req).await
+     *     } else {
+     *         Ok(req.into_response(HttpResponse::BadRequest().body("Missing X-Tenant header")))
+     *     }
+     * });
+     * ```
+     */
+    pub fn new<F, Fut>(f: F) -> Self
+    where
+        F: Fn(ServiceRequest, Next) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<ServiceResponse, Error>> + 'static,
+    {
+        Self(Arc::new(move |req, next| Box::pin(f(req, next))))
+    }
+}
+
+/**
+ * The remainder of the middleware chain, passed to each middleware.
+ */
+pub struct Next {
+    chain: Rc<[Middleware]>,
+    index: usize,
+    inner: Rc<InnerFn>,
+}
+
+impl Next {
+    /**
+     * Pass the request on to the next middleware, or to the route if this is the last one.
+     *
+     * # Returns
+     * The response produced by the rest of the chain.
+     */
+    pub async fn call(self, req: ServiceRequest) -> Result<ServiceResponse, Error> {
+        match self.chain.get(self.index).cloned() {
+            Some(middleware) => {
+                let next = Next { index: self.index + 1, ..self };
+                (middleware.0)(req, next).await
+            }
+            None => (self.inner)(req).await,
+        }
+    }
+}
+
+/**
+ * A transform that runs a list of middleware, in order, before the wrapped service.
+ */
+#[derive(Clone)]
+pub(crate) struct MiddlewareChain {
+    chain: Rc<[Middleware]>,
+}
+
+impl MiddlewareChain {
+    /// Create a chain that runs the given middleware in order.
+    pub(crate) fn new(middleware: &[Middleware]) -> Self {
+        Self { chain: middleware.into() }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for MiddlewareChain
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = MiddlewareChainService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MiddlewareChainService {
+            service: Rc::new(service),
+            chain: self.chain.clone(),
+        }))
+    }
+}
+
+/// The per-worker service created by the `MiddlewareChain` transform.
+pub(crate) struct MiddlewareChainService<S> {
+    service: Rc<S>,
+    chain: Rc<[Middleware]>,
+}
+
+impl<S, B> Service<ServiceRequest> for MiddlewareChainService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let inner: Rc<InnerFn> = Rc::new(move |req| {
+            let service = service.clone();
+            Box::pin(async move { Ok(service.call(req).await?.map_into_boxed_body()) })
+        });
+
+        let next = Next { chain: self.chain.clone(), index: 0, inner };
+        Box::pin(next.call(req))
+    }
+}
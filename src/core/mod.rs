@@ -2,4 +2,25 @@ pub mod config;
 pub mod user;
 pub mod auth;
 pub mod db;
-pub mod auth_routes;
\ No newline at end of file
+pub mod auth_routes;
+pub mod secret;
+pub mod api_key;
+pub mod rate_limit;
+pub mod client_ip;
+pub mod openapi;
+pub mod middleware;
+pub mod static_files;
+pub mod websocket;
+pub mod sse;
+pub mod error;
+pub mod request_id;
+pub mod validation;
+pub mod content;
+pub mod pagination;
+pub mod refresh_token;
+pub mod revocation;
+pub mod auth_config;
+pub mod jwks;
+pub mod two_factor;
+pub mod mailer;
+pub mod password_reset;
\ No newline at end of file
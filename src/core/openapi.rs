@@ -0,0 +1,297 @@
+/*!
+ * OpenAPI module.
+ *
+ * This module generates an OpenAPI 3.1 document from the routes registered with
+ * `Routes`, including their methods, paths, path parameters, protection and any
+ * request or response schemas. It also provides Swagger UI and Redoc pages that
+ * render the document in a browser. The document and pages are served by `Api`
+ * when enabled with `Api::openapi` and `Api::docs_ui`.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{Api, DocsUi, HttpResponse, Routes};
+ *
+ * async fn health() -> HttpResponse {
+ *     HttpResponse::Ok().body("OK")
+ * }
+ *
+ * let routes = Routes::new()
+ *     .add_route("/health", health)
+ *     .summary("Check that the server is running");
+ *
+ * let api = Api::new()
+ *     .configure_routes(routes)
+ *     .openapi_info("Inventory API", "1.2.0")
+ *     .openapi("/openapi.json")
+ *     .docs_ui(DocsUi::SwaggerUi, "/docs");
+ * ```
+ */
+use crate::core::api_key::API_KEY_HEADER;
+use crate::core::error::PROBLEM_CONTENT_TYPE;
+use crate::routes::{Protection, RouteInfo, Routes};
+use actix_web::HttpResponse;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+
+/// The browser interface used to render the OpenAPI document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocsUi {
+    /// Interactive documentation using Swagger UI.
+    SwaggerUi,
+    /// Read-only documentation using Redoc.
+    Redoc,
+}
+
+/**
+ * Generate an OpenAPI 3.1 document describing the given routes.
+ *
+ * # Arguments
+ * - `title`: The title of the API.
+ * - `version`: The version of the API.
+ * - `routes`: The routes to describe.
+ *
+ * # Returns
+ * The OpenAPI document as JSON.
+ */
+pub fn openapi_document<'a>(title: &str, version: &str, routes: impl IntoIterator<Item = &'a RouteInfo>) -> Value {
+    let mut paths = Map::new();
+    for route in routes {
+        let path = openapi_path(&route.path);
+        let item = paths.entry(path).or_insert_with(|| json!({}));
+        item[route.method.as_str().to_lowercase()] = operation(route);
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": { "title": title, "version": version },
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "bearerAuth": { "type": "http", "scheme": "bearer", "bearerFormat": "JWT" },
+                "apiKeyAuth": { "type": "apiKey", "in": "header", "name": API_KEY_HEADER },
+            },
+            "schemas": {
+                "Problem": problem_schema(),
+            }
+        }
+    })
+}
+
+/**
+ * Build the routes that serve the OpenAPI document and, optionally, a documentation page.
+ *
+ * # Arguments
+ * - `spec_path`: The URL path of the JSON document.
+ * - `document`: The OpenAPI document to serve.
+ * - `ui`: Optional documentation page and the URL path to serve it at.
+ */
+pub fn docs_routes(spec_path: &str, document: &Value, ui: Option<(DocsUi, &str)>) -> Routes {
+    let title = document["info"]["title"].as_str().unwrap_or("API").to_owned();
+    let document = Arc::new(document.to_string());
+
+    let mut routes = Routes::new().add_route(spec_path, move || {
+        let document = document.clone();
+        async move {
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .body(document.as_str().to_owned())
+        }
+    });
+
+    if let Some((ui, ui_path)) = ui {
+        let page = Arc::new(docs_page(ui, &title, spec_path));
+        routes = routes.add_route(ui_path, move || {
+            let page = page.clone();
+            async move {
+                HttpResponse::Ok()
+                    .content_type("text/html; charset=utf-8")
+                    .body(page.as_str().to_owned())
+            }
+        });
+    }
+
+    routes
+}
+
+/// Build the HTML page that renders the document at `spec_path` with the chosen interface.
+fn docs_page(ui: DocsUi, title: &str, spec_path: &str) -> String {
+    let title = escape_html(title);
+    let spec_path = escape_html(spec_path);
+    match ui {
+        DocsUi::SwaggerUi => format!(
+            r##"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>{title}</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>window.ui = SwaggerUIBundle({{ url: "{spec_path}", dom_id: "#swagger-ui" }});</script>
+</body>
+</html>"##
+        ),
+        DocsUi::Redoc => format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <redoc spec-url="{spec_path}"></redoc>
+    <script src="https://cdn.redoc.ly/redoc/latest/bundles/redoc.standalone.js"></script>
+</body>
+</html>"#
+        ),
+    }
+}
+
+/// Describe a single route as an OpenAPI operation.
+fn operation(route: &RouteInfo) -> Value {
+    let mut operation = json!({
+        "operationId": operation_id(route),
+        "responses": {
+            "200": response_body("Successful response", route.response_schema.as_ref()),
+            "429": problem_response("Too many requests"),
+        }
+    });
+
+    if let Some(summary) = &route.summary {
+        operation["summary"] = json!(summary);
+    }
+
+    let parameters: Vec<Value> = path_parameters(&route.path)
+        .into_iter()
+        .map(|name| json!({ "name": name, "in": "path", "required": true, "schema": { "type": "string" } }))
+        .collect();
+    if !parameters.is_empty() {
+        operation["parameters"] = json!(parameters);
+    }
+
+    if let Some(schema) = &route.request_schema {
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": schema } }
+        });
+        operation["responses"]["400"] = problem_response("Invalid request body");
+    }
+
+    let (security, errors): (Option<&str>, &[(&str, &str)]) = match route.protection {
+        Protection::Public => (None, &[]),
+        Protection::Password => (Some("apiKeyAuth"), &[("401", "Invalid password")]),
+        Protection::ApiKey => (
+            Some("apiKeyAuth"),
+            &[("401", "Missing or invalid API key"), ("403", "API key is not allowed to call this route")],
+        ),
+        Protection::Bearer => (Some("bearerAuth"), &[("401", "Missing or invalid token")]),
+        Protection::Privilege(level) => {
+            operation["x-privilege-level"] = json!(level);
+            (
+                Some("bearerAuth"),
+                &[("401", "Missing or invalid token"), ("403", "Insufficient privilege")],
+            )
+        }
+    };
+
+    if let Some(scheme) = security {
+        operation["security"] = json!([{ scheme: [] }]);
+    }
+    for (status, description) in errors {
+        operation["responses"][*status] = problem_response(description);
+    }
+
+    operation
+}
+
+/// Describe an error response, whose body is a `Problem`.
+fn problem_response(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { PROBLEM_CONTENT_TYPE: { "schema": { "$ref": "#/components/schemas/Problem" } } }
+    })
+}
+
+/// JSON Schema of the problem details sent by `ApiError`.
+fn problem_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "type": { "type": "string" },
+            "title": { "type": "string" },
+            "status": { "type": "integer" },
+            "detail": { "type": "string" },
+            "code": { "type": "string" },
+            "request_id": { "type": ["string", "null"] }
+        },
+        "required": ["type", "title", "status", "detail", "code"]
+    })
+}
+
+/// Describe a response, with its JSON body schema if known.
+fn response_body(description: &str, schema: Option<&Value>) -> Value {
+    match schema {
+        Some(schema) => json!({
+            "description": description,
+            "content": { "application/json": { "schema": schema } }
+        }),
+        None => json!({ "description": description }),
+    }
+}
+
+/// Build a unique operation ID from the method and path, such as `get_items_id`.
+fn operation_id(route: &RouteInfo) -> String {
+    let path: String = openapi_path(&route.path)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let path = path.split('_').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("_");
+    format!("{}_{}", route.method.as_str().to_lowercase(), path)
+}
+
+/// Convert an Actix path pattern to an OpenAPI path, dropping custom regexes (`{id:\d+}` becomes `{id}`).
+fn openapi_path(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    let mut in_regex = false;
+    let mut depth = 0;
+    for c in path.chars() {
+        match c {
+            '{' => {
+                depth += 1;
+                if !in_regex {
+                    result.push(c);
+                }
+            }
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    in_regex = false;
+                    result.push(c);
+                }
+            }
+            ':' if depth == 1 => in_regex = true,
+            _ if !in_regex => result.push(c),
+            _ => {}
+        }
+    }
+    result
+}
+
+/// List the names of the path parameters in an Actix path pattern.
+fn path_parameters(path: &str) -> Vec<String> {
+    openapi_path(path)
+        .split('{')
+        .skip(1)
+        .filter_map(|segment| segment.split_once('}').map(|(name, _)| name.to_owned()))
+        .collect()
+}
+
+/// Escape text for safe inclusion in HTML.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,606 @@
+/*!
+ * Pagination module.
+ *
+ * This module standardises list endpoints. The `Pagination` extractor reads the paging,
+ * sorting and filtering parameters of a request, `Listing` applies them to a SQLite
+ * query, and the resulting `Page` is sent as an envelope with links to the next and
+ * previous pages.
+ *
+ * # Query parameters
+ * - `limit`: The number of items per page, 20 by default and at most 100.
+ * - `cursor`: An opaque cursor taken from a `next` or `prev` link. Cursor pagination is
+ *   used unless the request has an `offset`, and stays stable while items are added.
+ * - `offset`: The number of items to skip, for clients that need to jump to a page.
+ * - `sort`: Comma-separated columns to sort by, each prefixed with `-` for descending
+ *   order (e.g. `sort=-price,name`).
+ * - `filter[column]=value`: Only include items whose column equals the value. Other
+ *   comparisons are written `filter[column][op]=value`, where `op` is one of `eq`, `ne`,
+ *   `lt`, `lte`, `gt`, `gte` or `contains`.
+ *
+ * Only the columns a `Listing` declares as sortable or filterable can be used, so
+ * parameters never reach the SQL unchecked. Other query parameters are left for the
+ * handler.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{Content, Listing, Page, Pagination, Routes, ApiError, DB_POOL};
+ * use serde::Serialize;
+ *
+ * #[derive(Serialize, sqlx::FromRow)]
+ * struct Product {
+ *     id: i64,
+ *     name: String,
+ *     price: f64,
+ * }
+ *
+ * // GET /products?sort=-price&filter[price][lte]=50&limit=10
+ * async fn list_products(pagination: Pagination) -> Result<Content<Page<Product>>, ApiError> {
+ *     let page = Listing::new("SELECT id, name, price FROM products")
+ *         .sortable(&["name", "price"])
+ *         .filterable(&["name", "price"])
+ *         .fetch(&DB_POOL, &pagination)
+ *         .await?;
+ *     Ok(Content(page))
+ * }
+ *
+ * let routes = Routes::new().add_route("/products", list_products);
+ * ```
+ */
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use futures_util::future::{ready, Ready};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
+use crate::core::error::ApiError;
+
+/// The number of items per page when the request does not set a limit.
+pub const DEFAULT_LIMIT: u32 = 20;
+
+/// The largest number of items a request can ask for in one page.
+pub const MAX_LIMIT: u32 = 100;
+
+/// The most columns a request can sort by.
+const MAX_SORT_KEYS: usize = 5;
+
+/// The query parameters used for paging, which are replaced when building links.
+const PAGING_PARAMS: [&str; 2] = ["cursor", "offset"];
+
+/**
+ * A column to sort by.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    /// The name of the column.
+    pub column: String,
+    /// Whether the column is sorted in descending order.
+    pub descending: bool,
+}
+
+/**
+ * A comparison used to filter items.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    /// The column contains the value as a substring.
+    Contains,
+}
+
+impl FilterOp {
+    /// Parse the name of an operator, as written in `filter[column][op]`.
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "eq" => Some(FilterOp::Eq),
+            "ne" => Some(FilterOp::Ne),
+            "lt" => Some(FilterOp::Lt),
+            "lte" => Some(FilterOp::Lte),
+            "gt" => Some(FilterOp::Gt),
+            "gte" => Some(FilterOp::Gte),
+            "contains" => Some(FilterOp::Contains),
+            _ => None,
+        }
+    }
+
+    /// The SQL operator of the comparison.
+    fn sql(self) -> &'static str {
+        match self {
+            FilterOp::Eq => " = ",
+            FilterOp::Ne => " <> ",
+            FilterOp::Lt => " < ",
+            FilterOp::Lte => " <= ",
+            FilterOp::Gt => " > ",
+            FilterOp::Gte => " >= ",
+            FilterOp::Contains => " LIKE ",
+        }
+    }
+}
+
+/**
+ * A filter on one column.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    /// The name of the column.
+    pub column: String,
+    /// The comparison to apply.
+    pub op: FilterOp,
+    /// The value to compare the column with.
+    pub value: String,
+}
+
+/**
+ * How the requested page is located.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageMode {
+    /// Skip the given number of items.
+    Offset(u64),
+    /// Continue from a cursor, or start from the first page if there is none.
+    Cursor(Option<Cursor>),
+}
+
+/**
+ * The position of a page boundary, as carried by the `cursor` parameter.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    /// The sort-column values of the item at the boundary.
+    #[serde(rename = "v")]
+    values: Vec<Value>,
+    /// Whether the page lies before the boundary, rather than after it.
+    #[serde(rename = "b", default)]
+    backward: bool,
+}
+
+impl Cursor {
+    /// Encode the cursor as an opaque, URL-safe string.
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    /// Decode a cursor from its opaque form.
+    fn decode(cursor: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+/**
+ * Extractor for the paging, sorting and filtering parameters of a list request.
+ *
+ * See the module documentation for the parameters it reads.
+ */
+#[derive(Debug, Clone)]
+pub struct Pagination {
+    /// The number of items per page.
+    pub limit: u32,
+    /// How the requested page is located.
+    pub mode: PageMode,
+    /// The columns to sort by, in order.
+    pub sort: Vec<SortKey>,
+    /// The filters every item must match.
+    pub filters: Vec<Filter>,
+    /// The path of the request, used to build links.
+    path: String,
+    /// The query parameters of the request, used to build links.
+    params: Vec<(String, String)>,
+}
+
+impl Pagination {
+    /**
+     * Parse the pagination parameters of a request.
+     *
+     * # Arguments
+     * - `path`: The path of the request.
+     * - `query`: The query string of the request, without the leading `?`.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Pagination;
+     * use rusty_api::core::pagination::{FilterOp, PageMode};
+     *
+     * let pagination = Pagination::parse("/products", "limit=5&sort=-price&filter[price][lte]=50").unwrap();
+     * assert_eq!(pagination.limit, 5);
+     * assert_eq!(pagination.mode, PageMode::Cursor(None));
+     * assert!(pagination.sort[0].descending);
+     * assert_eq!(pagination.filters[0].op, FilterOp::Lte);
+     *
+     * // Offsets must fit the database's signed integers
+     * assert!(Pagination::parse("/products", "offset=18446744073709551615").is_err());
+     * ```
+     */
+    pub fn parse(path: &str, query: &str) -> Result<Self, ApiError> {
+        let params = web::Query::<Vec<(String, String)>>::from_query(query)
+            .map_err(|e| ApiError::bad_request(e.to_string()).with_code("invalid_query"))?
+            .into_inner();
+
+        let mut pagination = Pagination {
+            limit: DEFAULT_LIMIT,
+            mode: PageMode::Cursor(None),
+            sort: Vec::new(),
+            filters: Vec::new(),
+            path: path.to_owned(),
+            params: Vec::new(),
+        };
+        let mut offset = None;
+        let mut cursor = None;
+
+        for (name, value) in &params {
+            match name.as_str() {
+                "limit" => {
+                    let limit: u32 = value.parse().map_err(|_| invalid_param("limit must be a positive number"))?;
+                    pagination.limit = limit.clamp(1, MAX_LIMIT);
+                }
+                "offset" => {
+                    let value: u64 = value.parse().map_err(|_| invalid_param("offset must be a positive number"))?;
+                    // SQLite takes a signed offset, and treats a negative one as zero
+                    if value > i64::MAX as u64 {
+                        return Err(invalid_param("offset is too large"));
+                    }
+                    offset = Some(value);
+                }
+                "cursor" => {
+                    cursor = Some(Cursor::decode(value).ok_or_else(|| invalid_param("cursor is invalid"))?);
+                }
+                "sort" => pagination.sort = parse_sort(value)?,
+                _ => {
+                    if let Some(filter) = parse_filter(name, value)? {
+                        pagination.filters.push(filter);
+                    }
+                }
+            }
+        }
+
+        pagination.mode = match (offset, cursor) {
+            (Some(_), Some(_)) => return Err(invalid_param("offset and cursor cannot be combined")),
+            (Some(offset), None) => PageMode::Offset(offset),
+            (None, cursor) => PageMode::Cursor(cursor),
+        };
+        pagination.params = params;
+        Ok(pagination)
+    }
+
+    /// Build the link to this list with the paging parameter replaced.
+    fn link(&self, paging: Option<(&str, String)>) -> String {
+        let mut params: Vec<(&str, &str)> = self
+            .params
+            .iter()
+            .filter(|(name, _)| !PAGING_PARAMS.contains(&name.as_str()))
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        if let Some((name, value)) = &paging {
+            params.push((name, value));
+        }
+
+        if params.is_empty() {
+            return self.path.clone();
+        }
+        let query: Vec<String> = params
+            .iter()
+            .map(|(name, value)| format!("{}={}", encode_component(name), encode_component(value)))
+            .collect();
+        format!("{}?{}", self.path, query.join("&"))
+    }
+}
+
+impl FromRequest for Pagination {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Pagination::parse(req.path(), req.query_string()))
+    }
+}
+
+/// The error for a malformed pagination parameter.
+fn invalid_param(message: &str) -> ApiError {
+    ApiError::bad_request(message).with_code("invalid_query")
+}
+
+/// Parse the `sort` parameter, such as `-price,name`.
+fn parse_sort(value: &str) -> Result<Vec<SortKey>, ApiError> {
+    let keys: Vec<SortKey> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| match key.strip_prefix('-') {
+            Some(column) => SortKey { column: column.to_owned(), descending: true },
+            None => SortKey { column: key.trim_start_matches('+').to_owned(), descending: false },
+        })
+        .collect();
+    if keys.len() > MAX_SORT_KEYS {
+        return Err(invalid_param("too many sort columns"));
+    }
+    Ok(keys)
+}
+
+/// Parse a `filter[column]` or `filter[column][op]` parameter, or return `None` for any other parameter.
+fn parse_filter(name: &str, value: &str) -> Result<Option<Filter>, ApiError> {
+    let Some(rest) = name.strip_prefix("filter[") else {
+        return Ok(None);
+    };
+    let (column, rest) = rest.split_once(']').ok_or_else(|| invalid_param("filter parameter is malformed"))?;
+    let op = match rest {
+        "" => FilterOp::Eq,
+        _ => rest
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+            .and_then(FilterOp::parse)
+            .ok_or_else(|| invalid_param("filter operator is not supported"))?,
+    };
+    Ok(Some(Filter { column: column.to_owned(), op, value: value.to_owned() }))
+}
+
+/// Percent-encode a query component.
+fn encode_component(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/**
+ * Links to the current, next and previous pages of a list.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PageLinks {
+    /// The current page.
+    #[serde(rename = "self")]
+    pub current: String,
+    /// The next page, or `None` on the last page.
+    pub next: Option<String>,
+    /// The previous page, or `None` on the first page.
+    pub prev: Option<String>,
+}
+
+/**
+ * One page of a list, sent as `{ "data": [...], "limit": 20, "links": { ... } }`.
+ */
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    /// The items on the page.
+    pub data: Vec<T>,
+    /// The number of items per page.
+    pub limit: u32,
+    /// Links to the current, next and previous pages.
+    pub links: PageLinks,
+}
+
+/**
+ * A paginated SQLite query.
+ *
+ * The base query is run as a subquery, so it can use joins, `WHERE` clauses and aliases
+ * freely; filters and sort columns refer to the columns it returns. Cursor pagination
+ * requires the sort columns to be `NOT NULL`.
+ */
+#[derive(Debug, Clone)]
+pub struct Listing<'a> {
+    base_query: &'a str,
+    sortable: &'a [&'a str],
+    filterable: &'a [&'a str],
+    key: &'a str,
+}
+
+impl<'a> Listing<'a> {
+    /**
+     * Create a listing of the rows returned by a query.
+     *
+     * # Arguments
+     * - `base_query`: A `SELECT` query without `ORDER BY` or `LIMIT`.
+     */
+    pub fn new(base_query: &'a str) -> Self {
+        Self { base_query, sortable: &[], filterable: &[], key: "id" }
+    }
+
+    /// Set the columns that requests may sort by.
+    pub fn sortable(mut self, columns: &'a [&'a str]) -> Self {
+        self.sortable = columns;
+        self
+    }
+
+    /// Set the columns that requests may filter on.
+    pub fn filterable(mut self, columns: &'a [&'a str]) -> Self {
+        self.filterable = columns;
+        self
+    }
+
+    /**
+     * Set the unique column that breaks ties between equal sort values, `id` by default.
+     *
+     * Items are always ordered by this column last, so pages never overlap or skip items.
+     */
+    pub fn key(mut self, column: &'a str) -> Self {
+        self.key = column;
+        self
+    }
+
+    /**
+     * Fetch the page requested by a `Pagination`.
+     *
+     * The item type must serialize its fields under the same names as the sort columns,
+     * which are read back from the last item to build the next cursor.
+     *
+     * # Returns
+     * The page, or `400 Bad Request` if the request sorts or filters by a column that is
+     * not allowed.
+     */
+    pub async fn fetch<T>(&self, pool: &SqlitePool, pagination: &Pagination) -> Result<Page<T>, ApiError>
+    where
+        T: for<'r> FromRow<'r, SqliteRow> + Serialize + Send + Unpin,
+    {
+        let sort = self.sort_keys(pagination)?;
+        for filter in &pagination.filters {
+            if !self.filterable.contains(&filter.column.as_str()) {
+                return Err(ApiError::bad_request(format!("Cannot filter by '{}'", filter.column))
+                    .with_code("invalid_filter"));
+            }
+        }
+
+        let (cursor, offset) = match &pagination.mode {
+            PageMode::Cursor(Some(cursor)) if cursor.values.len() != sort.len() => {
+                return Err(invalid_param("cursor does not match the sort order"));
+            }
+            PageMode::Cursor(cursor) => (cursor.as_ref(), 0),
+            PageMode::Offset(offset) => (None, *offset),
+        };
+        let backward = cursor.is_some_and(|cursor| cursor.backward);
+
+        let mut query = QueryBuilder::<Sqlite>::new("SELECT * FROM (");
+        query.push(self.base_query).push(") AS page WHERE 1 = 1");
+        for filter in &pagination.filters {
+            query.push(" AND ").push(quote(&filter.column)).push(filter.op.sql());
+            match filter.op {
+                FilterOp::Contains => {
+                    let escaped = filter.value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+                    query.push_bind(format!("%{}%", escaped)).push(" ESCAPE '\\'");
+                }
+                _ => {
+                    query.push_bind(filter.value.clone());
+                }
+            }
+        }
+        if let Some(cursor) = cursor {
+            push_cursor_condition(&mut query, &sort, cursor);
+        }
+
+        query.push(" ORDER BY ");
+        for (i, key) in sort.iter().enumerate() {
+            if i > 0 {
+                query.push(", ");
+            }
+            // A backward page is read in reverse order, then flipped back
+            let descending = key.descending != backward;
+            query.push(quote(&key.column)).push(if descending { " DESC" } else { " ASC" });
+        }
+        // One extra row tells whether there is another page
+        query.push(" LIMIT ").push_bind(i64::from(pagination.limit) + 1);
+        query.push(" OFFSET ").push_bind(offset as i64);
+
+        let mut data: Vec<T> = query.build_query_as().fetch_all(pool).await?;
+        let has_more = data.len() > pagination.limit as usize;
+        data.truncate(pagination.limit as usize);
+        if backward {
+            data.reverse();
+        }
+
+        let limit = u64::from(pagination.limit);
+        let (next, prev) = match &pagination.mode {
+            PageMode::Offset(offset) => (
+                has_more.then(|| ("offset", offset.saturating_add(limit).to_string())),
+                (*offset > 0).then(|| ("offset", offset.saturating_sub(limit).to_string())),
+            ),
+            PageMode::Cursor(_) => {
+                let boundary = |item: Option<&T>, backward: bool| -> Result<Option<(&str, String)>, ApiError> {
+                    Ok(match item {
+                        Some(item) => Some(("cursor", Cursor { values: sort_values(item, &sort)?, backward }.encode())),
+                        None => None,
+                    })
+                };
+                let (has_next, has_prev) = if backward { (true, has_more) } else { (has_more, cursor.is_some()) };
+                (
+                    if has_next { boundary(data.last(), false)? } else { None },
+                    if has_prev { boundary(data.first(), true)? } else { None },
+                )
+            }
+        };
+
+        let current = match &pagination.mode {
+            PageMode::Offset(offset) => Some(("offset", offset.to_string())),
+            PageMode::Cursor(cursor) => cursor.as_ref().map(|cursor| ("cursor", cursor.encode())),
+        };
+        Ok(Page {
+            data,
+            limit: pagination.limit,
+            links: PageLinks {
+                current: pagination.link(current),
+                next: next.map(|next| pagination.link(Some(next))),
+                prev: prev.map(|prev| pagination.link(Some(prev))),
+            },
+        })
+    }
+
+    /// Check the requested sort columns, and add the key column as the final tiebreak.
+    fn sort_keys(&self, pagination: &Pagination) -> Result<Vec<SortKey>, ApiError> {
+        let mut sort = pagination.sort.clone();
+        for key in &sort {
+            if key.column != self.key && !self.sortable.contains(&key.column.as_str()) {
+                return Err(ApiError::bad_request(format!("Cannot sort by '{}'", key.column)).with_code("invalid_sort"));
+            }
+        }
+        if !sort.iter().any(|key| key.column == self.key) {
+            sort.push(SortKey { column: self.key.to_owned(), descending: false });
+        }
+        Ok(sort)
+    }
+}
+
+/// Quote a column name that has already been checked against the allowed columns.
+fn quote(column: &str) -> String {
+    format!("\"{}\"", column.replace('"', "\"\""))
+}
+
+/**
+ * Add the condition selecting the items after (or, for a backward cursor, before) a cursor.
+ *
+ * For sort columns `a, b, c`, the items after `(x, y, z)` are those with `a > x`, or
+ * `a = x AND b > y`, or `a = x AND b = y AND c > z`, with `<` for descending columns.
+ */
+fn push_cursor_condition(query: &mut QueryBuilder<'_, Sqlite>, sort: &[SortKey], cursor: &Cursor) {
+    query.push(" AND (");
+    for i in 0..sort.len() {
+        if i > 0 {
+            query.push(" OR ");
+        }
+        query.push("(");
+        for (key, value) in sort.iter().zip(&cursor.values).take(i) {
+            query.push(quote(&key.column)).push(" = ");
+            push_value(query, value);
+            query.push(" AND ");
+        }
+        let after = sort[i].descending == cursor.backward;
+        query.push(quote(&sort[i].column)).push(if after { " > " } else { " < " });
+        push_value(query, &cursor.values[i]);
+        query.push(")");
+    }
+    query.push(")");
+}
+
+/// Bind a JSON value from a cursor with the matching SQLite type.
+fn push_value(query: &mut QueryBuilder<'_, Sqlite>, value: &Value) {
+    match value {
+        Value::Number(n) if n.is_i64() => query.push_bind(n.as_i64()),
+        Value::Number(n) => query.push_bind(n.as_f64()),
+        Value::Bool(b) => query.push_bind(*b),
+        Value::String(s) => query.push_bind(s.clone()),
+        _ => query.push_bind(None::<String>),
+    };
+}
+
+/// Read the values of the sort columns from an item.
+fn sort_values<T: Serialize>(item: &T, sort: &[SortKey]) -> Result<Vec<Value>, ApiError> {
+    let item = serde_json::to_value(item).map_err(|e| {
+        println!("ERROR: Failed to serialize list item: {}", e);
+        ApiError::internal("Failed to build page links")
+    })?;
+    sort.iter()
+        .map(|key| {
+            item.get(&key.column).cloned().ok_or_else(|| {
+                println!("ERROR: Sort column '{}' is not a field of the list items", key.column);
+                ApiError::internal("Failed to build page links")
+            })
+        })
+        .collect()
+}
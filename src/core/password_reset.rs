@@ -0,0 +1,294 @@
+/*!
+ * Password reset module.
+ *
+ * Users who forget their password can ask for a reset link, which is emailed to the
+ * address stored in the `email` column of the `users` table. The link carries a random
+ * token that sets a new password once, and only until it expires. Tokens are stored in
+ * the `password_reset_tokens` table only as a SHA-256 hash, and asking for a new link
+ * invalidates the previous one.
+ *
+ * Setting a new password goes through `change_password`, so every session of the user,
+ * including one an attacker may hold, has to log in again. Two-factor authentication
+ * stays enabled, so a reset link alone does not give access to a protected account.
+ *
+ * # Example
+ * ```rust,no_run
+ * use rusty_api::Api;
+ * use rusty_api::core::mailer::FileMailer;
+ * use rusty_api::core::password_reset::PasswordReset;
+ *
+ * // Reset emails are written to `mail/`, linking to the page that asks for the new password
+ * let reset = PasswordReset::new(FileMailer::new("mail"), "https://example.com/reset?token={token}");
+ *
+ * Api::new()
+ *     .enable_user_db()
+ *     .password_reset(reset)
+ *     .start();
+ * ```
+ */
+use chrono::{DateTime, Utc};
+use sqlx::{Row, SqlitePool};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::core::auth::change_password;
+use crate::core::error::ApiError;
+use crate::core::mailer::{Email, Mailer};
+use crate::core::secret::{constant_time_eq, generate_secret, hash_secret};
+
+/// Prefix added to every reset token, making tokens easy to recognise.
+const TOKEN_PREFIX: &str = "pr_";
+
+/// How long a reset link works by default.
+pub const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// The placeholder in the link template that is replaced by the token.
+const TOKEN_PLACEHOLDER: &str = "{token}";
+
+/**
+ * How password reset links are delivered.
+ */
+#[derive(Clone)]
+pub struct PasswordReset {
+    mailer: Arc<dyn Mailer>,
+    link: String,
+    token_lifetime: Duration,
+}
+
+impl PasswordReset {
+    /**
+     * Configure password resets.
+     *
+     * # Arguments
+     * - `mailer`: How reset emails are sent.
+     * - `link`: The link in the email, in which `{token}` is replaced by the reset token.
+     *   It normally points at a page of your frontend that asks for the new password and
+     *   sends both to the confirm route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::mailer::LogMailer;
+     * use rusty_api::core::password_reset::PasswordReset;
+     *
+     * let reset = PasswordReset::new(LogMailer, "https://example.com/reset?token={token}");
+     * assert_eq!(reset.get_token_lifetime().as_secs(), 3600);
+     * ```
+     */
+    pub fn new(mailer: impl Mailer + 'static, link: &str) -> Self {
+        if !link.contains(TOKEN_PLACEHOLDER) {
+            println!("WARNING: Password reset link '{}' has no {} placeholder", link, TOKEN_PLACEHOLDER);
+        }
+
+        Self {
+            mailer: Arc::new(mailer),
+            link: link.into(),
+            token_lifetime: DEFAULT_TOKEN_LIFETIME,
+        }
+    }
+
+    /**
+     * Set how long a reset link works, one hour by default.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::mailer::LogMailer;
+     * use rusty_api::core::password_reset::PasswordReset;
+     * use std::time::Duration;
+     *
+     * let reset = PasswordReset::new(LogMailer, "https://example.com/reset?token={token}")
+     *     .token_lifetime(Duration::from_secs(15 * 60));
+     * assert_eq!(reset.get_token_lifetime().as_secs(), 900);
+     * ```
+     */
+    pub fn token_lifetime(mut self, lifetime: Duration) -> Self {
+        self.token_lifetime = lifetime;
+        self
+    }
+
+    /// The link template, with its `{token}` placeholder.
+    pub fn get_link(&self) -> &str {
+        &self.link
+    }
+
+    /// How long a reset link works.
+    pub fn get_token_lifetime(&self) -> Duration {
+        self.token_lifetime
+    }
+
+    /// Build the reset email for a user.
+    fn email(&self, to: String, username: &str, token: &str) -> Email {
+        let link = self.link.replace(TOKEN_PLACEHOLDER, token);
+        Email {
+            to,
+            subject: "Reset your password".into(),
+            body: format!(
+                "Someone asked to reset the password of your account {}.\n\n\
+                 To choose a new password, open this link within {} minutes:\n\n{}\n\n\
+                 If you did not ask for this, you can ignore this email; your password has not changed.",
+                username,
+                self.token_lifetime.as_secs() / 60,
+                link
+            ),
+        }
+    }
+}
+
+impl fmt::Debug for PasswordReset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PasswordReset")
+            .field("link", &self.link)
+            .field("token_lifetime", &self.token_lifetime)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A row of the `password_reset_tokens` table.
+#[derive(sqlx::FromRow)]
+struct ResetTokenRow {
+    id: i32,
+    user_id: i32,
+    token_hash: String,
+    expires_at: DateTime<Utc>,
+    used: bool,
+}
+
+/**
+ * Email a reset link to a user.
+ *
+ * Nothing is sent if the user does not exist or has no email address, and the result is
+ * the same, so callers cannot find out which usernames exist. The email is sent in a
+ * background task, so a slow mail server does not make the response take longer for
+ * existing users, and a failure to send it is logged rather than returned.
+ *
+ * Expired tokens of every user are deleted at the same time, so the table does not grow
+ * without bound. This must be called from within the Actix runtime, as route handlers are.
+ *
+ * # Arguments
+ * - `pool`: The SQLite connection pool.
+ * - `reset`: How the email is sent, and how long the link works.
+ * - `username`: The username of the account.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::core::db::migrate;
+ * use rusty_api::core::mailer::FileMailer;
+ * use rusty_api::core::password_reset::{confirm_password_reset, request_password_reset, PasswordReset};
+ * use sqlx::sqlite::SqlitePoolOptions;
+ * use std::time::Duration;
+ *
+ * # actix_web::rt::System::new().block_on(async {
+ * let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+ * migrate(&pool).await.unwrap();
+ * sqlx::query("INSERT INTO users (username, password_hash, email) VALUES ('alice', '-', 'alice@example.com')")
+ *     .execute(&pool)
+ *     .await
+ *     .unwrap();
+ *
+ * let dir = std::env::temp_dir().join(format!("rusty-api-reset-{}", rand::random::<u32>()));
+ * let reset = PasswordReset::new(FileMailer::new(&dir), "https://example.com/reset?token={token}");
+ * request_password_reset(&pool, &reset, "alice").await.unwrap();
+ *
+ * // The email is sent in the background, so wait for it to be written
+ * let email = loop {
+ *     if let Some(file) = std::fs::read_dir(&dir).ok().and_then(|mut files| files.next()) {
+ *         break std::fs::read_to_string(file.unwrap().path()).unwrap();
+ *     }
+ *     actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+ * };
+ * let token = email.split("token=").nth(1).unwrap().split_whitespace().next().unwrap();
+ *
+ * // The link works once
+ * assert!(confirm_password_reset(&pool, token, "correct horse battery staple").await.is_ok());
+ * let err = confirm_password_reset(&pool, token, "another new password").await.unwrap_err();
+ * assert_eq!(err.code(), "invalid_reset_token");
+ * # std::fs::remove_dir_all(&dir).unwrap();
+ * # });
+ * ```
+ */
+pub async fn request_password_reset(pool: &SqlitePool, reset: &PasswordReset, username: &str) -> Result<(), ApiError> {
+    let now = Utc::now();
+    sqlx::query("DELETE FROM password_reset_tokens WHERE expires_at <= ?")
+        .bind(now)
+        .execute(pool)
+        .await?;
+
+    let row = sqlx::query("SELECT id, email FROM users WHERE username = ?")
+        .bind(username)
+        .fetch_optional(pool)
+        .await?;
+    let Some((user_id, email)) = row.and_then(|row| Some((row.get::<i32, _>("id"), row.get::<Option<String>, _>("email")?)))
+    else {
+        println!("INFO: Password reset requested for unknown user or user without email");
+        return Ok(());
+    };
+
+    // Only the newest link works
+    let token = generate_secret(TOKEN_PREFIX);
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES (?, ?, ?)")
+        .bind(user_id)
+        .bind(hash_secret(&token))
+        .bind(now + reset.token_lifetime)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    // Sending takes as long as the mail server does, which would reveal that the user exists
+    let mailer = Arc::clone(&reset.mailer);
+    let email = reset.email(email, username, &token);
+    actix_web::rt::spawn(async move {
+        if let Err(e) = mailer.send(&email).await {
+            println!("ERROR: Failed to send password reset email to user {}: {}", user_id, e);
+        }
+    });
+    Ok(())
+}
+
+/**
+ * Set a new password with the token from a reset link.
+ *
+ * The token is used up, and every access token and refresh token of the user is revoked.
+ *
+ * # Arguments
+ * - `pool`: The SQLite connection pool.
+ * - `token`: The plaintext reset token.
+ * - `new_password`: The new password, already checked against the password policy.
+ *
+ * # Returns
+ * `Ok(())`, or `400 Bad Request` if the token is unknown, expired or already used.
+ */
+pub async fn confirm_password_reset(pool: &SqlitePool, token: &str, new_password: &str) -> Result<(), ApiError> {
+    let invalid = || ApiError::bad_request("Invalid or expired reset token").with_code("invalid_reset_token");
+    let hash = hash_secret(token);
+
+    let row: Option<ResetTokenRow> = sqlx::query_as(
+        "SELECT id, user_id, token_hash, expires_at, used FROM password_reset_tokens WHERE token_hash = ?"
+    )
+    .bind(&hash)
+    .fetch_optional(pool)
+    .await?;
+
+    let row = row
+        .filter(|row| constant_time_eq(row.token_hash.as_bytes(), hash.as_bytes()))
+        .filter(|row| !row.used && row.expires_at > Utc::now())
+        .ok_or_else(invalid)?;
+
+    // Marking the token as used only succeeds once, even for concurrent requests
+    let marked = sqlx::query("UPDATE password_reset_tokens SET used = 1 WHERE id = ? AND used = 0")
+        .bind(row.id)
+        .execute(pool)
+        .await?
+        .rows_affected()
+        == 1;
+    if !marked {
+        return Err(invalid());
+    }
+
+    change_password(pool, row.user_id, new_password).await?;
+    println!("INFO: Password reset for user {}", row.user_id);
+    Ok(())
+}
@@ -0,0 +1,521 @@
+/*!
+ * Rate limiting module.
+ *
+ * This module provides the `RateLimiter` middleware used by rusty-api to limit how
+ * often each client may call the API. The same middleware is used for the global
+ * limit set with `Api::rate_limit`, for per-route limits set with `Routes::rate_limit`,
+ * and for the stricter default limit on the built-in login and register routes.
+ *
+ * Limits follow the generic cell rate algorithm (GCRA), which behaves like a token
+ * bucket: a client may send up to `burst_size` requests at once, after which one more
+ * request is allowed every `replenish_interval`. Clients are identified by their IP address
+ * (resolved through any trusted proxies), or optionally by their user ID or API key.
+ *
+ * When several limits apply to a request, they stack: the global limit is checked first,
+ * then the route's own limit, and the request is only handled if every limit allows it.
+ *
+ * Rate-limit state lives in a `RateLimitStore`. It is kept in memory by default, and can be
+ * shared between server instances through the SQLite database with `SqliteRateLimitStore`.
+ */
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::{web, Error, HttpMessage};
+use futures_util::future::{ready, BoxFuture, LocalBoxFuture, Ready};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::core::api_key::{verify_api_key, API_KEY_HEADER};
+use crate::core::auth::bearer_token;
+use crate::core::auth_config::AuthConfig;
+use crate::core::client_ip::client_ip;
+use crate::core::error::ApiError;
+
+/// Number of tracked clients above which idle entries are pruned from a memory store.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Number of checks between prunes of a SQLite store.
+const SQLITE_PRUNE_INTERVAL: u64 = 1_000;
+
+/// Header carrying the client's burst size.
+const RATELIMIT_LIMIT: &str = "ratelimit-limit";
+
+/// Header carrying the number of requests the client may still send right now.
+const RATELIMIT_REMAINING: &str = "ratelimit-remaining";
+
+/// Header carrying the number of seconds until the client's full allowance is restored.
+const RATELIMIT_RESET: &str = "ratelimit-reset";
+
+/**
+ * A rate limit: how many requests a client may burst, and how quickly that allowance refills.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::RateLimit;
+ * use std::time::Duration;
+ *
+ * let limit = RateLimit::per_minute(10, 5);
+ * assert_eq!(limit.burst_size(), 5);
+ * assert_eq!(limit.replenish_interval(), Duration::from_secs(6));
+ * ```
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    burst_size: u32,
+    replenish_interval: Duration,
+}
+
+impl RateLimit {
+    /**
+     * Create a rate limit that refills at the given number of requests per second.
+     *
+     * # Arguments
+     * - `per_second`: Number of requests allowed per second once the burst is used up.
+     * - `burst_size`: Maximum number of requests a client may send at once.
+     */
+    pub fn per_second(per_second: u64, burst_size: u32) -> Self {
+        Self::with_interval(Duration::from_secs(1), per_second, burst_size)
+    }
+
+    /**
+     * Create a rate limit that refills at the given number of requests per minute.
+     *
+     * # Arguments
+     * - `per_minute`: Number of requests allowed per minute once the burst is used up.
+     * - `burst_size`: Maximum number of requests a client may send at once.
+     */
+    pub fn per_minute(per_minute: u64, burst_size: u32) -> Self {
+        Self::with_interval(Duration::from_secs(60), per_minute, burst_size)
+    }
+
+    /// Maximum number of requests a client may send at once.
+    pub fn burst_size(&self) -> u32 { self.burst_size }
+
+    /// Time it takes for one request of the allowance to be replenished.
+    pub fn replenish_interval(&self) -> Duration { self.replenish_interval }
+
+    /// Spread `count` requests evenly over `period`, guarding against zero values.
+    fn with_interval(period: Duration, count: u64, burst_size: u32) -> Self {
+        let count = u32::try_from(count.max(1)).unwrap_or(u32::MAX);
+        Self {
+            burst_size: burst_size.max(1),
+            replenish_interval: period / count,
+        }
+    }
+}
+
+/**
+ * The outcome of checking a request against a rate limit.
+ *
+ * The fields map directly onto the `RateLimit-Limit`, `RateLimit-Remaining`,
+ * `RateLimit-Reset` and `Retry-After` response headers.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    /// Whether the request is within the limit.
+    pub allowed: bool,
+    /// The client's burst size.
+    pub limit: u32,
+    /// Requests the client may still send right now.
+    pub remaining: u32,
+    /// Time until the client's full allowance is restored.
+    pub reset: Duration,
+    /// For rejected requests, the time until the client may try again.
+    pub retry_after: Option<Duration>,
+}
+
+/**
+ * Storage for rate-limit state.
+ *
+ * A store keeps, for every client key, the time at which that client's allowance will be
+ * fully restored. The default `MemoryRateLimitStore` keeps this in the server's memory, so
+ * each process enforces its own limits. `SqliteRateLimitStore` keeps it in a SQLite
+ * database, so several server instances sharing the database file enforce one combined
+ * limit. Implement this trait to keep the state elsewhere, such as in Redis.
+ */
+pub trait RateLimitStore: Send + Sync {
+    /**
+     * Record a request from the given client and decide whether it is allowed.
+     *
+     * Implementations must perform the check and the update atomically, so concurrent
+     * requests cannot both use the last unit of a client's allowance.
+     *
+     * # Arguments
+     * - `key`: A key identifying the client and the limit being checked.
+     * - `limit`: The limit that applies to this client.
+     */
+    fn check<'a>(&'a self, key: &'a str, limit: RateLimit) -> BoxFuture<'a, Result<RateLimitDecision, StoreError>>;
+}
+
+/// The error type returned by rate-limit stores.
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Rate-limit state kept in the memory of the current process.
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    /// Theoretical arrival time of the next request for each client, in microseconds since the Unix epoch.
+    state: Mutex<HashMap<String, i64>>,
+}
+
+impl MemoryRateLimitStore {
+    /// Create a new, empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RateLimitStore for MemoryRateLimitStore {
+    fn check<'a>(&'a self, key: &'a str, limit: RateLimit) -> BoxFuture<'a, Result<RateLimitDecision, StoreError>> {
+        let now = now_micros();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.len() > PRUNE_THRESHOLD {
+            state.retain(|_, tat| *tat > now);
+        }
+
+        let tat = state.get(key).copied().unwrap_or(now).max(now);
+        let backlog = tat - now;
+        let decision = if backlog > tolerance_micros(limit) {
+            rejected(backlog, limit)
+        } else {
+            let tat = tat + interval_micros(limit);
+            state.insert(key.to_owned(), tat);
+            allowed(tat - now, limit)
+        };
+
+        Box::pin(ready(Ok(decision)))
+    }
+}
+
+/**
+ * Rate-limit state kept in the SQLite database managed by `core::db`.
+ *
+ * Every instance pointing at the same database file shares the same counts, so a
+ * quota is enforced once across all of them. Each check is a single atomic
+ * `INSERT ... ON CONFLICT` statement, and expired entries are pruned periodically.
+ * The `rate_limits` table is created by `core::db::migrate`.
+ *
+ * # Example
+ * ```rust,no_run
+ * use rusty_api::core::rate_limit::SqliteRateLimitStore;
+ * use rusty_api::Api;
+ *
+ * # async fn example() -> Result<(), sqlx::Error> {
+ * let pool = rusty_api::core::db::init_db().await?;
+ * let api = Api::new().rate_limit_store(SqliteRateLimitStore::new(pool));
+ * # Ok(())
+ * # }
+ * ```
+ */
+pub struct SqliteRateLimitStore {
+    pool: SqlitePool,
+    checks: AtomicU64,
+}
+
+impl SqliteRateLimitStore {
+    /// Create a store backed by the given connection pool.
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool, checks: AtomicU64::new(0) }
+    }
+
+    /// Delete entries whose allowance has been fully restored, as they no longer affect any decision.
+    async fn prune(&self, now: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM rate_limits WHERE tat < ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+impl RateLimitStore for SqliteRateLimitStore {
+    fn check<'a>(&'a self, key: &'a str, limit: RateLimit) -> BoxFuture<'a, Result<RateLimitDecision, StoreError>> {
+        Box::pin(async move {
+            let now = now_micros();
+            if self.checks.fetch_add(1, Ordering::Relaxed).is_multiple_of(SQLITE_PRUNE_INTERVAL) {
+                self.prune(now).await?;
+            }
+
+            // The update only happens when the request is within the limit, so no row means rejection
+            let tat: Option<i64> = sqlx::query_scalar(
+                "INSERT INTO rate_limits (key, tat) VALUES (?1, ?2 + ?3)
+                 ON CONFLICT(key) DO UPDATE SET tat = MAX(tat, ?2) + ?3 WHERE MAX(tat, ?2) - ?2 <= ?4
+                 RETURNING tat"
+            )
+            .bind(key)
+            .bind(now)
+            .bind(interval_micros(limit))
+            .bind(tolerance_micros(limit))
+            .fetch_optional(&self.pool)
+            .await?;
+
+            if let Some(tat) = tat {
+                return Ok(allowed(tat - now, limit));
+            }
+
+            let tat: Option<i64> = sqlx::query_scalar("SELECT tat FROM rate_limits WHERE key = ?")
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await?;
+            Ok(rejected(tat.unwrap_or(now).max(now) - now, limit))
+        })
+    }
+}
+
+/// Current time in microseconds since the Unix epoch, the unit used for stored rate-limit state.
+fn now_micros() -> i64 {
+    chrono::Utc::now().timestamp_micros()
+}
+
+/// Time between replenished requests, in microseconds.
+fn interval_micros(limit: RateLimit) -> i64 {
+    (limit.replenish_interval.as_micros() as i64).max(1)
+}
+
+/// How far ahead of now a client's arrival time may be while still allowing a request, in microseconds.
+fn tolerance_micros(limit: RateLimit) -> i64 {
+    interval_micros(limit) * (i64::from(limit.burst_size) - 1)
+}
+
+/// Build the decision for an allowed request, given the client's backlog after the request.
+fn allowed(backlog: i64, limit: RateLimit) -> RateLimitDecision {
+    let window = interval_micros(limit) * i64::from(limit.burst_size);
+    RateLimitDecision {
+        allowed: true,
+        limit: limit.burst_size,
+        remaining: ((window - backlog).max(0) / interval_micros(limit)) as u32,
+        reset: micros_to_duration(backlog),
+        retry_after: None,
+    }
+}
+
+/// Build the decision for a rejected request, given the client's current backlog.
+fn rejected(backlog: i64, limit: RateLimit) -> RateLimitDecision {
+    RateLimitDecision {
+        allowed: false,
+        limit: limit.burst_size,
+        remaining: 0,
+        reset: micros_to_duration(backlog),
+        retry_after: Some(micros_to_duration(backlog - tolerance_micros(limit))),
+    }
+}
+
+/// Convert a possibly negative number of microseconds into a duration, clamping at zero.
+fn micros_to_duration(micros: i64) -> Duration {
+    Duration::from_micros(micros.max(0) as u64)
+}
+
+/**
+ * Middleware that enforces a `RateLimit` for each client.
+ *
+ * Cloning a `RateLimiter` shares its state, so a limiter created once and cloned into
+ * every Actix worker enforces a single limit across the whole server. Requests over the
+ * limit receive `429 Too Many Requests`, and every response carries the standard
+ * `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset` headers, plus
+ * `Retry-After` when the request was rejected.
+ *
+ * By default clients are identified by IP address. A limiter created with
+ * `RateLimiter::by_identity` instead identifies callers by the user ID of a validly
+ * signed Bearer token or by a valid API key, and gives them their own, separate quota.
+ * Callers without valid credentials fall back to the anonymous, per-IP quota.
+ *
+ * Each limiter keeps its state in its own `MemoryRateLimitStore`, unless a shared store
+ * has been registered with `Api::rate_limit_store`, in which case every limiter uses that
+ * store under its own scope.
+ */
+#[derive(Clone)]
+pub struct RateLimiter {
+    /// Name that separates this limiter's keys from other limiters in a shared store.
+    scope: String,
+    limit: RateLimit,
+    /// Quota for callers identified by a token or API key, if identity keying is enabled.
+    authenticated: Option<RateLimit>,
+    /// Store used when no shared store is registered with the application.
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    /// Create a new limiter with its own, empty state, identifying clients by IP address.
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            scope: "global".into(),
+            limit,
+            authenticated: None,
+            store: Arc::new(MemoryRateLimitStore::new()),
+        }
+    }
+
+    /**
+     * Create a new limiter that identifies authenticated callers by user or API key.
+     *
+     * # Arguments
+     * - `anonymous`: The per-IP limit for callers without valid credentials.
+     * - `authenticated`: The limit for each user or API key.
+     */
+    pub fn by_identity(anonymous: RateLimit, authenticated: RateLimit) -> Self {
+        Self {
+            authenticated: Some(authenticated),
+            ..Self::new(anonymous)
+        }
+    }
+
+    /**
+     * Set the scope of this limiter.
+     *
+     * Limiters sharing a store must use different scopes to keep separate counts. Route
+     * limits are scoped to their method and path automatically, each `rate_limit_all` group
+     * gets a scope of its own, and the global limit uses `global`.
+     */
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = scope.into();
+        self
+    }
+
+    /// The limit enforced for anonymous clients.
+    pub fn limit(&self) -> RateLimit { self.limit }
+
+    /// The limit enforced for authenticated callers, if identity keying is enabled.
+    pub fn authenticated_limit(&self) -> Option<RateLimit> { self.authenticated }
+
+    /**
+     * Record a request from the given client against the given limit, using this limiter's own store.
+     *
+     * # Arguments
+     * - `client`: A key identifying the client, such as its IP address.
+     * - `limit`: The limit that applies to this client.
+     *
+     * # Returns
+     * The decision for the request, including the values for the rate-limit headers.
+     */
+    pub async fn check(&self, client: &str, limit: RateLimit) -> Result<RateLimitDecision, StoreError> {
+        self.check_in(self.store.as_ref(), client, limit).await
+    }
+
+    /// Record a request in the given store, under this limiter's scope.
+    async fn check_in(&self, store: &dyn RateLimitStore, client: &str, limit: RateLimit) -> Result<RateLimitDecision, StoreError> {
+        let key = format!("{}|{}", self.scope, client);
+        store.check(&key, limit).await
+    }
+
+    /// Work out the key and limit for a request, resolving its identity if enabled.
+    async fn classify(&self, req: &ServiceRequest) -> (String, RateLimit) {
+        if let Some(authenticated) = self.authenticated
+            && let Some(identity) = identify(req).await
+        {
+            return (identity, authenticated);
+        }
+
+        let ip = client_ip(req.request()).map(|ip| ip.to_string()).unwrap_or_default();
+        (format!("ip:{}", ip), self.limit)
+    }
+}
+
+/// Identify the caller of a request by a validly signed Bearer token or a valid API key.
+async fn identify(req: &ServiceRequest) -> Option<String> {
+    // Only the signature is checked, so no database query runs before the limit applies.
+    // A revoked token still counts against its own user, and the route then rejects it.
+    let token = bearer_token(req.request());
+    if let Some(claims) = token.and_then(|token| AuthConfig::for_request(req.request()).decode(token).ok()) {
+        return Some(format!("user:{}", claims.sub));
+    }
+
+    let secret = req.headers().get(API_KEY_HEADER).and_then(|h| h.to_str().ok())?;
+    let pool = req.app_data::<web::Data<SqlitePool>>()?;
+    let key = verify_api_key(pool, secret).await.ok().flatten()?;
+
+    // The route reuses the verified key instead of looking it up again
+    let id = key.id;
+    req.extensions_mut().insert(key);
+    Some(format!("key:{}", id))
+}
+
+/// Add the rate-limit headers to a response, unless a stricter limit has already set them.
+fn insert_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    let existing = headers
+        .get(RATELIMIT_REMAINING)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.parse::<u32>().ok());
+    if existing.is_some_and(|remaining| remaining <= decision.remaining) {
+        return;
+    }
+
+    headers.insert(HeaderName::from_static(RATELIMIT_LIMIT), HeaderValue::from(decision.limit));
+    headers.insert(HeaderName::from_static(RATELIMIT_REMAINING), HeaderValue::from(decision.remaining));
+    headers.insert(HeaderName::from_static(RATELIMIT_RESET), HeaderValue::from(ceil_secs(decision.reset)));
+    if let Some(retry_after) = decision.retry_after {
+        headers.insert(header::RETRY_AFTER, HeaderValue::from(ceil_secs(retry_after)));
+    }
+}
+
+/// Round a duration up to whole seconds, as used by the rate-limit headers.
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimiterMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterMiddleware {
+            service: Rc::new(service),
+            limiter: self.clone(),
+        }))
+    }
+}
+
+/// The per-worker service created by the `RateLimiter` transform.
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<S>,
+    limiter: RateLimiter,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limiter = self.limiter.clone();
+
+        Box::pin(async move {
+            let (client, limit) = limiter.classify(&req).await;
+            let shared = req.app_data::<web::Data<dyn RateLimitStore>>().cloned();
+            let store = shared.as_ref().map_or(limiter.store.as_ref(), |data| data.get_ref());
+
+            // A failing store must not take the API down with it, so the request is let through
+            let decision = match limiter.check_in(store, &client, limit).await {
+                Ok(decision) => decision,
+                Err(e) => {
+                    println!("ERROR: Rate limit store failed: {}", e);
+                    return Ok(service.call(req).await?.map_into_left_body());
+                }
+            };
+
+            let mut res = if decision.allowed {
+                service.call(req).await?.map_into_left_body()
+            } else {
+                req.error_response(ApiError::too_many_requests()).map_into_right_body()
+            };
+
+            insert_headers(res.headers_mut(), &decision);
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,196 @@
+/*!
+ * Refresh token module.
+ *
+ * Access tokens are short-lived JWTs. To stay logged in, clients exchange a refresh
+ * token for a new access token on the refresh route. Refresh tokens are long random
+ * secrets, stored in the `refresh_tokens` table only as a SHA-256 hash.
+ *
+ * Each refresh token can be used once: using it returns a new refresh token, and the old
+ * one is marked as used. All the tokens descended from one login form a family. If a
+ * used token is presented again, it has probably been stolen, since either the thief or
+ * the real client already holds its successor; the whole family is then revoked, and
+ * the user has to log in again.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::core::db::migrate;
+ * use rusty_api::core::refresh_token::{issue_refresh_token, rotate_refresh_token};
+ * use sqlx::sqlite::SqlitePoolOptions;
+ * use std::time::Duration;
+ *
+ * # actix_web::rt::System::new().block_on(async {
+ * # let pool = &SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+ * # migrate(pool).await.unwrap();
+ * # sqlx::query("INSERT INTO users (id, username, password_hash) VALUES (1, 'alice', '-')").execute(pool).await.unwrap();
+ * let lifetime = Duration::from_secs(30 * 24 * 60 * 60);
+ * let token = issue_refresh_token(pool, 1, lifetime).await?;
+ *
+ * // The first use succeeds and returns the next token of the family
+ * let (user_id, next) = rotate_refresh_token(pool, &token, lifetime).await?.expect("token is valid");
+ * assert_eq!(user_id, 1);
+ *
+ * // Reusing the old token fails, and revokes `next` too
+ * assert!(rotate_refresh_token(pool, &token, lifetime).await?.is_none());
+ * assert!(rotate_refresh_token(pool, &next, lifetime).await?.is_none());
+ * # Ok::<(), sqlx::Error>(())
+ * # }).unwrap();
+ * ```
+ */
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use std::time::Duration;
+use crate::core::secret::{constant_time_eq, generate_secret, hash_secret};
+
+/// Prefix added to every refresh token, making tokens easy to recognise.
+const TOKEN_PREFIX: &str = "rt_";
+
+/// A row of the `refresh_tokens` table.
+#[derive(sqlx::FromRow)]
+struct RefreshTokenRow {
+    id: i32,
+    user_id: i32,
+    family_id: String,
+    token_hash: String,
+    expires_at: DateTime<Utc>,
+    used: bool,
+    revoked: bool,
+}
+
+/**
+ * Issue a refresh token for a user that has just logged in, starting a new family.
+ *
+ * Expired tokens of every user are deleted at the same time, so the table does not grow
+ * without bound.
+ *
+ * # Arguments
+ * - `pool`: The SQLite connection pool.
+ * - `user_id`: The ID of the user.
+ * - `lifetime`: How long the token stays valid.
+ *
+ * # Returns
+ * The plaintext token. Only its hash is stored, so it must be handed to the client immediately.
+ */
+pub async fn issue_refresh_token(pool: &SqlitePool, user_id: i32, lifetime: Duration) -> Result<String, sqlx::Error> {
+    sqlx::query("DELETE FROM refresh_tokens WHERE expires_at <= ?")
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+
+    let family_id = generate_secret("");
+    insert_token(pool, user_id, &family_id, lifetime).await
+}
+
+/**
+ * Use a refresh token, replacing it with the next token of its family.
+ *
+ * # Arguments
+ * - `pool`: The SQLite connection pool.
+ * - `token`: The plaintext refresh token.
+ * - `lifetime`: How long the new token stays valid.
+ *
+ * # Returns
+ * The ID of the user and the new plaintext token, or `None` if the token is unknown,
+ * expired or revoked. If the token was already used, its whole family is revoked and
+ * `None` is returned.
+ */
+pub async fn rotate_refresh_token(
+    pool: &SqlitePool,
+    token: &str,
+    lifetime: Duration,
+) -> Result<Option<(i32, String)>, sqlx::Error> {
+    let hash = hash_secret(token);
+    let mut tx = pool.begin().await?;
+
+    let row: Option<RefreshTokenRow> = sqlx::query_as(
+        "SELECT id, user_id, family_id, token_hash, expires_at, used, revoked FROM refresh_tokens WHERE token_hash = ?"
+    )
+    .bind(&hash)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(row) = row.filter(|row| constant_time_eq(row.token_hash.as_bytes(), hash.as_bytes())) else {
+        return Ok(None);
+    };
+    if row.revoked || row.expires_at <= Utc::now() {
+        return Ok(None);
+    }
+
+    // Marking the token as used only succeeds once, even for concurrent requests
+    let marked = !row.used
+        && sqlx::query("UPDATE refresh_tokens SET used = 1 WHERE id = ? AND used = 0")
+            .bind(row.id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            == 1;
+
+    if !marked {
+        println!("WARNING: Refresh token reused for user {}, revoking its token family", row.user_id);
+        sqlx::query("UPDATE refresh_tokens SET revoked = 1 WHERE family_id = ?")
+            .bind(&row.family_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        return Ok(None);
+    }
+
+    let next = insert_token(&mut *tx, row.user_id, &row.family_id, lifetime).await?;
+    tx.commit().await?;
+    Ok(Some((row.user_id, next)))
+}
+
+/**
+ * Revoke every refresh token of a user, so all their sessions must log in again.
+ *
+ * # Returns
+ * The number of tokens revoked.
+ */
+pub async fn revoke_user_refresh_tokens(pool: &SqlitePool, user_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("UPDATE refresh_tokens SET revoked = 1 WHERE user_id = ? AND revoked = 0")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/**
+ * Revoke a refresh token of a user, together with the rest of its family.
+ *
+ * # Returns
+ * `true` if a family was revoked, or `false` if the token is unknown or belongs to
+ * another user.
+ */
+pub async fn revoke_refresh_token(pool: &SqlitePool, user_id: i32, token: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE refresh_tokens SET revoked = 1
+         WHERE family_id = (SELECT family_id FROM refresh_tokens WHERE token_hash = ? AND user_id = ?)"
+    )
+    .bind(hash_secret(token))
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Insert a new token into a family, and return its plaintext.
+async fn insert_token<'e, E>(executor: E, user_id: i32, family_id: &str, lifetime: Duration) -> Result<String, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    let token = generate_secret(TOKEN_PREFIX);
+    let now = Utc::now();
+    sqlx::query(
+        "INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at, created_at) VALUES (?, ?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(family_id)
+    .bind(hash_secret(&token))
+    .bind(now + lifetime)
+    .bind(now)
+    .execute(executor)
+    .await?;
+
+    Ok(token)
+}
@@ -0,0 +1,105 @@
+/*!
+ * Request ID module.
+ *
+ * Every request handled by `Api` is given an ID, which is sent back in the `X-Request-ID`
+ * header and in the body of error responses, so a failed request reported by a client
+ * can be found in the server logs. If the client or a proxy already sent an
+ * `X-Request-ID` header with a reasonable value, that ID is kept; otherwise a random one
+ * is generated.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{HttpResponse, RequestId, Routes};
+ *
+ * async fn checkout(request_id: RequestId) -> HttpResponse {
+ *     println!("INFO: Checkout started by request {}", request_id.0);
+ *     HttpResponse::Ok().body("Checkout started")
+ * }
+ *
+ * let routes = Routes::new().add_route("/checkout", checkout);
+ * ```
+ */
+use actix_web::body::BoxBody;
+use actix_web::dev::{Payload, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures_util::future::{ready, Ready};
+use std::convert::Infallible;
+use crate::core::error::ApiError;
+use crate::core::middleware::Middleware;
+
+/// The header carrying the request ID.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The longest request ID accepted from a client.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/**
+ * Extractor for the ID of the current request.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl FromRequest for RequestId {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // Outside of `Api` no ID has been assigned, so one is made up for this request
+        let id = req.extensions().get::<RequestId>().cloned();
+        ready(Ok(id.unwrap_or_else(|| RequestId(generate_request_id()))))
+    }
+}
+
+/**
+ * Create the middleware that assigns request IDs.
+ *
+ * It must wrap every other layer, so that responses produced by the rate limiter and
+ * CORS also carry the ID.
+ */
+pub(crate) fn request_id_middleware() -> Middleware {
+    Middleware::new(|req, next| async move {
+        let id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .filter(|id| is_valid_request_id(id))
+            .map(str::to_owned)
+            .unwrap_or_else(generate_request_id);
+        req.extensions_mut().insert(RequestId(id.clone()));
+
+        let res = next.call(req).await?;
+        Ok(stamp_response(res, &id))
+    })
+}
+
+/// Add the request ID to the headers of a response, and to its body if it is an `ApiError`.
+fn stamp_response(res: ServiceResponse, id: &str) -> ServiceResponse {
+    let (req, mut response) = res.into_parts();
+
+    let problem = response
+        .error()
+        .and_then(|e| e.as_error::<ApiError>())
+        .map(|error| error.to_problem(Some(id)).to_string());
+    if let Some(problem) = problem {
+        response = response.set_body(BoxBody::new(problem));
+    }
+
+    // The ID only contains visible ASCII characters, so it is always a valid header value
+    if let Ok(value) = HeaderValue::from_str(id) {
+        response.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    ServiceResponse::new(req, response)
+}
+
+/// Check whether a request ID sent by a client is safe to reuse in headers and logs.
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Generate a random request ID.
+fn generate_request_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
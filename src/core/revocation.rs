@@ -0,0 +1,136 @@
+/*!
+ * Token revocation module.
+ *
+ * Access tokens are JWTs, which stay valid until they expire. This module keeps the
+ * record of tokens that must be rejected earlier, in the user database:
+ * - A single token, such as the one a user logs out with, is revoked by its `jti` claim
+ *   in the `revoked_tokens` table until it would have expired anyway.
+ * - All the tokens of a user, when they log out everywhere or change their password,
+ *   are revoked by increasing the user's token version. Every token carries the version
+ *   it was issued with in its `ver` claim, so older tokens stop matching. The user's
+ *   refresh tokens are revoked at the same time.
+ *
+ * `validate_token` checks this record for every authenticated request.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::Claims;
+ * use rusty_api::core::db::migrate;
+ * use rusty_api::core::revocation::{is_token_revoked, revoke_all_tokens};
+ * use sqlx::sqlite::SqlitePoolOptions;
+ *
+ * # actix_web::rt::System::new().block_on(async {
+ * # let pool = &SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+ * # migrate(pool).await.unwrap();
+ * # sqlx::query("INSERT INTO users (id, username, password_hash) VALUES (42, 'alice', '-')").execute(pool).await.unwrap();
+ * let claims = Claims { sub: 42, jti: "laptop".into(), ver: 0, ..Default::default() };
+ * assert!(!is_token_revoked(pool, &claims).await?);
+ *
+ * // A user reported their laptop stolen: end all their sessions
+ * revoke_all_tokens(pool, 42).await?;
+ * assert!(is_token_revoked(pool, &claims).await?);
+ * # Ok::<(), sqlx::Error>(())
+ * # }).unwrap();
+ * ```
+ */
+use sqlx::SqlitePool;
+use crate::core::auth::Claims;
+use crate::core::refresh_token::revoke_user_refresh_tokens;
+
+/**
+ * Revoke a single access token.
+ *
+ * Revocations of tokens that have already expired are deleted at the same time, since
+ * those tokens are rejected anyway.
+ */
+pub async fn revoke_token(pool: &SqlitePool, claims: &Claims) -> Result<(), sqlx::Error> {
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query("DELETE FROM revoked_tokens WHERE expires_at <= ?")
+        .bind(now)
+        .execute(pool)
+        .await?;
+
+    // Tokens issued before `jti` was introduced cannot be revoked individually
+    if claims.jti.is_empty() {
+        return Ok(());
+    }
+    sqlx::query("INSERT OR IGNORE INTO revoked_tokens (jti, user_id, expires_at) VALUES (?, ?, ?)")
+        .bind(&claims.jti)
+        .bind(claims.sub)
+        .bind(claims.exp as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/**
+ * Revoke every access token and refresh token of a user.
+ *
+ * Tokens issued after this call are not affected, so the user can log in again.
+ */
+pub async fn revoke_all_tokens(pool: &SqlitePool, user_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET token_version = token_version + 1 WHERE id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    revoke_user_refresh_tokens(pool, user_id).await?;
+
+    Ok(())
+}
+
+/**
+ * Check whether an access token has been revoked.
+ *
+ * # Returns
+ * `true` if the token itself was revoked, if all tokens of its user were revoked after
+ * it was issued, or if its user no longer exists.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::Claims;
+ * use rusty_api::core::db::migrate;
+ * use rusty_api::core::revocation::{is_token_revoked, revoke_token};
+ * use sqlx::sqlite::SqlitePoolOptions;
+ *
+ * # actix_web::rt::System::new().block_on(async {
+ * # let pool = &SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+ * # migrate(pool).await.unwrap();
+ * # sqlx::query("INSERT INTO users (id, username, password_hash) VALUES (42, 'alice', '-')").execute(pool).await.unwrap();
+ * let exp = chrono::Utc::now().timestamp() as usize + 900;
+ * let phone = Claims { sub: 42, jti: "phone".into(), exp, ..Default::default() };
+ * let laptop = Claims { sub: 42, jti: "laptop".into(), exp, ..Default::default() };
+ *
+ * // Logging out revokes only the token it was made with
+ * revoke_token(pool, &phone).await?;
+ * assert!(is_token_revoked(pool, &phone).await?);
+ * assert!(!is_token_revoked(pool, &laptop).await?);
+ *
+ * // Tokens of deleted users are rejected too
+ * sqlx::query("DELETE FROM users WHERE id = 42").execute(pool).await?;
+ * assert!(is_token_revoked(pool, &laptop).await?);
+ * # Ok::<(), sqlx::Error>(())
+ * # }).unwrap();
+ * ```
+ */
+pub async fn is_token_revoked(pool: &SqlitePool, claims: &Claims) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM revoked_tokens WHERE jti = ?)
+             OR NOT EXISTS (SELECT 1 FROM users WHERE id = ? AND token_version = ?)"
+    )
+    .bind(&claims.jti)
+    .bind(claims.sub)
+    .bind(claims.ver)
+    .fetch_one(pool)
+    .await
+}
+
+/// Read the current token version of a user, which new tokens carry in their `ver` claim.
+pub(crate) async fn token_version(pool: &SqlitePool, user_id: i32) -> Result<i64, sqlx::Error> {
+    let version = sqlx::query_scalar("SELECT token_version FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(version.unwrap_or_default())
+}
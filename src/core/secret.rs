@@ -0,0 +1,41 @@
+/*!
+ * Secret module.
+ *
+ * This module provides helpers for generating, hashing, and comparing the random
+ * secrets used by rusty-api, such as API keys. Secrets are high-entropy random
+ * values, so a fast SHA-256 digest is sufficient for storing them; user-chosen
+ * passwords are hashed with bcrypt in the `auth` module instead.
+ */
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+/**
+ * Generate a new random secret.
+ *
+ * # Arguments
+ * - `prefix`: A short, human-readable prefix that identifies the kind of secret (e.g. `"rk_"`).
+ *
+ * # Returns
+ * The prefix followed by 32 random bytes encoded as hex.
+ */
+pub fn generate_secret(prefix: &str) -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{}{}", prefix, hex::encode(bytes))
+}
+
+/**
+ * Hash a secret for storage.
+ *
+ * # Returns
+ * The hex-encoded SHA-256 digest of the secret.
+ */
+pub fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Compare two byte strings in constant time, so the comparison does not leak how many bytes matched.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
@@ -0,0 +1,358 @@
+/*!
+ * Server-Sent Events module.
+ *
+ * This module pushes one-way event streams to clients, such as live dashboards, using
+ * Server-Sent Events (SSE). Server code publishes events through a `Broadcaster`, either
+ * to every subscriber or to a single user, and each SSE route registered with
+ * `Routes::add_sse` or `Routes::add_sse_with_auth` streams them to its subscribers.
+ *
+ * Every event is given an increasing ID, and the broadcaster keeps a short history of
+ * recent events. When a browser reconnects after a dropped connection, it sends the ID of
+ * the last event it received in the `Last-Event-ID` header, and the missed events are
+ * replayed before new ones. Idle streams receive a keep-alive comment every 15 seconds,
+ * so proxies do not close them.
+ *
+ * Authenticated SSE routes accept the JWT in the `Authorization` header or, because the
+ * browser `EventSource` API cannot set headers, in a `token` query parameter. The stream
+ * ends when the token expires, and the client must reconnect with a fresh token.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{web, AuthUser, HttpResponse, Method, Routes};
+ * use rusty_api::core::sse::{Broadcaster, Event};
+ *
+ * let broadcaster = Broadcaster::new();
+ *
+ * // Any server code holding a clone of the broadcaster can publish events
+ * let publisher = broadcaster.clone();
+ * let complete_job = move |user: AuthUser| {
+ *     let publisher = publisher.clone();
+ *     async move {
+ *         publisher.publish_to(user.id, Event::new("Your report is ready").event("job-complete"));
+ *         HttpResponse::Ok().body("Job complete")
+ *     }
+ * };
+ *
+ * let routes = Routes::new()
+ *     .add_sse_with_auth("/events", &broadcaster)
+ *     .add_method_route_with_bearer(Method::POST, "/jobs/complete", complete_job);
+ * ```
+ */
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse};
+use futures_util::future::{select, Either};
+use futures_util::stream;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// The interval after which an idle stream receives a keep-alive comment.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The number of recent events kept for replay by default.
+const DEFAULT_HISTORY: usize = 100;
+
+/// The number of events queued for a subscriber before it is considered too slow and dropped.
+const SUBSCRIBER_BUFFER: usize = 64;
+
+/// The comment sent to keep an idle stream open.
+const KEEP_ALIVE: &[u8] = b": keep-alive\n\n";
+
+/**
+ * An event to publish to SSE subscribers.
+ *
+ * The ID of the event is assigned by the `Broadcaster` when it is published.
+ */
+#[derive(Debug, Clone)]
+pub struct Event {
+    id: Option<u64>,
+    event: Option<String>,
+    data: String,
+}
+
+impl Event {
+    /**
+     * Create an event with the given data.
+     *
+     * # Arguments
+     * - `data`: The data of the event. It may span several lines.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::sse::Event;
+     *
+     * let event = Event::new("42 users online");
+     * ```
+     */
+    pub fn new(data: impl Into<String>) -> Self {
+        Self { id: None, event: None, data: data.into() }
+    }
+
+    /**
+     * Create an event whose data is the given value serialized as JSON.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::sse::Event;
+     * use serde_json::json;
+     *
+     * let event = Event::json(&json!({ "cpu": 0.42 })).unwrap().event("metrics");
+     * ```
+     */
+    pub fn json<T: Serialize>(value: &T) -> Result<Self, serde_json::Error> {
+        Ok(Self::new(serde_json::to_string(value)?))
+    }
+
+    /**
+     * Set the event type, which the browser dispatches to listeners added with
+     * `addEventListener(type, ...)` instead of `onmessage`.
+     */
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// The ID assigned by the broadcaster, or `None` if the event has not been published.
+    pub fn id(&self) -> Option<u64> {
+        self.id
+    }
+
+    /// Encode the event in the `text/event-stream` format.
+    fn to_bytes(&self) -> Bytes {
+        let mut encoded = String::new();
+        if let Some(id) = self.id {
+            encoded.push_str(&format!("id: {}\n", id));
+        }
+        // Line breaks end a field, so they must never reach the stream unencoded
+        if let Some(event) = &self.event {
+            encoded.push_str(&format!("event: {}\n", event.replace(['\r', '\n'], "")));
+        }
+        for line in self.data.replace("\r\n", "\n").split(['\r', '\n']) {
+            encoded.push_str(&format!("data: {}\n", line));
+        }
+        encoded.push('\n');
+        Bytes::from(encoded)
+    }
+}
+
+/// A published event and the user it was sent to, or `None` if it was sent to everyone.
+struct Published {
+    user_id: Option<i32>,
+    event: Event,
+}
+
+/// A connected SSE stream.
+struct Subscriber {
+    user_id: Option<i32>,
+    sender: mpsc::Sender<Event>,
+}
+
+/// The shared state of a broadcaster.
+struct BroadcasterState {
+    next_id: u64,
+    history: VecDeque<Published>,
+    history_size: usize,
+    subscribers: Vec<Subscriber>,
+}
+
+/**
+ * Publishes events to the subscribers of SSE routes.
+ *
+ * A broadcaster is cheap to clone, and every clone publishes to the same subscribers.
+ */
+#[derive(Clone)]
+pub struct Broadcaster {
+    state: Arc<Mutex<BroadcasterState>>,
+}
+
+impl Broadcaster {
+    /**
+     * Create a broadcaster that keeps the 100 most recent events for replay.
+     */
+    pub fn new() -> Self {
+        Self::with_history(DEFAULT_HISTORY)
+    }
+
+    /**
+     * Create a broadcaster that keeps the given number of recent events for replay.
+     *
+     * # Arguments
+     * - `history_size`: The number of events kept for clients that reconnect with
+     *   `Last-Event-ID`. Use `0` to disable replay.
+     */
+    pub fn with_history(history_size: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(BroadcasterState {
+                next_id: 1,
+                history: VecDeque::new(),
+                history_size,
+                subscribers: Vec::new(),
+            })),
+        }
+    }
+
+    /**
+     * Publish an event to every subscriber.
+     *
+     * # Returns
+     * The ID assigned to the event.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::sse::{Broadcaster, Event};
+     *
+     * let broadcaster = Broadcaster::new();
+     * let first = broadcaster.publish(Event::new("Maintenance starts in 5 minutes"));
+     * let second = broadcaster.publish(Event::new("Maintenance starts in 1 minute"));
+     * assert!(second > first);
+     * ```
+     */
+    pub fn publish(&self, event: Event) -> u64 {
+        self.send(None, event)
+    }
+
+    /**
+     * Publish an event to the subscribers of authenticated SSE routes logged in as the given user.
+     *
+     * # Returns
+     * The ID assigned to the event.
+     */
+    pub fn publish_to(&self, user_id: i32, event: Event) -> u64 {
+        self.send(Some(user_id), event)
+    }
+
+    /// The number of currently connected streams.
+    pub fn subscriber_count(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+        state.subscribers.len()
+    }
+
+    /// Assign an ID to an event, record it, and queue it for every subscriber that should see it.
+    fn send(&self, user_id: Option<i32>, mut event: Event) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        event.id = Some(id);
+
+        // Subscribers that have disconnected, or fallen too far behind, are dropped; a slow
+        // client reconnects with `Last-Event-ID` and catches up from the history
+        state.subscribers.retain(|subscriber| {
+            !is_visible(user_id, subscriber.user_id) || subscriber.sender.try_send(event.clone()).is_ok()
+        });
+
+        if state.history_size > 0 {
+            if state.history.len() == state.history_size {
+                state.history.pop_front();
+            }
+            state.history.push_back(Published { user_id, event });
+        }
+        id
+    }
+
+    /**
+     * Register a new stream.
+     *
+     * # Arguments
+     * - `user_id`: The logged-in user of the stream, or `None` for a public stream.
+     * - `last_event_id`: The ID of the last event the client received, if it is reconnecting.
+     *
+     * # Returns
+     * The recorded events the client missed, and a receiver for new events.
+     */
+    fn subscribe(&self, user_id: Option<i32>, last_event_id: Option<u64>) -> (Vec<Event>, mpsc::Receiver<Event>) {
+        let mut state = self.state.lock().unwrap();
+        let missed = match last_event_id {
+            Some(last_event_id) => state
+                .history
+                .iter()
+                .filter(|published| published.event.id > Some(last_event_id))
+                .filter(|published| is_visible(published.user_id, user_id))
+                .map(|published| published.event.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+        state.subscribers.push(Subscriber { user_id, sender });
+        (missed, receiver)
+    }
+}
+
+impl Default for Broadcaster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check whether an event sent to `recipient` (or to everyone) should reach a stream of `subscriber`.
+fn is_visible(recipient: Option<i32>, subscriber: Option<i32>) -> bool {
+    recipient.is_none() || recipient == subscriber
+}
+
+/// The state of one SSE stream while it is open.
+struct StreamState {
+    missed: VecDeque<Event>,
+    receiver: mpsc::Receiver<Event>,
+}
+
+/**
+ * Open an SSE stream for a request.
+ *
+ * # Arguments
+ * - `req`: The request, whose `Last-Event-ID` header is used to replay missed events.
+ * - `broadcaster`: The broadcaster to subscribe to.
+ * - `user_id`: The logged-in user of the stream, or `None` for a public stream.
+ * - `expires_in`: How long until the stream ends because the caller's token expires.
+ */
+pub(crate) fn event_stream(
+    req: &HttpRequest,
+    broadcaster: &Broadcaster,
+    user_id: Option<i32>,
+    expires_in: Option<Duration>,
+) -> HttpResponse {
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|id| id.trim().parse().ok());
+
+    let (missed, receiver) = broadcaster.subscribe(user_id, last_event_id);
+    let expires_at = expires_in.map(|expires_in| actix_web::rt::time::Instant::now() + expires_in);
+    let state = StreamState { missed: missed.into(), receiver };
+
+    let events = stream::unfold(state, move |mut state| async move {
+        if let Some(event) = state.missed.pop_front() {
+            return Some((Ok::<_, actix_web::Error>(event.to_bytes()), state));
+        }
+
+        // Wait for the next event, sending a keep-alive comment whenever the stream is idle
+        let expiry = async move {
+            match expires_at {
+                Some(expires_at) => actix_web::rt::time::sleep_until(expires_at).await,
+                None => std::future::pending().await,
+            }
+        };
+        let next = {
+            let received = pin!(state.receiver.recv());
+            let idle = pin!(actix_web::rt::time::sleep(KEEP_ALIVE_INTERVAL));
+            match select(received, select(idle, pin!(expiry))).await {
+                Either::Left((Some(event), _)) => Some(event.to_bytes()),
+                Either::Right((Either::Left(_), _)) => Some(Bytes::from_static(KEEP_ALIVE)),
+                // The broadcaster dropped this stream, or the token expired
+                _ => None,
+            }
+        };
+        next.map(|bytes| (Ok(bytes), state))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(events)
+}
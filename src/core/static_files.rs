@@ -0,0 +1,222 @@
+/*!
+ * Static files module.
+ *
+ * This module serves a directory of static files, such as a built frontend, from the
+ * same server as the API. Files are sent with a content type guessed from their
+ * extension, with `ETag` and `Last-Modified` headers, and conditional requests are
+ * answered with `304 Not Modified`. If the client accepts it, a precompressed sibling
+ * of the requested file (`app.js.br` or `app.js.gz`) is sent instead of the file itself.
+ *
+ * Static files are mounted after every API route, including the built-in login and
+ * register routes and the OpenAPI document, so a file can never shadow an API route.
+ * For single-page applications, `Api::serve_spa` answers any unknown path requested by
+ * a browser with `index.html`, leaving client-side routing to the frontend. API clients,
+ * which do not ask for HTML, still receive `404 Not Found`.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{Api, HttpResponse, Routes};
+ *
+ * async fn status() -> HttpResponse {
+ *     HttpResponse::Ok().body("OK")
+ * }
+ *
+ * let api = Api::new()
+ *     .configure_routes(Routes::new().add_route("/api/status", status))
+ *     .serve_static("/assets", "./public/assets")
+ *     .serve_spa("/", "./frontend/dist");
+ * ```
+ */
+use actix_files::NamedFile;
+use actix_web::http::header::{self, ContentEncoding, HeaderValue};
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::core::error::ApiError;
+
+/// The file served for directories, and as the fallback page of a single-page application.
+const INDEX_FILE: &str = "index.html";
+
+/// Precompressed variants to look for, in order of preference: file extension, encoding.
+const PRECOMPRESSED: [(&str, ContentEncoding); 2] = [("br", ContentEncoding::Brotli), ("gz", ContentEncoding::Gzip)];
+
+/**
+ * A directory of static files mounted at a URL path.
+ */
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    /// The URL path the directory is mounted at (e.g. `/` or `/assets`).
+    pub mount: String,
+    /// The directory on disk to serve files from.
+    pub dir: PathBuf,
+    /// Whether unknown paths requested by a browser are answered with `index.html`.
+    pub spa_fallback: bool,
+}
+
+impl StaticFiles {
+    /**
+     * Create a new static file mount.
+     *
+     * # Arguments
+     * - `mount`: The URL path to mount the directory at.
+     * - `dir`: The directory on disk to serve files from.
+     * - `spa_fallback`: Whether to answer unknown paths requested by a browser with `index.html`.
+     */
+    pub fn new(mount: &str, dir: impl Into<PathBuf>, spa_fallback: bool) -> Self {
+        Self { mount: mount.into(), dir: dir.into(), spa_fallback }
+    }
+
+    /**
+     * Apply the mount to a `ServiceConfig`.
+     *
+     * This must be called after every other route has been configured, so the mount only
+     * answers paths that no API route matches. Other methods than `GET` and `HEAD` get
+     * `405 Method Not Allowed` for paths that resolve to a file, including the single-page
+     * application fallback, and `404 Not Found` for any other path.
+     *
+     * # Example
+     * ```rust
+     * use actix_web::{test, App};
+     * use rusty_api::core::static_files::StaticFiles;
+     *
+     * let dir = std::env::temp_dir().join(format!("rusty-api-static-{}", rand::random::<u32>()));
+     * std::fs::create_dir_all(&dir).unwrap();
+     * std::fs::write(dir.join("app.js"), "console.log('hi');").unwrap();
+     * let files = StaticFiles::new("/", &dir, false);
+     *
+     * # actix_web::rt::System::new().block_on(async {
+     * let app = test::init_service(App::new().configure(|cfg| files.configure(cfg))).await;
+     *
+     * let res = test::call_service(&app, test::TestRequest::get().uri("/app.js").to_request()).await;
+     * assert_eq!(res.status(), 200);
+     *
+     * let res = test::call_service(&app, test::TestRequest::post().uri("/app.js").to_request()).await;
+     * assert_eq!(res.status(), 405);
+     *
+     * let res = test::call_service(&app, test::TestRequest::post().uri("/unknown").to_request()).await;
+     * assert_eq!(res.status(), 404);
+     * # });
+     * # std::fs::remove_dir_all(&dir).unwrap();
+     * ```
+     */
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        let base = self.mount.trim_end_matches('/');
+        let patterns = if base.is_empty() {
+            vec!["/{tail:.*}".to_owned()]
+        } else {
+            vec![base.to_owned(), format!("{}/{{tail:.*}}", base)]
+        };
+
+        let files = Arc::new(self.clone());
+        let serve = {
+            let files = files.clone();
+            move |req: HttpRequest| {
+                let files = files.clone();
+                async move { files.serve(&req) }
+            }
+        };
+        // Only paths the mount would serve have methods to report; anything else is simply missing
+        let other_method = move |req: HttpRequest| {
+            let files = files.clone();
+            async move {
+                match files.resolve(&req) {
+                    Some(_) => crate::routes::method_not_allowed("GET, HEAD"),
+                    None => ApiError::not_found("Not found").into(),
+                }
+            }
+        };
+
+        cfg.service(
+            web::resource(patterns)
+                .route(web::get().to(serve.clone()))
+                .route(web::head().to(serve))
+                .default_service(web::to(other_method)),
+        );
+    }
+
+    /// Find and send the file for a request.
+    fn serve(&self, req: &HttpRequest) -> HttpResponse {
+        match self.resolve(req) {
+            Some(path) => send_file(req, &path),
+            None => ApiError::not_found("Not found").into(),
+        }
+    }
+
+    /// Find the file a request resolves to, falling back to `index.html` for browsers if enabled.
+    fn resolve(&self, req: &HttpRequest) -> Option<PathBuf> {
+        let relative = sanitize(req.match_info().query("tail"))?;
+
+        let mut path = self.dir.join(relative);
+        if path.is_dir() {
+            path.push(INDEX_FILE);
+        }
+        if path.is_file() {
+            return Some(path);
+        }
+
+        let index = self.dir.join(INDEX_FILE);
+        (self.spa_fallback && accepts_html(req) && index.is_file()).then_some(index)
+    }
+}
+
+/// Send a file, or its best precompressed variant that the client accepts.
+fn send_file(req: &HttpRequest, path: &Path) -> HttpResponse {
+    let file = match NamedFile::open(path) {
+        Ok(file) => file,
+        Err(_) => return ApiError::not_found("Not found").into(),
+    };
+
+    // The variant keeps the content type of the original file, and declares its encoding
+    let compressed = PRECOMPRESSED
+        .iter()
+        .filter(|(_, encoding)| accepts_encoding(req, encoding.as_str()))
+        .find_map(|(extension, encoding)| {
+            let mut variant = path.as_os_str().to_owned();
+            variant.push(format!(".{}", extension));
+            NamedFile::open(variant)
+                .ok()
+                .map(|variant| variant.set_content_type(file.content_type().clone()).set_content_encoding(*encoding))
+        });
+
+    let mut res = compressed.unwrap_or(file).disable_content_disposition().into_response(req);
+    res.headers_mut().insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    res
+}
+
+/// Turn the matched tail of a URL into a relative path, refusing anything that could escape the directory.
+fn sanitize(tail: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for segment in tail.split('/') {
+        match segment {
+            "" | "." => continue,
+            // Parent directories, hidden files and Windows separators are never served
+            _ if segment.starts_with('.') || segment.contains(['\\', ':', '\0']) => return None,
+            _ => path.push(segment),
+        }
+    }
+    Some(path)
+}
+
+/// Check whether the client accepts the given content encoding.
+fn accepts_encoding(req: &HttpRequest, encoding: &str) -> bool {
+    header_values(req, header::ACCEPT_ENCODING).any(|value| value.eq_ignore_ascii_case(encoding))
+}
+
+/// Check whether the client asks for an HTML page, as browsers do when navigating.
+fn accepts_html(req: &HttpRequest) -> bool {
+    header_values(req, header::ACCEPT).any(|value| value.eq_ignore_ascii_case("text/html"))
+}
+
+/// List the values of a comma-separated header, without parameters, skipping values refused with `q=0`.
+fn header_values(req: &HttpRequest, name: header::HeaderName) -> impl Iterator<Item = &str> {
+    req.headers()
+        .get_all(name)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            let mut parts = element.split(';').map(str::trim);
+            let value = parts.next()?;
+            let refused = parts.any(|param| matches!(param, "q=0" | "q=0.0" | "q=0.00" | "q=0.000"));
+            (!refused).then_some(value)
+        })
+}
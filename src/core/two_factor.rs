@@ -0,0 +1,585 @@
+/*!
+ * Two-factor authentication module.
+ *
+ * Users can protect their account with time-based one-time passwords (TOTP,
+ * [RFC 6238](https://www.rfc-editor.org/rfc/rfc6238)), as generated by authenticator
+ * apps. Enrolment takes two steps: `begin_enrolment` creates a secret and the
+ * `otpauth://` URI that apps scan as a QR code, and `confirm_enrolment` enables two-factor
+ * authentication once the user proves their app produces valid codes. Confirming returns
+ * one-time recovery codes, for users who lose their authenticator; only their SHA-256
+ * hashes are stored, in the `recovery_codes` table.
+ *
+ * Once enabled, a correct password no longer logs the user in by itself. Login returns
+ * a short-lived challenge token instead, which is exchanged for tokens together with a
+ * TOTP or recovery code. Challenge tokens are stored hashed in the `login_challenges`
+ * table, and allow only a few attempts. Wrong codes are also counted per user, whichever
+ * challenge they come from: after `MAX_FAILED_ATTEMPTS` in a row, every code of the user
+ * is refused for `LOCKOUT_DURATION`, so logging in again for a fresh challenge does not
+ * allow more guesses.
+ *
+ * Unlike passwords and other secrets, the TOTP secret must be readable to check codes,
+ * so it is stored as-is in the `users` table. Each TOTP code is accepted only once.
+ *
+ * # Example
+ * ```rust,no_run
+ * use rusty_api::core::two_factor::{begin_enrolment, confirm_enrolment};
+ *
+ * # async fn example(pool: &sqlx::SqlitePool, code_from_app: &str) -> Result<(), rusty_api::ApiError> {
+ * let enrolment = begin_enrolment(pool, 1, "Example").await?;
+ * println!("Scan {}", enrolment.otpauth_uri);
+ *
+ * // Two-factor authentication is enabled once the user enters a code from their app
+ * let recovery_codes = confirm_enrolment(pool, 1, code_from_app).await?;
+ * println!("Store these somewhere safe: {}", recovery_codes.join(", "));
+ * # Ok(())
+ * # }
+ * ```
+ */
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::Serialize;
+use sha1::Sha1;
+use sqlx::{Row, SqlitePool};
+use std::time::Duration;
+use crate::core::error::ApiError;
+use crate::core::secret::{constant_time_eq, generate_secret, hash_secret};
+
+/// Length of a TOTP time step.
+const TOTP_STEP: u64 = 30;
+
+/// Number of digits in a TOTP code.
+const TOTP_DIGITS: usize = 6;
+
+/// Length of a TOTP secret, in bytes; the size of a SHA-1 digest, as RFC 4226 recommends.
+const TOTP_SECRET_BYTES: usize = 20;
+
+/// Number of recovery codes issued when two-factor authentication is enabled.
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Prefix added to every challenge token, making tokens easy to recognise.
+const CHALLENGE_PREFIX: &str = "ch_";
+
+/// How long a user has to enter their code after their password.
+pub const CHALLENGE_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// Number of wrong codes after which a challenge token stops working.
+const CHALLENGE_MAX_ATTEMPTS: i64 = 5;
+
+/// Number of wrong codes in a row, across all challenges, after which a user is locked out.
+pub const MAX_FAILED_ATTEMPTS: i64 = 10;
+
+/// How long every code of a user is refused after too many wrong codes.
+pub const LOCKOUT_DURATION: Duration = Duration::from_secs(15 * 60);
+
+/**
+ * A pending TOTP enrolment, returned by `begin_enrolment`.
+ */
+#[derive(Debug, Clone, Serialize)]
+pub struct Enrolment {
+    /// The base32-encoded secret, for users who type it into their app.
+    pub secret: String,
+    /// The `otpauth://totp/...` URI, usually shown as a QR code.
+    pub otpauth_uri: String,
+}
+
+/// A row of the `login_challenges` table.
+#[derive(sqlx::FromRow)]
+struct ChallengeRow {
+    id: i32,
+    user_id: i32,
+    token_hash: String,
+    expires_at: DateTime<Utc>,
+    attempts: i64,
+}
+
+/**
+ * Start enrolling a user in two-factor authentication.
+ *
+ * A new secret replaces any earlier enrolment that was not confirmed. Two-factor
+ * authentication stays disabled until the enrolment is confirmed with `confirm_enrolment`.
+ *
+ * # Arguments
+ * - `pool`: The SQLite connection pool.
+ * - `user_id`: The ID of the user.
+ * - `issuer`: The name of the service, shown next to the account in authenticator apps.
+ *
+ * # Returns
+ * The secret and its `otpauth://` URI, `404 Not Found` if the user does not exist, or
+ * `409 Conflict` if two-factor authentication is already enabled.
+ */
+pub async fn begin_enrolment(pool: &SqlitePool, user_id: i32, issuer: &str) -> Result<Enrolment, ApiError> {
+    let row = sqlx::query("SELECT username, totp_enabled FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| user_not_found(user_id))?;
+    if row.get::<bool, _>("totp_enabled") {
+        return Err(already_enabled());
+    }
+
+    let mut secret = [0u8; TOTP_SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut secret);
+    sqlx::query("UPDATE users SET totp_secret = ? WHERE id = ?")
+        .bind(hex::encode(secret))
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    let secret = base32(&secret);
+    let otpauth_uri = otpauth_uri(issuer, row.get("username"), &secret);
+    Ok(Enrolment { secret, otpauth_uri })
+}
+
+/**
+ * Enable two-factor authentication, once the user has entered a code from their app.
+ *
+ * # Arguments
+ * - `pool`: The SQLite connection pool.
+ * - `user_id`: The ID of the user.
+ * - `code`: The current TOTP code.
+ *
+ * # Returns
+ * The plaintext recovery codes, which must be shown to the user immediately since only
+ * their hashes are stored. Fails with `409 Conflict` if there is no pending enrolment,
+ * or `400 Bad Request` if the code is wrong.
+ */
+pub async fn confirm_enrolment(pool: &SqlitePool, user_id: i32, code: &str) -> Result<Vec<String>, ApiError> {
+    let row = sqlx::query("SELECT totp_secret, totp_enabled FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| user_not_found(user_id))?;
+    if row.get::<bool, _>("totp_enabled") {
+        return Err(already_enabled());
+    }
+    let secret = row
+        .get::<Option<String>, _>("totp_secret")
+        .and_then(|secret| hex::decode(secret).ok())
+        .ok_or_else(|| ApiError::conflict("Two-factor enrolment has not been started").with_code("two_factor_not_enrolled"))?;
+
+    let step = verify_totp(&secret, code, current_step()).ok_or_else(invalid_code)?;
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE users SET totp_enabled = 1, totp_last_step = ? WHERE id = ?")
+        .bind(step as i64)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let code = recovery_code();
+        sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) VALUES (?, ?)")
+            .bind(user_id)
+            .bind(hash_secret(&normalize_recovery_code(&code)))
+            .execute(&mut *tx)
+            .await?;
+        codes.push(code);
+    }
+    tx.commit().await?;
+
+    println!("INFO: Two-factor authentication enabled for user {}", user_id);
+    Ok(codes)
+}
+
+/**
+ * Disable two-factor authentication, deleting the secret and recovery codes.
+ *
+ * # Arguments
+ * - `pool`: The SQLite connection pool.
+ * - `user_id`: The ID of the user.
+ * - `code`: A current TOTP code or an unused recovery code, proving the request comes
+ *   from the owner of the second factor.
+ *
+ * # Returns
+ * `Ok(())`, `409 Conflict` if two-factor authentication is not enabled, or
+ * `400 Bad Request` if the code is wrong.
+ */
+pub async fn disable_two_factor(pool: &SqlitePool, user_id: i32, code: &str) -> Result<(), ApiError> {
+    if !is_two_factor_enabled(pool, user_id).await? {
+        return Err(ApiError::conflict("Two-factor authentication is not enabled").with_code("two_factor_disabled"));
+    }
+    if !verify_second_factor(pool, user_id, code).await? {
+        return Err(invalid_code());
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE users SET totp_secret = NULL, totp_enabled = 0, totp_last_step = 0 WHERE id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    println!("INFO: Two-factor authentication disabled for user {}", user_id);
+    Ok(())
+}
+
+/// Check whether a user has confirmed a two-factor enrolment.
+pub async fn is_two_factor_enabled(pool: &SqlitePool, user_id: i32) -> Result<bool, sqlx::Error> {
+    let enabled: Option<bool> = sqlx::query_scalar("SELECT totp_enabled FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(enabled.unwrap_or(false))
+}
+
+/**
+ * Check a second factor: a TOTP code, or a recovery code.
+ *
+ * A TOTP code is accepted within one time step of the server clock, and only once. A
+ * recovery code is used up when accepted. After `MAX_FAILED_ATTEMPTS` wrong codes in a
+ * row, the user is locked out for `LOCKOUT_DURATION`, during which every code is refused.
+ *
+ * # Returns
+ * `true` if the code is valid.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::core::db::migrate;
+ * use rusty_api::core::two_factor::{totp, verify_second_factor, MAX_FAILED_ATTEMPTS};
+ * use sqlx::sqlite::SqlitePoolOptions;
+ *
+ * # actix_web::rt::System::new().block_on(async {
+ * # let pool = &SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+ * # migrate(pool).await.unwrap();
+ * let secret = b"12345678901234567890";
+ * sqlx::query("INSERT INTO users (id, username, password_hash, totp_secret, totp_enabled) VALUES (1, 'alice', '-', ?, 1)")
+ *     .bind(hex::encode(secret))
+ *     .execute(pool)
+ *     .await?;
+ *
+ * let step = chrono::Utc::now().timestamp() as u64 / 30;
+ * let code = totp(secret, step);
+ * assert!(verify_second_factor(pool, 1, &code).await?);
+ *
+ * // A code cannot be replayed
+ * assert!(!verify_second_factor(pool, 1, &code).await?);
+ *
+ * // After too many wrong codes, even the next valid code is refused
+ * for _ in 0..MAX_FAILED_ATTEMPTS {
+ *     assert!(!verify_second_factor(pool, 1, "not-a-code").await?);
+ * }
+ * assert!(!verify_second_factor(pool, 1, &totp(secret, step + 1)).await?);
+ * # Ok::<(), sqlx::Error>(())
+ * # }).unwrap();
+ * ```
+ */
+pub async fn verify_second_factor(pool: &SqlitePool, user_id: i32, code: &str) -> Result<bool, sqlx::Error> {
+    let now = Utc::now();
+
+    // Counting the attempt before checking the code limits guesses, even for concurrent requests
+    let counted = sqlx::query(
+        "UPDATE users SET totp_failures = totp_failures + 1
+         WHERE id = ? AND totp_failures < ? AND (totp_locked_until IS NULL OR totp_locked_until <= ?)
+         RETURNING totp_failures"
+    )
+    .bind(user_id)
+    .bind(MAX_FAILED_ATTEMPTS)
+    .bind(now)
+    .fetch_optional(pool)
+    .await?;
+    let Some(failures) = counted.map(|row| row.get::<i64, _>("totp_failures")) else {
+        return Ok(false);
+    };
+
+    if check_second_factor(pool, user_id, code).await? {
+        sqlx::query("UPDATE users SET totp_failures = 0 WHERE id = ?")
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        return Ok(true);
+    }
+
+    if failures >= MAX_FAILED_ATTEMPTS {
+        println!("WARNING: Too many wrong two-factor codes for user {}, locking them out", user_id);
+        sqlx::query("UPDATE users SET totp_failures = 0, totp_locked_until = ? WHERE id = ?")
+            .bind(now + LOCKOUT_DURATION)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+    }
+    Ok(false)
+}
+
+/// Check a TOTP or recovery code, without counting the attempt.
+async fn check_second_factor(pool: &SqlitePool, user_id: i32, code: &str) -> Result<bool, sqlx::Error> {
+    let code = code.trim();
+
+    if code.len() == TOTP_DIGITS {
+        let secret: Option<Option<String>> =
+            sqlx::query_scalar("SELECT totp_secret FROM users WHERE id = ? AND totp_enabled = 1")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?;
+        let Some(secret) = secret.flatten().and_then(|secret| hex::decode(secret).ok()) else {
+            return Ok(false);
+        };
+        let Some(step) = verify_totp(&secret, code, current_step()) else {
+            return Ok(false);
+        };
+
+        // Moving the last step forward only succeeds once, so a code cannot be replayed
+        let result = sqlx::query("UPDATE users SET totp_last_step = ? WHERE id = ? AND totp_last_step < ?")
+            .bind(step as i64)
+            .bind(user_id)
+            .bind(step as i64)
+            .execute(pool)
+            .await?;
+        return Ok(result.rows_affected() == 1);
+    }
+
+    let result = sqlx::query("UPDATE recovery_codes SET used = 1 WHERE user_id = ? AND code_hash = ? AND used = 0")
+        .bind(user_id)
+        .bind(hash_secret(&normalize_recovery_code(code)))
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 1 {
+        println!("INFO: Recovery code used for user {}", user_id);
+    }
+    Ok(result.rows_affected() == 1)
+}
+
+/**
+ * Issue a challenge token for a user who has entered the right password.
+ *
+ * Expired challenges of every user are deleted at the same time, so the table does not
+ * grow without bound.
+ *
+ * # Returns
+ * The plaintext challenge token, valid for `CHALLENGE_LIFETIME`.
+ */
+pub async fn create_challenge(pool: &SqlitePool, user_id: i32) -> Result<String, sqlx::Error> {
+    let now = Utc::now();
+    sqlx::query("DELETE FROM login_challenges WHERE expires_at <= ?")
+        .bind(now)
+        .execute(pool)
+        .await?;
+
+    let token = generate_secret(CHALLENGE_PREFIX);
+    sqlx::query("INSERT INTO login_challenges (user_id, token_hash, expires_at) VALUES (?, ?, ?)")
+        .bind(user_id)
+        .bind(hash_secret(&token))
+        .bind(now + CHALLENGE_LIFETIME)
+        .execute(pool)
+        .await?;
+
+    Ok(token)
+}
+
+/**
+ * Complete a challenge with a second factor.
+ *
+ * The challenge is deleted once the code is accepted, or after too many wrong codes.
+ *
+ * # Arguments
+ * - `pool`: The SQLite connection pool.
+ * - `token`: The plaintext challenge token.
+ * - `code`: A TOTP code or recovery code.
+ *
+ * # Returns
+ * The ID of the user, or `None` if the challenge is unknown, expired or used up, or the
+ * code is wrong.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::core::db::migrate;
+ * use rusty_api::core::two_factor::{begin_enrolment, confirm_enrolment, create_challenge, redeem_challenge, totp};
+ * use sqlx::sqlite::SqlitePoolOptions;
+ *
+ * # actix_web::rt::System::new().block_on(async {
+ * # let pool = &SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+ * # migrate(pool).await.unwrap();
+ * # sqlx::query("INSERT INTO users (id, username, password_hash) VALUES (1, 'alice', '-')").execute(pool).await.unwrap();
+ * begin_enrolment(pool, 1, "Example").await.unwrap();
+ * # let secret: String = sqlx::query_scalar("SELECT totp_secret FROM users WHERE id = 1").fetch_one(pool).await?;
+ * # let secret = hex::decode(secret).unwrap();
+ * let code_from_app = totp(&secret, chrono::Utc::now().timestamp() as u64 / 30);
+ * let recovery_codes = confirm_enrolment(pool, 1, &code_from_app).await.unwrap();
+ *
+ * // A wrong code fails, and the challenge still allows another attempt
+ * let challenge = create_challenge(pool, 1).await?;
+ * assert_eq!(redeem_challenge(pool, &challenge, "0000-0000-0000-0000").await?, None);
+ * assert_eq!(redeem_challenge(pool, &challenge, &recovery_codes[0]).await?, Some(1));
+ *
+ * // The challenge and the recovery code both work only once
+ * assert_eq!(redeem_challenge(pool, &challenge, &recovery_codes[1]).await?, None);
+ * let challenge = create_challenge(pool, 1).await?;
+ * assert_eq!(redeem_challenge(pool, &challenge, &recovery_codes[0]).await?, None);
+ * # Ok::<(), sqlx::Error>(())
+ * # }).unwrap();
+ * ```
+ */
+pub async fn redeem_challenge(pool: &SqlitePool, token: &str, code: &str) -> Result<Option<i32>, sqlx::Error> {
+    let hash = hash_secret(token);
+    let row: Option<ChallengeRow> = sqlx::query_as(
+        "SELECT id, user_id, token_hash, expires_at, attempts FROM login_challenges WHERE token_hash = ?"
+    )
+    .bind(&hash)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(row) = row.filter(|row| constant_time_eq(row.token_hash.as_bytes(), hash.as_bytes())) else {
+        return Ok(None);
+    };
+    if row.expires_at <= Utc::now() || row.attempts >= CHALLENGE_MAX_ATTEMPTS {
+        return Ok(None);
+    }
+
+    // Counting the attempt before checking the code limits guesses, even for concurrent requests
+    let counted = sqlx::query("UPDATE login_challenges SET attempts = attempts + 1 WHERE id = ? AND attempts < ?")
+        .bind(row.id)
+        .bind(CHALLENGE_MAX_ATTEMPTS)
+        .execute(pool)
+        .await?
+        .rows_affected()
+        == 1;
+    if !counted {
+        return Ok(None);
+    }
+
+    if !verify_second_factor(pool, row.user_id, code).await? {
+        if row.attempts + 1 >= CHALLENGE_MAX_ATTEMPTS {
+            println!("WARNING: Too many wrong two-factor codes for user {}", row.user_id);
+        }
+        return Ok(None);
+    }
+
+    sqlx::query("DELETE FROM login_challenges WHERE id = ?")
+        .bind(row.id)
+        .execute(pool)
+        .await?;
+    Ok(Some(row.user_id))
+}
+
+/**
+ * Compute the TOTP code of a secret for a time step, using HMAC-SHA1 and six digits as
+ * authenticator apps expect.
+ *
+ * # Arguments
+ * - `secret`: The raw secret, as bytes.
+ * - `step`: The number of 30-second steps since the Unix epoch.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::core::two_factor::totp;
+ *
+ * // The SHA-1 test vectors of RFC 6238, truncated to six digits
+ * let secret = b"12345678901234567890";
+ * assert_eq!(totp(secret, 59 / 30), "287082");
+ * assert_eq!(totp(secret, 1111111109 / 30), "081804");
+ * assert_eq!(totp(secret, 1111111111 / 30), "050471");
+ * assert_eq!(totp(secret, 1234567890 / 30), "005924");
+ * assert_eq!(totp(secret, 2000000000 / 30), "279037");
+ * assert_eq!(totp(secret, 20000000000 / 30), "353130");
+ * ```
+ */
+pub fn totp(secret: &[u8], step: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Dynamic truncation, as defined by RFC 4226
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!("{:0width$}", value % 10u32.pow(TOTP_DIGITS as u32), width = TOTP_DIGITS)
+}
+
+/// Find the time step, within one step of `now`, whose code matches.
+fn verify_totp(secret: &[u8], code: &str, now: u64) -> Option<u64> {
+    let code = code.trim();
+    (now.saturating_sub(1)..=now + 1).find(|&step| constant_time_eq(totp(secret, step).as_bytes(), code.as_bytes()))
+}
+
+/// The current TOTP time step.
+fn current_step() -> u64 {
+    Utc::now().timestamp().max(0) as u64 / TOTP_STEP
+}
+
+/// Build the `otpauth://` URI understood by authenticator apps.
+fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        secret,
+        percent_encode(issuer),
+        TOTP_DIGITS,
+        TOTP_STEP
+    )
+}
+
+/// Encode bytes as unpadded base32 (RFC 4648), the format authenticator apps accept secrets in.
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+/// Percent-encode everything but unreserved characters (RFC 3986).
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Generate a recovery code: 64 random bits, as four groups of hex digits.
+fn recovery_code() -> String {
+    let hex = hex::encode(rand::random::<u64>().to_be_bytes());
+    format!("{}-{}-{}-{}", &hex[0..4], &hex[4..8], &hex[8..12], &hex[12..16])
+}
+
+/// Normalize a recovery code as typed by a user, ignoring case, spaces and dashes.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// The error for a user that does not exist.
+fn user_not_found(user_id: i32) -> ApiError {
+    ApiError::not_found(format!("User {} not found", user_id)).with_code("user_not_found")
+}
+
+/// The error for enrolling a user who already has two-factor authentication.
+fn already_enabled() -> ApiError {
+    ApiError::conflict("Two-factor authentication is already enabled").with_code("two_factor_enabled")
+}
+
+/// The error for a wrong TOTP or recovery code.
+fn invalid_code() -> ApiError {
+    ApiError::bad_request("Invalid two-factor code").with_code("invalid_two_factor_code")
+}
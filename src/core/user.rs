@@ -5,53 +5,244 @@
  * user registration, login, and the user model itself.
  */
 use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Privilege level given to newly registered users.
+pub const PRIVILEGE_USER: i32 = 0;
+
+/// Privilege level conventionally used for administrators.
+pub const PRIVILEGE_ADMIN: i32 = 100;
 
 /**
  * User struct
  *
  * This struct represents a user in the system, containing fields for the user's
- * ID, username, and password hash. The user database can contain more fields, but
- * these are the essential ones for authentication and identification.
+ * ID, username, password hash, and privilege level. The user database can contain
+ * more fields, but these are the essential ones for authentication and authorization.
+ * Higher privilege levels grant access to more routes. The password hash is never
+ * serialized, so a `User` can be returned from a route as-is.
  */
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
     pub id: i32,
     pub username: String,
+    #[serde(skip_serializing)]
     pub password_hash: String,
+    pub privilege: i32,
 }
 
 /**
  * Input struct for user registration
  *
  * This struct is used to deserialize the input data for user registration.
- * It contains fields for the username and password.
+ * It contains fields for the username and password. Both must be 1 to 256 characters
+ * long; the register route then applies the configured `UsernamePolicy` and `PasswordPolicy`.
+ * The optional email address is where password reset links are sent.
  */
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct RegisterInput {
+    #[validate(length(min = 1, max = 256))]
     pub username: String,
+    #[validate(length(min = 1, max = 256))]
     pub password: String,
+    #[validate(email, length(max = 254))]
+    #[serde(default)]
+    pub email: Option<String>,
 }
 
 /**
  * Input struct for user login
  *
  * This struct is used to deserialize the input data for user login.
- * It contains fields for the username and password.
+ * It contains fields for the username and password, each 1 to 256 characters long.
  */
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct LoginInput {
+    #[validate(length(min = 1, max = 256))]
     pub username: String,
+    #[validate(length(min = 1, max = 256))]
     pub password: String,
 }
 
 
 /**
- * Response struct for user registration
+ * Input struct for the refresh route
+ *
+ * This struct is used to deserialize the refresh token sent to renew an access token.
+ */
+#[derive(Debug, Deserialize, Validate)]
+pub struct RefreshInput {
+    #[validate(length(min = 1, max = 256))]
+    pub refresh_token: String,
+}
+
+/**
+ * Input struct for the logout route
+ *
+ * This struct is used to deserialize the optional body of a logout request. If it
+ * contains the session's refresh token, that token is revoked too.
+ */
+#[derive(Debug, Deserialize)]
+pub struct LogoutInput {
+    pub refresh_token: Option<String>,
+}
+
+/**
+ * Input struct for the password reset request route
+ *
+ * This struct is used to deserialize the username of the account whose password was
+ * forgotten.
+ */
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasswordResetRequestInput {
+    #[validate(length(min = 1, max = 256))]
+    pub username: String,
+}
+
+/**
+ * Input struct for the password reset confirm route
+ *
+ * This struct is used to deserialize the token from the reset email together with the
+ * new password, which must follow the configured `PasswordPolicy`.
+ */
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasswordResetConfirmInput {
+    #[validate(length(min = 1, max = 256))]
+    pub token: String,
+    #[validate(length(min = 1, max = 256))]
+    pub new_password: String,
+}
+
+/**
+ * Input struct for the second step of a two-factor login
+ *
+ * This struct is used to deserialize the challenge token returned by the login route,
+ * together with a TOTP code or recovery code.
+ */
+#[derive(Debug, Deserialize, Validate)]
+pub struct TwoFactorLoginInput {
+    #[validate(length(min = 1, max = 256))]
+    pub challenge_token: String,
+    #[validate(length(min = 1, max = 64))]
+    pub code: String,
+}
+
+/**
+ * Input struct for the two-factor enrol route
  *
- * This struct is used to serialize the response data for user registration.
- * It contains a field for the users token, which is used for authentication.
+ * This struct is used to deserialize the current password of the user, so a stolen
+ * access token alone cannot attach the thief's authenticator to the account.
+ */
+#[derive(Debug, Deserialize, Validate)]
+pub struct TwoFactorEnrolInput {
+    #[validate(length(min = 1, max = 256))]
+    pub password: String,
+}
+
+/**
+ * Input struct for the two-factor confirm route
+ *
+ * This struct is used to deserialize the current password of the user together with a
+ * TOTP code from the newly enrolled authenticator.
+ */
+#[derive(Debug, Deserialize, Validate)]
+pub struct TwoFactorConfirmInput {
+    #[validate(length(min = 1, max = 256))]
+    pub password: String,
+    #[validate(length(min = 1, max = 64))]
+    pub code: String,
+}
+
+/**
+ * Input struct for the two-factor disable route
+ *
+ * This struct is used to deserialize a TOTP code or a recovery code.
+ */
+#[derive(Debug, Deserialize, Validate)]
+pub struct TwoFactorCodeInput {
+    #[validate(length(min = 1, max = 64))]
+    pub code: String,
+}
+
+/**
+ * Response struct for a login that needs a second factor
+ *
+ * This struct is returned by the login route instead of tokens when the user has
+ * two-factor authentication enabled. The challenge token is sent to the two-factor
+ * login route together with a code.
+ */
+#[derive(Serialize)]
+pub struct ChallengeResponse {
+    /// The single-use token to send with the code.
+    pub challenge_token: String,
+    /// The kind of code expected, always `totp`; recovery codes are accepted too.
+    pub challenge_type: &'static str,
+    /// The number of seconds until the challenge token expires.
+    pub expires_in: u64,
+}
+
+impl ChallengeResponse {
+    /// Build the response for a newly issued challenge token.
+    pub fn new(challenge_token: String, expires_in: std::time::Duration) -> Self {
+        Self {
+            challenge_token,
+            challenge_type: "totp",
+            expires_in: expires_in.as_secs(),
+        }
+    }
+}
+
+/**
+ * Response struct for a confirmed two-factor enrolment
+ *
+ * This struct is used to serialize the one-time recovery codes, which are only ever
+ * shown once.
+ */
+#[derive(Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+/**
+ * The result of checking a username and password
+ *
+ * Users without two-factor authentication receive their tokens straight away; the
+ * others receive a challenge to complete with a code.
+ */
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginOutcome {
+    Tokens(LoginResponse),
+    Challenge(ChallengeResponse),
+}
+
+/**
+ * Response struct for user login
+ *
+ * This struct is used to serialize the response data for login and refresh requests.
+ * It contains the user's short-lived access token, which is used for authentication,
+ * and the refresh token that renews it.
  */
 #[derive(Serialize)]
 pub struct LoginResponse {
+    /// The access token, sent as `Authorization: Bearer <token>`.
     pub token: String,
+    /// Always `Bearer`.
+    pub token_type: &'static str,
+    /// The number of seconds until the access token expires.
+    pub expires_in: u64,
+    /// The single-use token to send to the refresh route for new tokens.
+    pub refresh_token: String,
+}
+
+impl LoginResponse {
+    /// Build the response for a newly issued access token and refresh token.
+    pub fn new(token: String, expires_in: std::time::Duration, refresh_token: String) -> Self {
+        Self {
+            token,
+            token_type: "Bearer",
+            expires_in: expires_in.as_secs(),
+            refresh_token,
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,417 @@
+/*!
+ * Validation module.
+ *
+ * This module validates request inputs before they reach a handler. Input structs declare
+ * their rules with the [`validator`](https://docs.rs/validator) derive macro, such as
+ * `length`, `email`, `range` and `regex`, and are extracted through `Valid`, which wraps
+ * another extractor such as `web::Json<T>` or `web::Query<T>`. Inputs that break a rule
+ * are rejected with `422 Unprocessable Entity`, and the problem details list every
+ * failing field:
+ *
+ * ```json
+ * {
+ *   "status": 422,
+ *   "code": "validation_failed",
+ *   "detail": "Request validation failed",
+ *   "errors": [
+ *     { "field": "email", "code": "email", "message": "must be a valid email address" },
+ *     { "field": "name", "code": "length", "message": "must be between 1 and 64 characters long" }
+ *   ]
+ * }
+ * ```
+ *
+ * The derive macro refers to the `validator` crate by name, so it must be a dependency of
+ * your crate too (`validator = { version = "0.20", features = ["derive"] }`).
+ *
+ * The username and password rules of the built-in register route are set at runtime with
+ * `Api::username_policy` and `Api::password_policy`.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::{web, HttpResponse, Method, Routes, Valid};
+ * use serde::Deserialize;
+ * use validator::Validate;
+ *
+ * #[derive(Deserialize, Validate)]
+ * struct NewContact {
+ *     #[validate(length(min = 1, max = 64))]
+ *     name: String,
+ *     #[validate(email)]
+ *     email: String,
+ *     #[validate(range(min = 18, max = 130))]
+ *     age: u32,
+ * }
+ *
+ * async fn add_contact(contact: Valid<web::Json<NewContact>>) -> HttpResponse {
+ *     HttpResponse::Created().body(format!("Added {}", contact.name))
+ * }
+ *
+ * let routes = Routes::new().add_method_route(Method::POST, "/contacts", add_contact);
+ * ```
+ */
+use actix_web::dev::Payload;
+use actix_web::http::StatusCode;
+use actix_web::{FromRequest, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use regex::Regex;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::ops::Deref;
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+use crate::core::error::ApiError;
+
+pub use validator::Validate;
+
+/// The longest password bcrypt can hash without silently ignoring the rest, in bytes.
+pub const MAX_PASSWORD_BYTES: usize = 72;
+
+/// A class of characters a password may be required to contain: whether it is required, a test, the rule code and a description.
+type CharacterClass = (bool, fn(char) -> bool, &'static str, &'static str);
+
+/**
+ * Extractor that validates the value of another extractor.
+ *
+ * `Valid<E>` runs the extractor `E`, then the `Validate` rules of the value it produced.
+ * It dereferences to that value, so fields can be read directly.
+ */
+#[derive(Debug)]
+pub struct Valid<E>(pub E);
+
+impl<E> Valid<E> {
+    /// Unwrap the inner extractor.
+    pub fn into_inner(self) -> E {
+        self.0
+    }
+}
+
+impl<E: Deref> Deref for Valid<E> {
+    type Target = E::Target;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<E> FromRequest for Valid<E>
+where
+    E: FromRequest + Deref + 'static,
+    E::Target: Validate,
+    E::Error: Into<actix_web::Error>,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let extracted = E::from_request(req, payload);
+        Box::pin(async move {
+            let value = extracted.await.map_err(Into::into)?;
+            value.validate().map_err(ApiError::from)?;
+            Ok(Valid(value))
+        })
+    }
+}
+
+/**
+ * A rule broken by one field of a request.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// The path of the field, such as `email`, `address.city` or `items[2].quantity`.
+    pub field: String,
+    /// The rule that was broken, such as `length` or `email`.
+    pub code: String,
+    /// A human-readable description of the rule.
+    pub message: String,
+}
+
+/**
+ * List the rules broken by each field, sorted by field.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::core::validation::field_errors;
+ * use validator::{ValidationError, ValidationErrors};
+ *
+ * let mut errors = ValidationErrors::new();
+ * errors.add("email", ValidationError::new("email"));
+ *
+ * let fields = field_errors(&errors);
+ * assert_eq!(fields[0].field, "email");
+ * assert_eq!(fields[0].message, "must be a valid email address");
+ * ```
+ */
+pub fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut fields = Vec::new();
+    collect_field_errors("", errors, &mut fields);
+    fields.sort_by(|a, b| a.field.cmp(&b.field));
+    fields
+}
+
+/// Flatten nested validation errors into `fields`, prefixing each field with its parent's path.
+fn collect_field_errors(prefix: &str, errors: &ValidationErrors, fields: &mut Vec<FieldError>) {
+    for (name, kind) in errors.errors() {
+        let path = if prefix.is_empty() { name.to_string() } else { format!("{}.{}", prefix, name) };
+        match kind {
+            ValidationErrorsKind::Field(errors) => fields.extend(errors.iter().map(|error| FieldError {
+                field: path.clone(),
+                code: error.code.to_string(),
+                message: describe(error),
+            })),
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(&path, errors, fields),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect_field_errors(&format!("{}[{}]", path, index), errors, fields);
+                }
+            }
+        }
+    }
+}
+
+/// Describe a broken rule, using its own message if it has one.
+fn describe(error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+
+    let param = |name: &str| error.params.get(name).map(|value| value.to_string());
+    let bounds = |unit: &str| match (param("min"), param("max"), param("equal")) {
+        (_, _, Some(equal)) => format!("must be exactly {}{}", equal, unit),
+        (Some(min), Some(max), _) => format!("must be between {} and {}{}", min, max, unit),
+        (Some(min), None, _) => format!("must be at least {}{}", min, unit),
+        (None, Some(max), _) => format!("must be at most {}{}", max, unit),
+        (None, None, _) => "is out of range".to_owned(),
+    };
+
+    match error.code.as_ref() {
+        "length" => bounds(" characters long"),
+        "range" => bounds(""),
+        "email" => "must be a valid email address".to_owned(),
+        "url" => "must be a valid URL".to_owned(),
+        "regex" => "has an invalid format".to_owned(),
+        "required" => "is required".to_owned(),
+        "must_match" => match param("other") {
+            Some(other) => format!("must match {}", other.trim_matches('"')),
+            None => "must match another field".to_owned(),
+        },
+        _ => "is invalid".to_owned(),
+    }
+}
+
+/// Turn broken rules into a `422 Unprocessable Entity` error listing every failing field.
+impl From<ValidationErrors> for ApiError {
+    fn from(errors: ValidationErrors) -> Self {
+        ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "validation_failed", "Request validation failed")
+            .with_extension("errors", field_errors(&errors))
+    }
+}
+
+/// Create a broken rule with a message.
+fn rule(code: &'static str, message: String) -> ValidationError {
+    ValidationError::new(code).with_message(Cow::Owned(message))
+}
+
+/**
+ * The rules a username must follow to register.
+ *
+ * By default, usernames are 3 to 32 characters long and may contain ASCII letters,
+ * digits, `_`, `.` and `-`.
+ */
+#[derive(Debug, Clone)]
+pub struct UsernamePolicy {
+    min_length: usize,
+    max_length: usize,
+    pattern: Regex,
+}
+
+impl UsernamePolicy {
+    /// Create the default username policy.
+    pub fn new() -> Self {
+        Self {
+            min_length: 3,
+            max_length: 32,
+            pattern: Regex::new(r"^[A-Za-z0-9_.-]+$").unwrap(),
+        }
+    }
+
+    /**
+     * Set the allowed length of usernames, in characters.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::validation::UsernamePolicy;
+     *
+     * let policy = UsernamePolicy::new().length(5, 20);
+     * assert!(!policy.check("bob").is_empty());
+     * assert!(policy.check("bobby").is_empty());
+     * ```
+     */
+    pub fn length(mut self, min: usize, max: usize) -> Self {
+        self.min_length = min;
+        self.max_length = max;
+        self
+    }
+
+    /**
+     * Set the regular expression usernames must match.
+     *
+     * # Panics
+     * Panics if the pattern is not a valid regular expression.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::validation::UsernamePolicy;
+     *
+     * // Lowercase usernames only
+     * let policy = UsernamePolicy::new().pattern(r"^[a-z0-9_]+$");
+     * assert!(!policy.check("Alice").is_empty());
+     * assert!(policy.check("alice").is_empty());
+     * ```
+     */
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.pattern = Regex::new(pattern).expect("Invalid username pattern");
+        self
+    }
+
+    /// The minimum length of usernames, in characters.
+    pub fn get_min_length(&self) -> usize { self.min_length }
+
+    /// The maximum length of usernames, in characters.
+    pub fn get_max_length(&self) -> usize { self.max_length }
+
+    /// The regular expression usernames must match.
+    pub fn get_pattern(&self) -> &str { self.pattern.as_str() }
+
+    /**
+     * Check a username against the policy.
+     *
+     * # Returns
+     * The rules the username breaks, or an empty list if it is allowed.
+     */
+    pub fn check(&self, username: &str) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let length = username.chars().count();
+        if length < self.min_length || length > self.max_length {
+            errors.push(rule(
+                "length",
+                format!("must be between {} and {} characters long", self.min_length, self.max_length),
+            ));
+        }
+        if !self.pattern.is_match(username) {
+            errors.push(rule("regex", "contains characters that are not allowed".to_owned()));
+        }
+        errors
+    }
+}
+
+impl Default for UsernamePolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * The rules a password must follow to register.
+ *
+ * By default, passwords must be at least 8 characters long. Passwords are hashed with
+ * bcrypt, which only uses the first 72 bytes, so longer passwords are always rejected.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    min_length: usize,
+    require_uppercase: bool,
+    require_lowercase: bool,
+    require_digit: bool,
+    require_symbol: bool,
+}
+
+impl PasswordPolicy {
+    /// Create the default password policy.
+    pub fn new() -> Self {
+        Self {
+            min_length: 8,
+            require_uppercase: false,
+            require_lowercase: false,
+            require_digit: false,
+            require_symbol: false,
+        }
+    }
+
+    /**
+     * Set the minimum length of passwords, in characters.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::core::validation::PasswordPolicy;
+     *
+     * let policy = PasswordPolicy::new().min_length(12).require_digit();
+     * assert!(!policy.check("correcthorse").is_empty());
+     * assert!(policy.check("correcthorse9").is_empty());
+     * ```
+     */
+    pub fn min_length(mut self, min_length: usize) -> Self {
+        self.min_length = min_length;
+        self
+    }
+
+    /// Require at least one uppercase letter.
+    pub fn require_uppercase(mut self) -> Self {
+        self.require_uppercase = true;
+        self
+    }
+
+    /// Require at least one lowercase letter.
+    pub fn require_lowercase(mut self) -> Self {
+        self.require_lowercase = true;
+        self
+    }
+
+    /// Require at least one digit.
+    pub fn require_digit(mut self) -> Self {
+        self.require_digit = true;
+        self
+    }
+
+    /// Require at least one character that is neither a letter nor a digit.
+    pub fn require_symbol(mut self) -> Self {
+        self.require_symbol = true;
+        self
+    }
+
+    /// The minimum length of passwords, in characters.
+    pub fn get_min_length(&self) -> usize { self.min_length }
+
+    /**
+     * Check a password against the policy.
+     *
+     * # Returns
+     * The rules the password breaks, or an empty list if it is allowed.
+     */
+    pub fn check(&self, password: &str) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if password.chars().count() < self.min_length {
+            errors.push(rule("length", format!("must be at least {} characters long", self.min_length)));
+        }
+        if password.len() > MAX_PASSWORD_BYTES {
+            errors.push(rule("length", format!("must be at most {} bytes long", MAX_PASSWORD_BYTES)));
+        }
+
+        let classes: [CharacterClass; 4] = [
+            (self.require_uppercase, char::is_uppercase, "uppercase", "an uppercase letter"),
+            (self.require_lowercase, char::is_lowercase, "lowercase", "a lowercase letter"),
+            (self.require_digit, |c| c.is_ascii_digit(), "digit", "a digit"),
+            (self.require_symbol, |c| !c.is_alphanumeric(), "symbol", "a symbol"),
+        ];
+        for (required, matches, code, description) in classes {
+            if required && !password.chars().any(matches) {
+                errors.push(rule(code, format!("must contain {}", description)));
+            }
+        }
+        errors
+    }
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
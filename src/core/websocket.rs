@@ -0,0 +1,115 @@
+/*!
+ * WebSocket module.
+ *
+ * This module supports the WebSocket routes registered with `Routes::add_websocket` and
+ * `Routes::add_websocket_with_auth`. Each connection is handled by an async session
+ * handler, which receives a `Session` to send messages and a `MessageStream` to read
+ * them. The handler runs in its own task for as long as the connection is open.
+ *
+ * Authenticated WebSocket routes accept the JWT in any of three places, because browsers
+ * cannot set an `Authorization` header on a WebSocket:
+ * - An `Authorization: Bearer <token>` header, for native clients.
+ * - The `Sec-WebSocket-Protocol` header, by offering the subprotocols `bearer` and the token
+ *   (e.g. `new WebSocket(url, ["bearer", token])` in a browser).
+ * - A `token` query parameter (e.g. `wss://example.com/ws?token=...`). Query strings may
+ *   end up in proxy logs, so prefer the subprotocol where possible.
+ *
+ * The connection is closed with a policy-violation close frame when the token expires.
+ *
+ * # Example
+ * ```rust
+ * use rusty_api::Routes;
+ * use rusty_api::core::websocket::{Message, MessageStream, Session};
+ *
+ * async fn echo(mut session: Session, mut stream: MessageStream) {
+ *     while let Some(Ok(message)) = stream.recv().await {
+ *         let sent = match message {
+ *             Message::Text(text) => session.text(text).await,
+ *             Message::Ping(bytes) => session.pong(&bytes).await,
+ *             Message::Close(reason) => {
+ *                 let _ = session.close(reason).await;
+ *                 return;
+ *             }
+ *             _ => Ok(()),
+ *         };
+ *         if sent.is_err() {
+ *             return;
+ *         }
+ *     }
+ * }
+ *
+ * async fn notifications(mut session: Session, _stream: MessageStream, user_id: i32) {
+ *     let _ = session.text(format!("Hello, user {}", user_id)).await;
+ * }
+ *
+ * let routes = Routes::new()
+ *     .add_websocket("/ws/echo", echo)
+ *     .add_websocket_with_auth("/ws/notifications", notifications);
+ * ```
+ */
+use actix_web::http::header;
+use actix_web::HttpRequest;
+use futures_util::future::{select, Either};
+use std::future::Future;
+use std::pin::pin;
+use crate::core::auth::{bearer_token, query_token, time_until_expiry};
+
+pub use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+
+/// The subprotocol a browser offers, followed by the token, to authenticate a WebSocket.
+pub const BEARER_PROTOCOL: &str = "bearer";
+
+/// Where the token of a WebSocket request was found.
+pub(crate) enum TokenSource {
+    /// The `Authorization` header or the `token` query parameter.
+    Request,
+    /// The `Sec-WebSocket-Protocol` header, which requires the server to confirm the `bearer` subprotocol.
+    Subprotocol,
+}
+
+/**
+ * Find the JWT of a WebSocket upgrade request.
+ *
+ * # Returns
+ * The token and where it was found, or `None` if the request carries no token.
+ */
+pub(crate) fn websocket_token(req: &HttpRequest) -> Option<(String, TokenSource)> {
+    if let Some(token) = bearer_token(req) {
+        return Some((token.to_owned(), TokenSource::Request));
+    }
+
+    let protocols = req.headers().get(header::SEC_WEBSOCKET_PROTOCOL).and_then(|h| h.to_str().ok());
+    if let Some(protocols) = protocols {
+        let mut protocols = protocols.split(',').map(str::trim);
+        if protocols.any(|protocol| protocol.eq_ignore_ascii_case(BEARER_PROTOCOL))
+            && let Some(token) = protocols.next()
+        {
+            return Some((token.to_owned(), TokenSource::Subprotocol));
+        }
+    }
+
+    query_token(req).map(|token| (token, TokenSource::Request))
+}
+
+/**
+ * Run a session handler until it finishes or the token expires.
+ *
+ * If the token expires first, the handler is stopped and the connection is closed with
+ * a policy-violation close frame.
+ *
+ * # Arguments
+ * - `session`: The session of the connection, used to close it.
+ * - `expires_at`: The expiry time of the token, in seconds since the Unix epoch.
+ * - `handler`: The session handler.
+ */
+pub(crate) async fn run_until_expiry<F>(session: Session, expires_at: usize, handler: F)
+where
+    F: Future<Output = ()>,
+{
+    let handler = pin!(handler);
+    let expiry = pin!(actix_web::rt::time::sleep(time_until_expiry(expires_at)));
+    if let Either::Right(_) = select(handler, expiry).await {
+        let reason = CloseReason { code: CloseCode::Policy, description: Some("Token expired".into()) };
+        let _ = session.close(Some(reason)).await;
+    }
+}
@@ -10,7 +10,7 @@
  * - **Actix Web Integration**: Built on the Actix Web framework for high-performance and asynchronous web applications.
  * - **TLS Support**: Includes utilities for configuring Rustls for secure HTTPS communication.
  * - **CORS Handling**: Provides seamless integration with Actix CROS for managing croos-origin requests.
- * - **Password Protection**: Offers built-in support for password-protected routes, enhancing security for sensitive endpoints.
+ * - **API Key Protection**: Offers built-in support for routes protected by named, scoped API keys, enhancing security for sensitive endpoints.
  *
  * Rust API is ideal for developers looking to quickly build secure and scalable APIs with minimal boilerplate.
  *
@@ -20,17 +20,24 @@
  * To use rusty-api in your project, add the following to your `Cargo.toml`:
  * ```toml
  * [dependencies]
- * rusty-api = "0.1.8"
+ * rusty-api = "0.2.0"
  * ```
  * 
+ * ## Upgrading to 0.2
+ * **Breaking:** the first argument of `Api::rate_limit` is now the number of requests
+ * allowed per second. In 0.1 it was the number of seconds between requests, so
+ * `rate_limit(3, 20)` used to allow one request every three seconds and now allows three
+ * requests per second, still with bursts of up to 20. The default limit of `(3, 20)` has
+ * loosened in the same way, so review the global limit when upgrading.
+ *
  * ## Example
  * ### Setting up your API
- * Here's an example of how to use rusty-api to create an API with public and password-protected routes:
+ * Here's an example of how to use rusty-api to create an API with public and API key protected routes:
  * ```rust,no_run,ignore
  * use rusty_api;
  *
- * async fn password_route(_req: rusty_api::HttpRequest) -> rusty_api::HttpResponse {
- *     rusty_api::HttpResponse::Ok().body("Password route accessed!")
+ * async fn api_key_route(_req: rusty_api::HttpRequest) -> rusty_api::HttpResponse {
+ *     rusty_api::HttpResponse::Ok().body("API key route accessed!")
  * }
  *
  * async fn open_route(_req: rusty_api::HttpRequest) -> rusty_api::HttpResponse {
@@ -39,12 +46,12 @@
  *
  * fn main() {
  *     let routes = rusty_api::Routes::new()
- *         .add_route_with_password("/password_route", password_route, "Password123")
+ *         .add_route_with_api_key("/api_key_route", api_key_route)
  *         .add_route("/open_route", open_route);
  *
  *     rusty_api::Api::new()
  *         .certs("certs/cert.pem", "certs/key.pem")
- *         .rate_limit(3, 20)
+ *         .rate_limit(1, 20)
  *         .bind("127.0.0.1", 8443)
  *         .configure_routes(routes)
  *         .configure_cors(|| {
@@ -76,14 +83,27 @@ pub mod routes;
 pub mod core;
 
 pub use crate::api::Api;
-pub use crate::routes::Routes;
+pub use crate::routes::{Protection, RouteInfo, Routes};
 pub use crate::core::config::load_rustls_config;
-pub use crate::core::db::{get_user_field, set_user_field};
+pub use crate::core::db::{get_user_field, set_user_field, set_user_privilege, change_user_privilege};
+pub use crate::core::user::{PRIVILEGE_USER, PRIVILEGE_ADMIN};
 pub use crate::core::auth::validate_token;
-pub use crate::core::auth::Claims;
+pub use crate::core::auth::{AuthUser, Claims};
+pub use crate::core::auth_config::AuthConfig;
+pub use crate::core::api_key::ApiKey;
+pub use crate::core::rate_limit::RateLimit;
+pub use crate::core::client_ip::{ClientIp, ForwardedHeader};
+pub use crate::core::openapi::DocsUi;
+pub use crate::core::middleware::{Middleware, Next};
+pub use crate::core::error::ApiError;
+pub use crate::core::request_id::RequestId;
+pub use crate::core::validation::{PasswordPolicy, UsernamePolicy, Valid};
+pub use crate::core::content::Content;
+pub use crate::core::pagination::{Listing, Page, Pagination};
 
 pub use actix_web::{web, HttpResponse, HttpRequest};
-pub use actix_web::http::StatusCode;
+pub use actix_web::dev::{ServiceRequest, ServiceResponse};
+pub use actix_web::http::{Method, StatusCode};
 pub use actix_cors::Cors;
 
 use once_cell::sync::Lazy;
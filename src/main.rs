@@ -1,38 +1,36 @@
-use rusty_api;
-
-async fn password_route(_req: rusty_api::HttpRequest) -> rusty_api::HttpResponse {
-    rusty_api::HttpResponse::Ok().body("Password route accessed!")
+async fn api_key_route(_req: rusty_api::HttpRequest) -> rusty_api::HttpResponse {
+    rusty_api::HttpResponse::Ok().body("API key route accessed!")
 }
 
 async fn open_route(_req: rusty_api::HttpRequest) -> rusty_api::HttpResponse {
     rusty_api::HttpResponse::Ok().body("Open route accessed!")
 }
 
-async fn get_role(_req: rusty_api::HttpRequest, user_id: i32) -> rusty_api::HttpResponse {
-    rusty_api::get_user_field(user_id, "role").await
+async fn get_privilege(user: rusty_api::AuthUser) -> rusty_api::HttpResponse {
+    rusty_api::HttpResponse::Ok().body(user.privilege.to_string())
 }
 
-async fn set_role(req: rusty_api::HttpRequest, user_id: i32) -> rusty_api::HttpResponse {
-    let new_role = req
-        .uri()
-        .query()
-        .and_then(|q| q.split('&').find(|pair| pair.starts_with("args=")))
-        .and_then(|pair| pair.split('=').nth(1))
-        .unwrap_or("default_role");
+#[derive(serde::Deserialize)]
+struct PromoteQuery {
+    user_id: i32,
+    level: i32,
+}
 
-    rusty_api::set_user_field(user_id, "role", &new_role).await
+async fn promote(actor: rusty_api::AuthUser, query: rusty_api::web::Query<PromoteQuery>) -> rusty_api::HttpResponse {
+    rusty_api::change_user_privilege(&actor, query.user_id, query.level).await
 }
 
 fn main() {
     let routes = rusty_api::Routes::new()
-        .add_route_with_password("/password_route", password_route, "Password123")
+        .add_route_with_api_key("/api_key_route", api_key_route)
         .add_route("/open_route", open_route)
-        .add_route_with_auth("/get_role", get_role)
-        .add_route_with_auth("/set_role", set_role);
+        .add_route_with_bearer("/get_privilege", get_privilege)
+        .add_method_route_with_privilege(rusty_api::Method::POST, "/promote", promote, rusty_api::PRIVILEGE_ADMIN);
 
     rusty_api::Api::new()
         .certs("certs/cert.pem", "certs/key.pem")
-        .rate_limit(3, 20)
+        .rate_limit(1, 20)
+        .trusted_proxies(&["127.0.0.1", "::1"])
         .bind("127.0.0.1", 8443)
         .configure_routes(routes)
         .configure_cors(|| {
@@ -42,5 +40,6 @@ fn main() {
                 .allowed_header("ngrok-skip-browser-warning")
         })
         .enable_user_db()
+        .docs_ui(rusty_api::DocsUi::SwaggerUi, "/docs")
         .start();
 }
\ No newline at end of file
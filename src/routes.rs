@@ -6,20 +6,145 @@
  * up API endpoints and ensures secure access to protected routes.
  *
  * This module features:
- * - **Password-Protected Routes**: Easily secure specific routes with a password.
+ * - **Password-Protected Routes**: Secure specific routes with a fixed password (deprecated in favour of API keys).
+ * - **API Key Routes**: Secure routes with named, scoped, expiring and rotatable API keys.
  * - **Public Routes**: Define routes that are accessible without authentication.
+ * - **Privileged Routes**: Restrict routes to users with a minimum privilege level.
+ * - **HTTP Methods**: Register routes for any HTTP method, with several methods sharing one path.
+ * - **Per-Route Rate Limits**: Give individual routes stricter limits than the global one.
+ * - **Route Documentation**: Describe routes with summaries and JSON Schemas for the OpenAPI document.
+ * - **Route Middleware**: Run your own middleware on individual routes or on every route in a `Routes` value.
+ * - **WebSocket Routes**: Handle WebSocket connections, optionally authenticated with a JWT.
+ * - **Server-Sent Events**: Stream events published through a `Broadcaster` to all subscribers or to one user.
+ * - **Route Groups**: Nest routes under a shared path prefix, protection, rate limit and middleware with
+ *   `scope`, and combine routes built in different modules with `merge`.
  * - **Flexible Configuration**: Apply routes to an Actix Web `ServiceConfig` for seamless integration.
  *
  * The `Routes` struct serves as a container for all defined routes, allowing for
  * easy management and configuration.
  */
-use actix_web::{web, Responder, FromRequest, HttpRequest, HttpResponse, dev::Handler};
-use crate::core::auth::{validate_token};
+use actix_web::{web, Responder, FromRequest, HttpRequest, HttpResponse, Route, dev::Handler};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::core::auth::{authenticate_token, bearer_token, insufficient_privilege, missing_token, query_token, time_until_expiry, AuthUser};
+use crate::core::error::ApiError;
+use crate::core::sse::{event_stream, Broadcaster};
+use crate::core::websocket::{run_until_expiry, websocket_token, MessageStream, Session, TokenSource, BEARER_PROTOCOL};
+use crate::core::api_key::{ApiKey, API_KEY_HEADER};
+use crate::core::secret::{constant_time_eq, hash_secret};
+use crate::core::rate_limit::{RateLimit, RateLimiter};
+use crate::core::middleware::{Middleware, MiddlewareChain};
+
+/// A factory that builds a fresh Actix `Route` each time the routes are applied to a worker.
+type RouteFactory = Box<dyn Fn() -> Route + Send + Sync>;
+
+/// Number of the next group limiter created by `Routes::rate_limit_all`, used to give each group its own scope.
+static NEXT_LIMITER_GROUP: AtomicU64 = AtomicU64::new(1);
+
+/**
+ * How a route is protected.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protection {
+    /// Anyone may call the route.
+    Public,
+    /// The caller must send the route's password in the `X-API-Key` header.
+    Password,
+    /// The caller must send a valid API key in the `X-API-Key` header.
+    ApiKey,
+    /// The caller must send a valid JWT in the `Authorization` header.
+    Bearer,
+    /// The caller must send a valid JWT for a user with at least this privilege level.
+    Privilege(i32),
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protection::Public => write!(f, "public"),
+            Protection::Password => write!(f, "password"),
+            Protection::ApiKey => write!(f, "api key"),
+            Protection::Bearer => write!(f, "bearer token"),
+            Protection::Privilege(level) => write!(f, "privilege >= {}", level),
+        }
+    }
+}
+
+impl Protection {
+    /// The stronger of two protections, used to describe a protected route inside a protected group.
+    fn strongest(self, other: Protection) -> Protection {
+        fn rank(protection: Protection) -> u8 {
+            match protection {
+                Protection::Public => 0,
+                Protection::Password => 1,
+                Protection::ApiKey => 2,
+                Protection::Bearer => 3,
+                Protection::Privilege(_) => 4,
+            }
+        }
+
+        match (self, other) {
+            (Protection::Privilege(a), Protection::Privilege(b)) => Protection::Privilege(a.max(b)),
+            _ if rank(other) > rank(self) => other,
+            _ => self,
+        }
+    }
+}
+
+/**
+ * Information about a registered route.
+ *
+ * This describes what a route does and how it is protected, without the handler itself.
+ * It is used to generate the OpenAPI document and the route table printed at startup,
+ * and can be inspected in tests through `Routes::iter`.
+ */
+#[derive(Debug, Clone)]
+pub struct RouteInfo {
+    /// The HTTP method the route answers to.
+    pub method: Method,
+    /// The URL path of the route, including any path parameters (e.g. `/items/{id}`).
+    pub path: String,
+    /// How the route is protected.
+    pub protection: Protection,
+    /// Optional short description of the route.
+    pub summary: Option<String>,
+    /// Optional JSON Schema of the request body.
+    pub request_schema: Option<serde_json::Value>,
+    /// Optional JSON Schema of the successful response body.
+    pub response_schema: Option<serde_json::Value>,
+}
+
+impl RouteInfo {
+    /// The minimum privilege level required to call the route, if it is a privileged route.
+    pub fn privilege(&self) -> Option<i32> {
+        match self.protection {
+            Protection::Privilege(level) => Some(level),
+            _ => None,
+        }
+    }
+}
+
+/// A single registered route: its information, how to build it, its own rate limiter and middleware.
+struct RouteEntry {
+    info: RouteInfo,
+    route: RouteFactory,
+    rate_limiter: Option<RateLimiter>,
+    middleware: Vec<Middleware>,
+    /// Rate limiters of the enclosing scopes, outermost first.
+    scope_limiters: Vec<RateLimiter>,
+    /// Middleware of the enclosing scopes, outermost first.
+    scope_middleware: Vec<Middleware>,
+    /// Protection checks of the enclosing scopes, outermost first.
+    scope_guards: Vec<Middleware>,
+}
 
 /**
  * The `Routes` struct is used to manage API routes.
  *
- * It allows for the addition of routes with or without password protection,
+ * It allows for the addition of public and protected routes,
  * and provides a method to apply these routes to an Actix Web `ServiceConfig`.
  *
  * # Example
@@ -37,11 +162,15 @@ use crate::core::auth::{validate_token};
  * 
  * let routes = Routes::new()
  *     .add_route("/public", public_route)
- *     .add_route_with_password("/protected", protected_route, "SecretPassword");
+ *     .add_route_with_api_key("/protected", protected_route);
  * ```
  */
 pub struct Routes {
-    routes: Vec<Box<dyn Fn(&mut web::ServiceConfig) + Send + Sync>>,
+    routes: Vec<RouteEntry>,
+    middleware: Vec<Middleware>,
+    rate_limiter: Option<RateLimiter>,
+    guards: Vec<Middleware>,
+    protection: Protection,
 }
 
 impl Routes {
@@ -62,14 +191,26 @@ impl Routes {
      * ```
      */
     pub fn new() -> Self {
-        Self { routes: Vec::new() }
+        Self {
+            routes: Vec::new(),
+            middleware: Vec::new(),
+            rate_limiter: None,
+            guards: Vec::new(),
+            protection: Protection::Public,
+        }
     }
 
     /**
      * Add a new route to the `Routes` instance with password protection.
      *
      * This method allows you to define a route that requires a password to access.
-     * The password is passed as a query parameter in the request.
+     * The password acts as a fixed API key: clients send it in the `X-API-Key` header,
+     * and it is kept only as a hash and compared in constant time.
+     *
+     * The password is compiled into the binary, shared by every client and cannot be
+     * rotated or revoked without a new release, so this method is deprecated. Use
+     * `add_route_with_api_key`, whose keys are named, scoped to routes, expire and can be
+     * rotated at runtime.
      *
      * # Arguments
      * - `path`: The URL path for the route.
@@ -78,6 +219,7 @@ impl Routes {
      *
      * # Example
      * ```rust
+     * # #![allow(deprecated)]
      * use rusty_api::{Routes, HttpRequest, HttpResponse};
      *
      * async fn protected_route(_req: HttpRequest) -> HttpResponse {
@@ -88,9 +230,10 @@ impl Routes {
      *    .add_route_with_password("/protected", protected_route, "SecretPassword");
      * ```
      */
+    #[deprecated(since = "0.2.0", note = "use `add_route_with_api_key` with a named, route-scoped key instead")]
     pub fn add_route_with_password<H, Args, R>(
         self,
-        path: &'static str,
+        path: &str,
         handler: H,
         password: &'static str,
     ) -> Self
@@ -99,13 +242,17 @@ impl Routes {
         Args: FromRequest + 'static,
         R: Responder + 'static,
     {
-        self.add_route_internal(path, handler, Some(password))
+        #[allow(deprecated)]
+        self.add_method_route_with_password(Method::GET, path, handler, password)
     }
 
     /**
      * Add a new route to the `Routes` instance without password protection.
      *
      * This method allows you to define a public route that does not require authentication.
+     * The route is listed as public in the route table and the OpenAPI document, even if
+     * the handler takes an `AuthUser` argument; register such handlers with
+     * `add_route_with_bearer` or `add_route_with_privilege` instead.
      *
      * # Arguments
      * - `path`: The URL path for the route.
@@ -123,13 +270,13 @@ impl Routes {
      *   .add_route("/public", public_route);
      * ```
      */
-    pub fn add_route<H, Args, R>(self, path: &'static str, handler: H) -> Self
+    pub fn add_route<H, Args, R>(self, path: &str, handler: H) -> Self
     where
         H: Handler<Args, Output = R> + Clone + Send + Sync + 'static,
         Args: FromRequest + 'static,
         R: Responder + 'static,
     {
-        self.add_route_internal(path, handler, None)
+        self.add_method_route(Method::GET, path, handler)
     }
 
     /**
@@ -138,6 +285,9 @@ impl Routes {
      * This method allows you to define a route that requires authentication via a token.
      * The token is passed in the `Authorization` header of the request.
      *
+     * Handlers that need other extractors (such as `web::Json<T>` or `web::Query<T>`)
+     * can instead take an `AuthUser` argument and be registered with `add_route_with_bearer`.
+     *
      * # Arguments
      * - `path`: The URL path for the route.
      * - `handler`: The handler function for the route.
@@ -154,119 +304,1291 @@ impl Routes {
      *    .add_route_with_auth("/auth", auth_route);
      * ```
      */
-    pub fn add_route_with_auth<H, R>(mut self, path: &'static str, handler: H) -> Self
+    pub fn add_route_with_auth<H, R>(self, path: &str, handler: H) -> Self
+    where
+        H: Fn(HttpRequest, i32) -> R + Clone + Send + Sync + 'static,
+        R: futures_util::Future<Output = HttpResponse> + 'static,
+    {
+        self.add_method_route_with_auth(Method::GET, path, handler)
+    }
+
+    /**
+     * Add a new public route that responds to the given HTTP method.
+     *
+     * Routes registered on the same path share a single resource, so a path can
+     * answer to several methods. Requests using a method that was not registered
+     * receive `405 Method Not Allowed` with an `Allow` header listing the valid methods.
+     * Like `add_route`, this is for public routes only.
+     *
+     * # Arguments
+     * - `method`: The HTTP method the route responds to.
+     * - `path`: The URL path for the route.
+     * - `handler`: The handler function for the route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, Method, HttpRequest, HttpResponse};
+     *
+     * async fn list_items(_req: HttpRequest) -> HttpResponse {
+     *    HttpResponse::Ok().body("Items listed!")
+     * }
+     *
+     * async fn create_item(_req: HttpRequest) -> HttpResponse {
+     *    HttpResponse::Created().body("Item created!")
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_route("/items", list_items)
+     *    .add_method_route(Method::POST, "/items", create_item);
+     * ```
+     */
+    pub fn add_method_route<H, Args, R>(self, method: Method, path: &str, handler: H) -> Self
+    where
+        H: Handler<Args, Output = R> + Clone + Send + Sync + 'static,
+        Args: FromRequest + 'static,
+        R: Responder + 'static,
+    {
+        self.add_route_internal(method, path, handler, None)
+    }
+
+    /**
+     * Add a new password-protected route that responds to the given HTTP method.
+     *
+     * This behaves like `add_route_with_password`, but lets you choose the HTTP method.
+     * It is deprecated for the same reasons; use `add_method_route_with_api_key` instead.
+     *
+     * # Arguments
+     * - `method`: The HTTP method the route responds to.
+     * - `path`: The URL path for the route.
+     * - `handler`: The handler function for the route.
+     * - `password`: The password required to access the route.
+     *
+     * # Example
+     * ```rust
+     * # #![allow(deprecated)]
+     * use rusty_api::{Routes, Method, HttpRequest, HttpResponse};
+     *
+     * async fn delete_item(_req: HttpRequest) -> HttpResponse {
+     *    HttpResponse::NoContent().finish()
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_method_route_with_password(Method::DELETE, "/items", delete_item, "SecretPassword");
+     * ```
+     */
+    #[deprecated(since = "0.2.0", note = "use `add_method_route_with_api_key` with a named, route-scoped key instead")]
+    pub fn add_method_route_with_password<H, Args, R>(
+        self,
+        method: Method,
+        path: &str,
+        handler: H,
+        password: &'static str,
+    ) -> Self
+    where
+        H: Handler<Args, Output = R> + Clone + Send + Sync + 'static,
+        Args: FromRequest + 'static,
+        R: Responder + 'static,
+    {
+        self.add_route_internal(method, path, handler, Some(password))
+    }
+
+    /**
+     * Add a new authenticated route that responds to the given HTTP method.
+     *
+     * This behaves like `add_route_with_auth`, but lets you choose the HTTP method.
+     *
+     * # Arguments
+     * - `method`: The HTTP method the route responds to.
+     * - `path`: The URL path for the route.
+     * - `handler`: The handler function for the route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, Method, HttpRequest, HttpResponse};
+     *
+     * async fn update_profile(_req: HttpRequest, user_id: i32) -> HttpResponse {
+     *    HttpResponse::Ok().body(format!("Updated profile for user {}", user_id))
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_method_route_with_auth(Method::PUT, "/profile", update_profile);
+     * ```
+     */
+    pub fn add_method_route_with_auth<H, R>(self, method: Method, path: &str, handler: H) -> Self
     where
         H: Fn(HttpRequest, i32) -> R + Clone + Send + Sync + 'static,
         R: futures_util::Future<Output = HttpResponse> + 'static,
     {
-        let wrapped_handler = move |req: HttpRequest| {
+        // The `AuthUser` extractor rejects requests without a valid token before this runs
+        let wrapped_handler = move |req: HttpRequest, user: AuthUser| {
             let handler = handler.clone();
-            async move {
-                // Extract and validate the token
-                let token = match req
-                    .headers()
-                    .get("Authorization")
-                    .and_then(|h| h.to_str().ok())
-                    .and_then(|h| h.strip_prefix("Bearer "))
-                {
-                    Some(token) => token,
-                    None => return HttpResponse::Unauthorized().body("Missing or invalid token"),
-                };
-
-                // Validate the token and extract the user ID
-                let user_id = match validate_token(token) {
-                    Ok(claims) => claims.sub,
-                    Err(_) => return HttpResponse::Unauthorized().body("Invalid token"),
-                };
-
-                // Call the handler with the user ID
-                handler(req, user_id).await
-            }
+            async move { handler(req, user.id).await }
         };
 
-        let route = {
-            let wrapped_handler = wrapped_handler.clone(); // Clone the handler inside the closure
-            move |cfg: &mut web::ServiceConfig| {
-                cfg.route(path, web::get().to(wrapped_handler.clone())); // Clone again for Actix
-            }
+        self.push_route(method, path, Protection::Bearer, wrapped_handler)
+    }
+
+    /**
+     * Add a new route to the `Routes` instance that requires a valid JWT.
+     *
+     * Unlike `add_route_with_auth`, the handler can take any extractors. Requests without a
+     * valid token in the `Authorization` header receive `401 Unauthorized` before the handler
+     * runs, and the handler can take an `AuthUser` argument to learn who is calling.
+     *
+     * # Arguments
+     * - `path`: The URL path for the route.
+     * - `handler`: The handler function for the route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{AuthUser, Protection, Routes, HttpResponse};
+     *
+     * async fn profile(user: AuthUser) -> HttpResponse {
+     *    HttpResponse::Ok().body(format!("User {}", user.id))
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_route_with_bearer("/profile", profile);
+     * assert_eq!(routes.iter().next().unwrap().protection, Protection::Bearer);
+     * ```
+     */
+    pub fn add_route_with_bearer<H, Args, R>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<Args, Output = R> + Clone + Send + Sync + 'static,
+        Args: FromRequest + 'static,
+        R: Responder + 'static,
+    {
+        self.add_method_route_with_bearer(Method::GET, path, handler)
+    }
+
+    /**
+     * Add a new route that requires a valid JWT and responds to the given HTTP method.
+     *
+     * This behaves like `add_route_with_bearer`, but lets you choose the HTTP method.
+     *
+     * # Arguments
+     * - `method`: The HTTP method the route responds to.
+     * - `path`: The URL path for the route.
+     * - `handler`: The handler function for the route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{web, AuthUser, Routes, Method, HttpResponse};
+     *
+     * async fn rename(user: AuthUser, name: web::Json<String>) -> HttpResponse {
+     *    HttpResponse::Ok().body(format!("User {} renamed to {}", user.id, name))
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_method_route_with_bearer(Method::POST, "/rename", rename);
+     * ```
+     */
+    pub fn add_method_route_with_bearer<H, Args, R>(self, method: Method, path: &str, handler: H) -> Self
+    where
+        H: Handler<Args, Output = R> + Clone + Send + Sync + 'static,
+        Args: FromRequest + 'static,
+        R: Responder + 'static,
+    {
+        // The `AuthUser` extractor rejects requests without a valid token, whatever the handler extracts
+        let wrapped_handler = move |req: HttpRequest, _user: AuthUser, args: Args| {
+            let handler = handler.clone();
+            async move { handler.call(args).await.respond_to(&req).map_into_boxed_body() }
         };
 
-        self.routes.push(Box::new(route));
-        self
+        self.push_route(method, path, Protection::Bearer, wrapped_handler)
     }
 
-    /// Internal function to handle adding routes with or without passwords.
-    fn add_route_internal<H, Args, R>(
-        mut self,
-        path: &'static str,
+    /**
+     * Add a new route to the `Routes` instance that requires a minimum privilege level.
+     *
+     * The request must carry a valid token in the `Authorization` header, as with
+     * `add_route_with_auth`. Requests without a valid token receive `401 Unauthorized`,
+     * and requests whose privilege level is below `level` receive `403 Forbidden`.
+     * The handler can take an `AuthUser` argument to learn who is calling.
+     *
+     * # Arguments
+     * - `path`: The URL path for the route.
+     * - `handler`: The handler function for the route.
+     * - `level`: The minimum privilege level required to access the route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{AuthUser, Routes, HttpResponse, PRIVILEGE_ADMIN};
+     *
+     * async fn admin_route(user: AuthUser) -> HttpResponse {
+     *    HttpResponse::Ok().body(format!("Welcome, administrator {}", user.id))
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_route_with_privilege("/admin", admin_route, PRIVILEGE_ADMIN);
+     * ```
+     */
+    pub fn add_route_with_privilege<H, Args, R>(self, path: &str, handler: H, level: i32) -> Self
+    where
+        H: Handler<Args, Output = R> + Clone + Send + Sync + 'static,
+        Args: FromRequest + 'static,
+        R: Responder + 'static,
+    {
+        self.add_method_route_with_privilege(Method::GET, path, handler, level)
+    }
+
+    /**
+     * Add a new route that requires a minimum privilege level and responds to the given HTTP method.
+     *
+     * This behaves like `add_route_with_privilege`, but lets you choose the HTTP method.
+     *
+     * # Arguments
+     * - `method`: The HTTP method the route responds to.
+     * - `path`: The URL path for the route.
+     * - `handler`: The handler function for the route.
+     * - `level`: The minimum privilege level required to access the route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, Method, HttpResponse, PRIVILEGE_ADMIN};
+     *
+     * async fn purge_cache() -> HttpResponse {
+     *    HttpResponse::NoContent().finish()
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_method_route_with_privilege(Method::DELETE, "/cache", purge_cache, PRIVILEGE_ADMIN);
+     * ```
+     */
+    pub fn add_method_route_with_privilege<H, Args, R>(
+        self,
+        method: Method,
+        path: &str,
         handler: H,
-        password: Option<&'static str>,
+        level: i32,
     ) -> Self
     where
         H: Handler<Args, Output = R> + Clone + Send + Sync + 'static,
         Args: FromRequest + 'static,
         R: Responder + 'static,
     {
-        let handler = handler.clone(); // Clone the handler to avoid moving it
-        let wrapped_handler = move |req: HttpRequest, args: Args| {
-            let handler = handler.clone(); // Clone the handler inside the closure
+        // The `AuthUser` extractor runs first, so unauthenticated requests never reach the level check
+        let wrapped_handler = move |req: HttpRequest, user: AuthUser, args: Args| {
+            let handler = handler.clone();
             async move {
-                if let Some(expected_password) = password {
-                    if !check_password(&req, expected_password) {
-                        return HttpResponse::Unauthorized().body("Invalid password").into();
-                    }
+                if user.privilege < level {
+                    return insufficient_privilege().into();
                 }
-                // Call the original handler and convert its output to an HttpResponse
                 handler.call(args).await.respond_to(&req).map_into_boxed_body()
             }
         };
 
-        let route = move |cfg: &mut web::ServiceConfig| {
-            let wrapped_handler = wrapped_handler.clone(); // Clone the wrapped handler inside the route closure
-            cfg.service(web::resource(path).route(web::get().to(wrapped_handler)));
-        };
-        self.routes.push(Box::new(route));
-        self
+        self.push_route(method, path, Protection::Privilege(level), wrapped_handler)
     }
 
     /**
-     * Apply the routes to a `ServiceConfig`.
+     * Add a new route to the `Routes` instance that requires an API key.
      *
-     * This method iterates over all defined routes and applies them to the
-     * provided Axtix Web `ServiceConfig`. It is used internally by the `Api` struct.
+     * Clients send the key in the `X-API-Key` header. The key must exist in the user
+     * database, must not be revoked or expired, and must list this route's path among
+     * the routes it may call. Keys are managed with the functions in `core::api_key`,
+     * and the user database must be enabled with `Api::enable_user_db`.
      *
      * # Arguments
-     * - `cfg`: A mutable reference to the `ServiceConfig` to which the routes will be applied.
+     * - `path`: The URL path for the route.
+     * - `handler`: The handler function for the route.
      *
      * # Example
      * ```rust
-     * use rusty_api::{Routes, Api};
+     * use rusty_api::{ApiKey, Routes, HttpResponse};
      *
-     * let routes = Routes::new();
+     * async fn reports(key: ApiKey) -> HttpResponse {
+     *    HttpResponse::Ok().body(format!("Reports requested by {}", key.name))
+     * }
      *
-     * let api = Api::new()
-     *    .configure_routes(routes); // The configure_routes method calls the configure method internally.
+     * let routes = Routes::new()
+     *    .add_route_with_api_key("/reports", reports);
      * ```
      */
-    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
-        for route in &self.routes {
-            route(cfg);
-        }
+    pub fn add_route_with_api_key<H, Args, R>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<Args, Output = R> + Clone + Send + Sync + 'static,
+        Args: FromRequest + 'static,
+        R: Responder + 'static,
+    {
+        self.add_method_route_with_api_key(Method::GET, path, handler)
     }
-}
 
-/// Check if the request contains the expected password in the query string.
-fn check_password(req: &HttpRequest, expected_password: &str) -> bool {
-    let query_string = req.query_string();
+    /**
+     * Add a new route that requires an API key and responds to the given HTTP method.
+     *
+     * This behaves like `add_route_with_api_key`, but lets you choose the HTTP method.
+     *
+     * # Arguments
+     * - `method`: The HTTP method the route responds to.
+     * - `path`: The URL path for the route.
+     * - `handler`: The handler function for the route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, Method, HttpResponse};
+     *
+     * async fn ingest() -> HttpResponse {
+     *    HttpResponse::Accepted().finish()
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_method_route_with_api_key(Method::POST, "/ingest", ingest);
+     * ```
+     */
+    pub fn add_method_route_with_api_key<H, Args, R>(self, method: Method, path: &str, handler: H) -> Self
+    where
+        H: Handler<Args, Output = R> + Clone + Send + Sync + 'static,
+        Args: FromRequest + 'static,
+        R: Responder + 'static,
+    {
+        // The `ApiKey` extractor rejects missing, invalid and out-of-scope keys before this runs
+        let wrapped_handler = move |req: HttpRequest, _key: ApiKey, args: Args| {
+            let handler = handler.clone();
+            async move { handler.call(args).await.respond_to(&req).map_into_boxed_body() }
+        };
 
-    for pair in query_string.split('&') {
-        let mut key_value = pair.splitn(2, '=');
-        if let (Some(key), Some(value)) = (key_value.next(), key_value.next()) {
-            if key == "password" && value == expected_password {
-                return true;
-            }
-        }
+        self.push_route(method, path, Protection::ApiKey, wrapped_handler)
     }
 
-    false
-}
\ No newline at end of file
+    /**
+     * Add a WebSocket route.
+     *
+     * The route answers `GET` requests that ask to upgrade to a WebSocket. For each
+     * connection, the handler is started in its own task with the `Session` used to send
+     * messages and the `MessageStream` of incoming messages.
+     *
+     * # Arguments
+     * - `path`: The URL path for the route.
+     * - `handler`: The session handler, run once per connection.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Routes;
+     * use rusty_api::core::websocket::{MessageStream, Session};
+     *
+     * async fn greet(mut session: Session, _stream: MessageStream) {
+     *    let _ = session.text("Hello!").await;
+     *    let _ = session.close(None).await;
+     * }
+     *
+     * let routes = Routes::new().add_websocket("/ws", greet);
+     * ```
+     */
+    pub fn add_websocket<H, F>(self, path: &str, handler: H) -> Self
+    where
+        H: Fn(Session, MessageStream) -> F + Clone + Send + Sync + 'static,
+        F: Future<Output = ()> + 'static,
+    {
+        let wrapped_handler = move |req: HttpRequest, body: web::Payload| {
+            let handler = handler.clone();
+            async move {
+                let (res, session, stream) = actix_ws::handle(&req, body)?;
+                actix_web::rt::spawn(handler(session, stream));
+                Ok::<_, actix_web::Error>(res)
+            }
+        };
+
+        self.push_route(Method::GET, path, Protection::Public, wrapped_handler)
+    }
+
+    /**
+     * Add a WebSocket route that requires a valid JWT.
+     *
+     * The token may be sent in the `Authorization` header, as the `bearer` subprotocol,
+     * or in a `token` query parameter; see the `websocket` module for details. Requests
+     * without a valid token are rejected with `401 Unauthorized` before the upgrade. The
+     * handler receives the user ID from the token, and the connection is closed when the
+     * token expires.
+     *
+     * # Arguments
+     * - `path`: The URL path for the route.
+     * - `handler`: The session handler, run once per connection with the user ID.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Routes;
+     * use rusty_api::core::websocket::{MessageStream, Session};
+     *
+     * async fn inbox(mut session: Session, _stream: MessageStream, user_id: i32) {
+     *    let _ = session.text(format!("No new messages for user {}", user_id)).await;
+     * }
+     *
+     * let routes = Routes::new().add_websocket_with_auth("/ws/inbox", inbox);
+     * ```
+     */
+    pub fn add_websocket_with_auth<H, F>(self, path: &str, handler: H) -> Self
+    where
+        H: Fn(Session, MessageStream, i32) -> F + Clone + Send + Sync + 'static,
+        F: Future<Output = ()> + 'static,
+    {
+        let wrapped_handler = move |req: HttpRequest, body: web::Payload| {
+            let handler = handler.clone();
+            async move {
+                let (token, source) = websocket_token(&req).ok_or_else(missing_token)?;
+                let claims = authenticate_token(&req, &token).await?;
+
+                let (mut res, session, stream) = actix_ws::handle(&req, body)?;
+                // Browsers drop the connection unless the server confirms the subprotocol they offered
+                if let TokenSource::Subprotocol = source {
+                    res.headers_mut().insert(
+                        header::SEC_WEBSOCKET_PROTOCOL,
+                        header::HeaderValue::from_static(BEARER_PROTOCOL),
+                    );
+                }
+
+                let session_handler = handler(session.clone(), stream, claims.sub);
+                actix_web::rt::spawn(run_until_expiry(session, claims.exp, session_handler));
+                Ok::<_, actix_web::Error>(res)
+            }
+        };
+
+        self.push_route(Method::GET, path, Protection::Bearer, wrapped_handler)
+    }
+
+    /**
+     * Add a Server-Sent Events route.
+     *
+     * Each `GET` request opens an event stream that receives every event published to
+     * all subscribers with `Broadcaster::publish`. See the `sse` module for details.
+     *
+     * # Arguments
+     * - `path`: The URL path for the route.
+     * - `broadcaster`: The broadcaster whose events are streamed.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Routes;
+     * use rusty_api::core::sse::{Broadcaster, Event};
+     *
+     * let status = Broadcaster::new();
+     * let routes = Routes::new().add_sse("/status/events", &status);
+     *
+     * status.publish(Event::new("All systems operational"));
+     * ```
+     */
+    pub fn add_sse(self, path: &str, broadcaster: &Broadcaster) -> Self {
+        let broadcaster = broadcaster.clone();
+        let handler = move |req: HttpRequest| {
+            let broadcaster = broadcaster.clone();
+            async move { event_stream(&req, &broadcaster, None, None) }
+        };
+
+        self.push_route(Method::GET, path, Protection::Public, handler)
+    }
+
+    /**
+     * Add a Server-Sent Events route that requires a valid JWT.
+     *
+     * The token may be sent in the `Authorization` header or in a `token` query parameter.
+     * Each stream receives the events published to all subscribers, and the events
+     * published to its user with `Broadcaster::publish_to`. The stream ends when the
+     * token expires.
+     *
+     * # Arguments
+     * - `path`: The URL path for the route.
+     * - `broadcaster`: The broadcaster whose events are streamed.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Routes;
+     * use rusty_api::core::sse::{Broadcaster, Event};
+     *
+     * let notifications = Broadcaster::new();
+     * let routes = Routes::new().add_sse_with_auth("/notifications", &notifications);
+     *
+     * notifications.publish_to(42, Event::new("You have a new follower"));
+     * ```
+     */
+    pub fn add_sse_with_auth(self, path: &str, broadcaster: &Broadcaster) -> Self {
+        let broadcaster = broadcaster.clone();
+        let handler = move |req: HttpRequest| {
+            let broadcaster = broadcaster.clone();
+            async move {
+                let token = bearer_token(&req)
+                    .map(str::to_owned)
+                    .or_else(|| query_token(&req))
+                    .ok_or_else(missing_token)?;
+                let claims = authenticate_token(&req, &token).await?;
+
+                let expires_in = time_until_expiry(claims.exp);
+                Ok::<_, actix_web::Error>(event_stream(&req, &broadcaster, Some(claims.sub), Some(expires_in)))
+            }
+        };
+
+        self.push_route(Method::GET, path, Protection::Bearer, handler)
+    }
+
+    /// Internal function to handle adding routes with or without passwords.
+    fn add_route_internal<H, Args, R>(
+        self,
+        method: Method,
+        path: &str,
+        handler: H,
+        password: Option<&'static str>,
+    ) -> Self
+    where
+        H: Handler<Args, Output = R> + Clone + Send + Sync + 'static,
+        Args: FromRequest + 'static,
+        R: Responder + 'static,
+    {
+        // Only the hash of the password is kept, so it can be compared in constant time
+        let password_hash = password.map(hash_secret);
+        let wrapped_handler = move |req: HttpRequest, args: Args| {
+            let handler = handler.clone(); // Clone the handler inside the closure
+            let password_hash = password_hash.clone();
+            async move {
+                if let Some(expected_hash) = password_hash
+                    && !check_password(&req, &expected_hash)
+                {
+                    return invalid_password().into();
+                }
+                // Call the original handler and convert its output to an HttpResponse
+                handler.call(args).await.respond_to(&req).map_into_boxed_body()
+            }
+        };
+
+        let protection = if password.is_some() { Protection::Password } else { Protection::Public };
+        self.push_route(method, path, protection, wrapped_handler)
+    }
+
+    /// Internal function to store a wrapped handler under the given method and path.
+    fn push_route<H, Args, R>(mut self, method: Method, path: &str, protection: Protection, handler: H) -> Self
+    where
+        H: Handler<Args, Output = R> + Clone + Send + Sync + 'static,
+        Args: FromRequest + 'static,
+        R: Responder + 'static,
+    {
+        let route_method = method.clone();
+        let route = move || web::method(route_method.clone()).to(handler.clone());
+        self.routes.push(RouteEntry {
+            info: RouteInfo {
+                method,
+                path: path.to_owned(),
+                protection: protection.strongest(self.protection),
+                summary: None,
+                request_schema: None,
+                response_schema: None,
+            },
+            route: Box::new(route),
+            rate_limiter: None,
+            middleware: Vec::new(),
+            scope_limiters: Vec::new(),
+            scope_middleware: Vec::new(),
+            scope_guards: Vec::new(),
+        });
+        self
+    }
+
+    /**
+     * Set a rate limit for the most recently added route.
+     *
+     * The limit applies on top of the global limit set with `Api::rate_limit`: a request
+     * must be allowed by both, and counts against both. Each route keeps its own count per
+     * client, so a strict limit on one route does not affect other routes.
+     *
+     * # Arguments
+     * - `limit`: The rate limit to apply to the route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, RateLimit, HttpRequest, HttpResponse};
+     *
+     * async fn expensive_report(_req: HttpRequest) -> HttpResponse {
+     *    HttpResponse::Ok().body("Report generated!")
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_route("/report", expensive_report)
+     *    .rate_limit(RateLimit::per_minute(2, 1));
+     * ```
+     */
+    pub fn rate_limit(self, limit: RateLimit) -> Self {
+        self.set_rate_limiter(RateLimiter::new(limit))
+    }
+
+    /**
+     * Set a rate limit for the most recently added route, keyed by caller identity.
+     *
+     * Callers with a valid Bearer token are counted per user ID and callers with a valid
+     * API key are counted per key, using the `authenticated` limit. All other callers are
+     * counted per IP address using the `anonymous` limit. Like `rate_limit`, this stacks
+     * on top of the global limit.
+     *
+     * # Arguments
+     * - `anonymous`: The per-IP limit for callers without valid credentials.
+     * - `authenticated`: The limit for each user or API key.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, RateLimit, HttpRequest, HttpResponse};
+     *
+     * async fn search(_req: HttpRequest) -> HttpResponse {
+     *    HttpResponse::Ok().body("Search results")
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_route("/search", search)
+     *    .rate_limit_by_identity(RateLimit::per_minute(10, 5), RateLimit::per_minute(120, 20));
+     * ```
+     */
+    pub fn rate_limit_by_identity(self, anonymous: RateLimit, authenticated: RateLimit) -> Self {
+        self.set_rate_limiter(RateLimiter::by_identity(anonymous, authenticated))
+    }
+
+    /**
+     * Add middleware to the most recently added route.
+     *
+     * Route middleware runs after the route's rate limit and before its protection check,
+     * in the order it was added. See the `middleware` module for the full ordering.
+     *
+     * # Arguments
+     * - `middleware`: The middleware to run on the route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Middleware, Routes, HttpResponse};
+     *
+     * async fn delete_everything() -> HttpResponse {
+     *    HttpResponse::Ok().body("Deleted")
+     * }
+     *
+     * let audit = Middleware::new(|req, next| async move {
+     *    println!("AUDIT: {} {}", req.method(), req.path());
+     *    next.call(req).await
+     * });
+     *
+     * let routes = Routes::new()
+     *    .add_route("/delete_everything", delete_everything)
+     *    .wrap(audit);
+     * ```
+     */
+    pub fn wrap(mut self, middleware: Middleware) -> Self {
+        if let Some(entry) = self.routes.last_mut() {
+            entry.middleware.push(middleware);
+        }
+        self
+    }
+
+    /**
+     * Add middleware to every route in this `Routes` value.
+     *
+     * Unlike `wrap`, this applies to routes added both before and after the call. It runs
+     * before any middleware added to individual routes with `wrap`.
+     *
+     * # Arguments
+     * - `middleware`: The middleware to run on every route.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Middleware, Routes, HttpResponse};
+     *
+     * async fn list_invoices() -> HttpResponse {
+     *    HttpResponse::Ok().body("Invoices")
+     * }
+     *
+     * let no_cache = Middleware::new(|req, next| async move {
+     *    let mut res = next.call(req).await?;
+     *    res.headers_mut().insert(
+     *        actix_web::http::header::CACHE_CONTROL,
+     *        actix_web::http::header::HeaderValue::from_static("no-store"),
+     *    );
+     *    Ok(res)
+     * });
+     *
+     * let routes = Routes::new()
+     *    .wrap_all(no_cache)
+     *    .add_route("/invoices", list_invoices);
+     * ```
+     */
+    pub fn wrap_all(mut self, middleware: Middleware) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /**
+     * Require a password for every route in this `Routes` value.
+     *
+     * Like `add_route_with_password`, the password is sent in the `X-API-Key` header.
+     * This applies to routes added both before and after the call, and is checked in
+     * addition to any protection of the individual routes. It is deprecated for the same
+     * reasons as `add_route_with_password`; use `require_api_key` instead.
+     *
+     * # Arguments
+     * - `password`: The password required to call the routes.
+     *
+     * # Example
+     * ```rust
+     * # #![allow(deprecated)]
+     * use rusty_api::{Routes, HttpResponse};
+     *
+     * async fn metrics() -> HttpResponse {
+     *    HttpResponse::Ok().body("requests_total 42")
+     * }
+     *
+     * let routes = Routes::new().scope("/internal", |internal| internal
+     *    .require_password("Password123")
+     *    .add_route("/metrics", metrics));
+     * ```
+     */
+    #[deprecated(since = "0.2.0", note = "use `require_api_key` with named, route-scoped keys instead")]
+    pub fn require_password(self, password: &'static str) -> Self {
+        let password_hash = hash_secret(password);
+        self.add_guard(Protection::Password, Middleware::new(move |req, next| {
+            let password_hash = password_hash.clone();
+            async move {
+                if !check_password(req.request(), &password_hash) {
+                    return Ok(req.error_response(invalid_password()));
+                }
+                next.call(req).await
+            }
+        }))
+    }
+
+    /**
+     * Require a valid API key for every route in this `Routes` value.
+     *
+     * The key must be allowed to call the full pattern of each route, including the prefix
+     * of any enclosing scopes (e.g. `/v1/reports/{id}`).
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, HttpResponse};
+     *
+     * async fn list_reports() -> HttpResponse {
+     *    HttpResponse::Ok().body("Reports")
+     * }
+     *
+     * let routes = Routes::new().scope("/reports", |reports| reports
+     *    .require_api_key()
+     *    .add_route("", list_reports));
+     * ```
+     */
+    pub fn require_api_key(self) -> Self {
+        self.add_guard(Protection::ApiKey, Middleware::new(|req, next| async move {
+            if let Err(e) = ApiKey::extract(req.request()).await {
+                return Ok(req.error_response(e));
+            }
+            next.call(req).await
+        }))
+    }
+
+    /**
+     * Require a valid JWT for every route in this `Routes` value.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{AuthUser, Routes, HttpResponse};
+     *
+     * async fn profile(user: AuthUser) -> HttpResponse {
+     *    HttpResponse::Ok().body(format!("User {}", user.id))
+     * }
+     *
+     * let routes = Routes::new().scope("/me", |me| me
+     *    .require_auth()
+     *    .add_route("/profile", profile));
+     * ```
+     */
+    pub fn require_auth(self) -> Self {
+        self.add_guard(Protection::Bearer, Middleware::new(|req, next| async move {
+            if let Err(e) = AuthUser::extract(req.request()).await {
+                return Ok(req.error_response(e));
+            }
+            next.call(req).await
+        }))
+    }
+
+    /**
+     * Require a valid JWT with at least the given privilege level for every route in this `Routes` value.
+     *
+     * # Arguments
+     * - `level`: The minimum privilege level required to call the routes.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Method, Routes, HttpResponse, PRIVILEGE_ADMIN};
+     *
+     * async fn list_users() -> HttpResponse {
+     *    HttpResponse::Ok().body("Users")
+     * }
+     *
+     * async fn delete_user() -> HttpResponse {
+     *    HttpResponse::Ok().body("User deleted")
+     * }
+     *
+     * let routes = Routes::new().scope("/v1/admin", |admin| admin
+     *    .require_privilege(PRIVILEGE_ADMIN)
+     *    .add_route("/users", list_users)
+     *    .add_method_route(Method::DELETE, "/users/{id}", delete_user));
+     *
+     * assert!(routes.iter().all(|route| route.privilege() == Some(PRIVILEGE_ADMIN)));
+     * ```
+     */
+    pub fn require_privilege(self, level: i32) -> Self {
+        self.add_guard(Protection::Privilege(level), Middleware::new(move |req, next| async move {
+            match AuthUser::extract(req.request()).await {
+                Ok(user) if user.privilege >= level => next.call(req).await,
+                Ok(_) => Ok(req.error_response(insufficient_privilege())),
+                Err(e) => Ok(req.error_response(e)),
+            }
+        }))
+    }
+
+    /**
+     * Set a rate limit shared by every route in this `Routes` value.
+     *
+     * All routes in the group count against the same limit, on top of the global limit
+     * and any limits of the individual routes.
+     *
+     * # Arguments
+     * - `limit`: The rate limit to apply to the group.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, RateLimit, HttpResponse};
+     *
+     * async fn search() -> HttpResponse {
+     *    HttpResponse::Ok().body("Results")
+     * }
+     *
+     * async fn suggest() -> HttpResponse {
+     *    HttpResponse::Ok().body("Suggestions")
+     * }
+     *
+     * // At most 30 requests per minute across both routes
+     * let routes = Routes::new().scope("/search", |group| group
+     *    .rate_limit_all(RateLimit::per_minute(30, 10))
+     *    .add_route("", search)
+     *    .add_route("/suggest", suggest));
+     * ```
+     *
+     * Each group keeps its own count, even when merged with other groups under the same
+     * prefix and sharing one rate-limit store:
+     * ```rust
+     * use std::sync::Arc;
+     * use actix_web::{test, App};
+     * use rusty_api::core::rate_limit::{MemoryRateLimitStore, RateLimitStore};
+     * use rusty_api::{web, Routes, RateLimit, HttpResponse};
+     *
+     * async fn ok() -> HttpResponse {
+     *    HttpResponse::Ok().finish()
+     * }
+     *
+     * # actix_web::rt::System::new().block_on(async {
+     * let items = Routes::new().rate_limit_all(RateLimit::per_minute(1, 1)).add_route("/items", ok);
+     * let orders = Routes::new().rate_limit_all(RateLimit::per_minute(1, 1)).add_route("/orders", ok);
+     * let routes = Routes::new().merge(items).merge(orders);
+     *
+     * let store: Arc<dyn RateLimitStore> = Arc::new(MemoryRateLimitStore::new());
+     * let app = test::init_service(App::new()
+     *    .app_data(web::Data::from(store))
+     *    .configure(|cfg| routes.configure(cfg))).await;
+     *
+     * let status = |path| test::TestRequest::get().uri(path).peer_addr("10.0.0.1:1234".parse().unwrap()).to_request();
+     * assert_eq!(test::call_service(&app, status("/items")).await.status(), 200);
+     * assert_eq!(test::call_service(&app, status("/items")).await.status(), 429);
+     * assert_eq!(test::call_service(&app, status("/orders")).await.status(), 200);
+     * # });
+     * ```
+     */
+    pub fn rate_limit_all(mut self, limit: RateLimit) -> Self {
+        // Each group gets its own scope, so groups merged under the same prefix never share counts
+        let group = NEXT_LIMITER_GROUP.fetch_add(1, Ordering::Relaxed);
+        self.rate_limiter = Some(RateLimiter::new(limit).scope(format!("group {}", group)));
+        self
+    }
+
+    /**
+     * Add a group of routes that share a path prefix.
+     *
+     * The closure receives an empty `Routes` value. Any protection, rate limit or middleware
+     * set on it with `require_password`, `require_api_key`, `require_auth`,
+     * `require_privilege`, `rate_limit_all` or `wrap_all` applies to every route in the
+     * group. Scopes can be nested, in which case the prefixes are joined and the settings
+     * of every enclosing scope apply, outermost first.
+     *
+     * # Arguments
+     * - `prefix`: The path prefix of the group (e.g. `/v1/admin`).
+     * - `build`: A closure that adds the routes of the group.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, HttpResponse, PRIVILEGE_ADMIN};
+     *
+     * async fn status() -> HttpResponse {
+     *    HttpResponse::Ok().body("OK")
+     * }
+     *
+     * async fn list_users() -> HttpResponse {
+     *    HttpResponse::Ok().body("Users")
+     * }
+     *
+     * let routes = Routes::new().scope("/v1", |v1| v1
+     *    .add_route("/status", status)
+     *    .scope("/admin", |admin| admin
+     *        .require_privilege(PRIVILEGE_ADMIN)
+     *        .add_route("/users", list_users)));
+     *
+     * let paths: Vec<&str> = routes.iter().map(|route| route.path.as_str()).collect();
+     * assert_eq!(paths, ["/v1/status", "/v1/admin/users"]);
+     * ```
+     */
+    pub fn scope<F>(self, prefix: &str, build: F) -> Self
+    where
+        F: FnOnce(Routes) -> Routes,
+    {
+        self.absorb(prefix, build(Routes::new()))
+    }
+
+    /**
+     * Add every route from another `Routes` value.
+     *
+     * This allows routes to be built in different modules and combined into one value
+     * for `Api::configure_routes`. Any protection, rate limit or middleware set on `other`
+     * for all of its routes still applies to them, and only to them.
+     *
+     * # Arguments
+     * - `other`: The routes to add.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, HttpResponse};
+     *
+     * async fn list_items() -> HttpResponse {
+     *    HttpResponse::Ok().body("Items")
+     * }
+     *
+     * async fn list_orders() -> HttpResponse {
+     *    HttpResponse::Ok().body("Orders")
+     * }
+     *
+     * let items = Routes::new().add_route("/items", list_items);
+     * let orders = Routes::new().require_auth().add_route("/orders", list_orders);
+     *
+     * let routes = items.merge(orders);
+     * assert_eq!(routes.iter().count(), 2);
+     * ```
+     */
+    pub fn merge(self, other: Routes) -> Self {
+        self.absorb("", other)
+    }
+
+    /**
+     * Set a short description of the most recently added route.
+     *
+     * The summary is shown in the generated OpenAPI document.
+     *
+     * # Arguments
+     * - `summary`: A one-line description of what the route does.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, HttpResponse};
+     *
+     * async fn health() -> HttpResponse {
+     *    HttpResponse::Ok().body("OK")
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_route("/health", health)
+     *    .summary("Check that the server is running");
+     * ```
+     */
+    pub fn summary(mut self, summary: &str) -> Self {
+        if let Some(entry) = self.routes.last_mut() {
+            entry.info.summary = Some(summary.to_owned());
+        }
+        self
+    }
+
+    /**
+     * Set the JSON Schema of the request body for the most recently added route.
+     *
+     * The schema is only used to document the route in the generated OpenAPI document;
+     * requests are not validated against it.
+     *
+     * # Arguments
+     * - `schema`: A JSON Schema describing the JSON request body.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, Method, HttpResponse};
+     * use serde_json::json;
+     *
+     * async fn create_item() -> HttpResponse {
+     *    HttpResponse::Created().finish()
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_method_route(Method::POST, "/items", create_item)
+     *    .request_schema(json!({
+     *        "type": "object",
+     *        "properties": { "name": { "type": "string" } },
+     *        "required": ["name"]
+     *    }));
+     * ```
+     */
+    pub fn request_schema(mut self, schema: serde_json::Value) -> Self {
+        if let Some(entry) = self.routes.last_mut() {
+            entry.info.request_schema = Some(schema);
+        }
+        self
+    }
+
+    /**
+     * Set the JSON Schema of the successful response body for the most recently added route.
+     *
+     * Like `request_schema`, this is only used in the generated OpenAPI document.
+     *
+     * # Arguments
+     * - `schema`: A JSON Schema describing the JSON response body.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, HttpResponse};
+     * use serde_json::json;
+     *
+     * async fn list_items() -> HttpResponse {
+     *    HttpResponse::Ok().json(vec!["apple", "pear"])
+     * }
+     *
+     * let routes = Routes::new()
+     *    .add_route("/items", list_items)
+     *    .response_schema(json!({ "type": "array", "items": { "type": "string" } }));
+     * ```
+     */
+    pub fn response_schema(mut self, schema: serde_json::Value) -> Self {
+        if let Some(entry) = self.routes.last_mut() {
+            entry.info.response_schema = Some(schema);
+        }
+        self
+    }
+
+    /**
+     * Iterate over information about every registered route, in registration order.
+     *
+     * This is useful in tests, to check how each route is protected.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Method, Protection, Routes, PRIVILEGE_ADMIN};
+     * use actix_web::{HttpRequest, HttpResponse};
+     *
+     * async fn delete_user(_req: HttpRequest) -> HttpResponse {
+     *     HttpResponse::Ok().body("User deleted")
+     * }
+     *
+     * let routes = Routes::new()
+     *     .add_method_route_with_privilege(Method::DELETE, "/admin/users/{id}", delete_user, PRIVILEGE_ADMIN);
+     *
+     * // No admin route may be reachable without sufficient privilege
+     * assert!(routes
+     *     .iter()
+     *     .filter(|route| route.path.starts_with("/admin"))
+     *     .all(|route| route.privilege() >= Some(PRIVILEGE_ADMIN)));
+     * assert_eq!(routes.iter().next().unwrap().protection, Protection::Privilege(PRIVILEGE_ADMIN));
+     * ```
+     */
+    pub fn iter(&self) -> impl Iterator<Item = &RouteInfo> {
+        self.routes.iter().map(|entry| &entry.info)
+    }
+
+    /**
+     * Describe the registered routes as a table of methods, paths and protection.
+     *
+     * # Returns
+     * A text table with one line per route, as printed by `Api::start`.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::Routes;
+     * use actix_web::HttpResponse;
+     *
+     * async fn health() -> HttpResponse {
+     *     HttpResponse::Ok().body("OK")
+     * }
+     *
+     * let table = Routes::new().add_route("/health", health).describe();
+     * assert!(table.contains("GET     /health  public"));
+     * ```
+     */
+    pub fn describe(&self) -> String {
+        route_table(self.iter())
+    }
+
+    /// Internal function to add a protection check to every route in this `Routes` value.
+    fn add_guard(mut self, protection: Protection, guard: Middleware) -> Self {
+        self.protection = self.protection.strongest(protection);
+        for entry in &mut self.routes {
+            entry.info.protection = entry.info.protection.strongest(protection);
+        }
+        self.guards.push(guard);
+        self
+    }
+
+    /// Internal function to move the routes of `child` into this value under a path prefix.
+    fn absorb(mut self, prefix: &str, child: Routes) -> Self {
+        let prefix = prefix.trim_end_matches('/');
+        for mut entry in child.routes {
+            entry.info.path = format!("{}{}", prefix, entry.info.path);
+            entry.info.protection = entry.info.protection.strongest(self.protection);
+
+            // Route limiter scopes are keyed by the full path, so routes in different groups never share counts
+            entry.rate_limiter = entry
+                .rate_limiter
+                .map(|limiter| limiter.scope(format!("{} {}", entry.info.method, entry.info.path)));
+
+            // The child's own settings become the innermost enclosing scope of its routes
+            if let Some(limiter) = &child.rate_limiter {
+                entry.scope_limiters.insert(0, limiter.clone());
+            }
+            entry.scope_middleware.splice(0..0, child.middleware.iter().cloned());
+            entry.scope_guards.splice(0..0, child.guards.iter().cloned());
+
+            self.routes.push(entry);
+        }
+        self
+    }
+
+    /// Internal function to attach a limiter to the most recently added route, scoped to its method and path.
+    fn set_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        if let Some(entry) = self.routes.last_mut() {
+            let scope = format!("{} {}", entry.info.method, entry.info.path);
+            entry.rate_limiter = Some(limiter.scope(scope));
+        }
+        self
+    }
+
+    /**
+     * Apply the routes to a `ServiceConfig`.
+     *
+     * This method iterates over all defined routes and applies them to the
+     * provided Axtix Web `ServiceConfig`. It is used internally by the `Api` struct.
+     * Routes sharing a path are grouped into a single resource, which answers any
+     * unregistered method with `405 Method Not Allowed` and an `Allow` header.
+     *
+     * # Arguments
+     * - `cfg`: A mutable reference to the `ServiceConfig` to which the routes will be applied.
+     *
+     * # Example
+     * ```rust
+     * use rusty_api::{Routes, Api};
+     *
+     * let routes = Routes::new();
+     *
+     * let api = Api::new()
+     *    .configure_routes(routes); // The configure_routes method calls the configure method internally.
+     * ```
+     */
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        // Group routes by path, keeping the order in which each path was first registered
+        let mut paths: Vec<&str> = Vec::new();
+        for entry in &self.routes {
+            if !paths.contains(&entry.info.path.as_str()) {
+                paths.push(&entry.info.path);
+            }
+        }
+
+        for path in paths {
+            let entries: Vec<&RouteEntry> = self.routes.iter().filter(|e| e.info.path == path).collect();
+
+            let mut allowed: Vec<&str> = Vec::new();
+            let mut resource = web::resource(path);
+            for entry in entries {
+                if !allowed.contains(&entry.info.method.as_str()) {
+                    allowed.push(entry.info.method.as_str());
+                }
+                resource = resource.route(self.build_route(entry));
+            }
+
+            let allow = allowed.join(", ");
+            cfg.service(resource.default_service(web::to(move || {
+                let allow = allow.clone();
+                async move { method_not_allowed(&allow) }
+            })));
+        }
+    }
+    /// Internal function to build a route with its rate limits, middleware and protection checks.
+    fn build_route(&self, entry: &RouteEntry) -> Route {
+        // Middleware runs outermost scope first, then the route's own, then the protection checks
+        let chain: Vec<Middleware> = self
+            .middleware
+            .iter()
+            .chain(&entry.scope_middleware)
+            .chain(&entry.middleware)
+            .chain(&self.guards)
+            .chain(&entry.scope_guards)
+            .cloned()
+            .collect();
+
+        let mut route = (entry.route)();
+        if !chain.is_empty() {
+            route = route.wrap(MiddlewareChain::new(&chain));
+        }
+
+        // The last limiter wrapped runs first, so wrap from the innermost limit outwards
+        if let Some(limiter) = &entry.rate_limiter {
+            route = route.wrap(limiter.clone());
+        }
+        for limiter in entry.scope_limiters.iter().rev() {
+            route = route.wrap(limiter.clone());
+        }
+        if let Some(limiter) = &self.rate_limiter {
+            route = route.wrap(limiter.clone());
+        }
+        route
+    }
+}
+
+impl Default for Routes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * Format routes as a table with method, path and protection columns.
+ *
+ * # Arguments
+ * - `routes`: The routes to describe.
+ *
+ * # Returns
+ * The table, with a header line and one line per route.
+ */
+pub(crate) fn route_table<'a>(routes: impl IntoIterator<Item = &'a RouteInfo>) -> String {
+    let rows: Vec<(&str, &str, String)> = routes
+        .into_iter()
+        .map(|route| (route.method.as_str(), route.path.as_str(), route.protection.to_string()))
+        .collect();
+
+    let method_width = rows.iter().map(|row| row.0.len()).chain(["METHOD".len()]).max().unwrap_or(0);
+    let path_width = rows.iter().map(|row| row.1.len()).chain(["PATH".len()]).max().unwrap_or(0);
+
+    let mut table = format!("{:<method_width$}  {:<path_width$}  PROTECTION", "METHOD", "PATH");
+    for (method, path, protection) in rows {
+        table.push_str(&format!("\n{:<method_width$}  {:<path_width$}  {}", method, path, protection));
+    }
+    table
+}
+
+/// Build a `405 Method Not Allowed` response listing the methods the resource supports.
+pub(crate) fn method_not_allowed(allow: &str) -> HttpResponse {
+    let mut res = HttpResponse::from(ApiError::new(StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed", "Method not allowed"));
+    if let Ok(allow) = HeaderValue::from_str(allow) {
+        res.headers_mut().insert(header::ALLOW, allow);
+    }
+    res
+}
+
+/// The error for a request to a password-protected route without the right password.
+fn invalid_password() -> ApiError {
+    ApiError::unauthorized("Invalid password").with_code("invalid_password")
+}
+
+/// Check if the `X-API-Key` header of the request matches the expected password hash.
+fn check_password(req: &HttpRequest, expected_hash: &str) -> bool {
+    req.headers()
+        .get(API_KEY_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|password| constant_time_eq(hash_secret(password).as_bytes(), expected_hash.as_bytes()))
+}